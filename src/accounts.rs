@@ -0,0 +1,168 @@
+use std::{collections::HashMap, fs::read_to_string, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::error_structs::ConfigReadError;
+
+pub const DEFAULT_ITERATIONS: u32 = 4096;
+
+/// Where SASL looks up accounts. `None` until a backend gets configured, in which case nobody can
+/// log in.
+pub static CREDENTIAL_BACKEND: Lazy<Mutex<Option<Arc<dyn CredentialBackend>>>> =
+    Lazy::new(|| Mutex::new(None));
+
+/// A source of account credentials. Implement this to plug in other account stores.
+#[async_trait]
+pub trait CredentialBackend: Send + Sync {
+    async fn get_credentials(&self, account: &str) -> Option<Credentials>;
+    /// find the account a TLS client certificate fingerprint belongs to, for SASL EXTERNAL
+    async fn get_credentials_by_certfp(&self, certfp: &str) -> Option<Credentials>;
+}
+
+/// SCRAM-SHA-256 credentials of an account. The password itself is never stored, PLAIN logins
+/// are checked by deriving the stored key from the supplied password.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
+pub struct Credentials {
+    pub name: String,
+    /// base64 encoded
+    pub salt: String,
+    pub iterations: u32,
+    /// base64 encoded
+    pub stored_key: String,
+    /// base64 encoded
+    pub server_key: String,
+    /// SHA-256 fingerprints of client certificates which can log into this account
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub certfps: Vec<String>,
+}
+
+/// Accounts stored in a local TOML file, see `examples/example_accounts.toml`.
+pub struct LocalFileBackend {
+    accounts: HashMap<String, Credentials>,
+}
+
+#[derive(Deserialize)]
+struct AccountsFile {
+    #[serde(default)]
+    accounts: Vec<Credentials>,
+}
+
+pub async fn set_credential_backend(backend: Arc<dyn CredentialBackend>) {
+    *CREDENTIAL_BACKEND.lock().await = Some(backend);
+}
+
+pub async fn get_credentials(account: &str) -> Option<Credentials> {
+    let backend = CREDENTIAL_BACKEND.lock().await.clone()?;
+
+    backend.get_credentials(account).await
+}
+
+pub async fn get_credentials_by_certfp(certfp: &str) -> Option<Credentials> {
+    let backend = CREDENTIAL_BACKEND.lock().await.clone()?;
+
+    backend.get_credentials_by_certfp(certfp).await
+}
+
+impl LocalFileBackend {
+    pub fn load(path: &str) -> Result<Self, ConfigReadError> {
+        let file: AccountsFile = toml::from_str(&read_to_string(PathBuf::from(path))?)?;
+
+        Ok(Self {
+            accounts: file
+                .accounts
+                .into_iter()
+                .map(|credentials| (credentials.name.to_lowercase(), credentials))
+                .collect(),
+        })
+    }
+}
+
+#[async_trait]
+impl CredentialBackend for LocalFileBackend {
+    async fn get_credentials(&self, account: &str) -> Option<Credentials> {
+        self.accounts.get(&account.to_lowercase()).cloned()
+    }
+
+    async fn get_credentials_by_certfp(&self, certfp: &str) -> Option<Credentials> {
+        self.accounts
+            .values()
+            .find(|credentials| {
+                credentials
+                    .certfps
+                    .iter()
+                    .any(|x| x.eq_ignore_ascii_case(certfp))
+            })
+            .cloned()
+    }
+}
+
+impl Credentials {
+    pub fn new(name: &str, password: &str) -> Self {
+        let mut salt = [0u8; 16];
+        rand::rng().fill_bytes(&mut salt);
+
+        let salted_password = salted_password(password, &salt, DEFAULT_ITERATIONS);
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+        let server_key = hmac_sha256(&salted_password, b"Server Key");
+
+        Self {
+            name: name.to_owned(),
+            salt: BASE64.encode(salt),
+            iterations: DEFAULT_ITERATIONS,
+            stored_key: BASE64.encode(Sha256::digest(client_key)),
+            server_key: BASE64.encode(server_key),
+            certfps: Vec::new(),
+        }
+    }
+
+    pub fn verify_password(&self, password: &str) -> bool {
+        let (Ok(salt), Ok(stored_key)) =
+            (BASE64.decode(&self.salt), BASE64.decode(&self.stored_key))
+        else {
+            return false;
+        };
+
+        let salted_password = salted_password(password, &salt, self.iterations);
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+
+        constant_time_eq(&Sha256::digest(client_key), &stored_key)
+    }
+}
+
+pub fn salted_password(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut output = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut output);
+
+    output
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::accounts::Credentials;
+
+    #[test]
+    fn test_verify_password() {
+        let credentials = Credentials::new("alice", "hunter2");
+
+        assert!(credentials.verify_password("hunter2"));
+        assert!(!credentials.verify_password("hunter3"));
+    }
+}
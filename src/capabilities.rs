@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+
+use once_cell::sync::Lazy;
+use tokio::sync::Mutex;
+
+use crate::{
+    messages::{CapChangeMessage, Message},
+    routing::route,
+};
+
+/// Every capability the server currently offers, keyed on its name. Features register their
+/// capabilities here instead of the CAP handler hardcoding them.
+pub static CAPABILITIES: Lazy<Mutex<BTreeMap<String, Capability>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Capability {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl Capability {
+    /// Format the capability for a `CAP LS`/`CAP NEW` reply. Values are only sent to clients
+    /// which negotiated CAP version 302 or later.
+    pub fn to_ls_string(&self, cap_version: u16) -> String {
+        match (&self.value, cap_version >= 302) {
+            (Some(value), true) => format!("{}={}", self.name, value),
+            _ => self.name.clone(),
+        }
+    }
+}
+
+/// Register a capability, notifying connected clients with `CAP NEW` if the capability was not
+/// offered before.
+pub async fn register_capability(name: &str, value: Option<String>) {
+    let capability = Capability {
+        name: name.to_owned(),
+        value,
+    };
+
+    let previous = CAPABILITIES
+        .lock()
+        .await
+        .insert(name.to_owned(), capability.clone());
+
+    if previous.as_ref() != Some(&capability) {
+        notify_clients(CapChangeMessage {
+            added: true,
+            capabilities: vec![capability],
+        })
+        .await;
+    }
+}
+
+#[allow(dead_code)]
+/// Stop offering a capability, notifying connected clients with `CAP DEL`.
+pub async fn unregister_capability(name: &str) {
+    let removed = CAPABILITIES.lock().await.remove(name);
+
+    if let Some(capability) = removed {
+        notify_clients(CapChangeMessage {
+            added: false,
+            capabilities: vec![capability],
+        })
+        .await;
+    }
+}
+
+pub async fn get_capability(name: &str) -> Option<Capability> {
+    CAPABILITIES.lock().await.get(name).cloned()
+}
+
+pub async fn list_capabilities() -> Vec<Capability> {
+    CAPABILITIES.lock().await.values().cloned().collect()
+}
+
+async fn notify_clients(message: CapChangeMessage) {
+    route(Message::CapChangeMessage(message)).await;
+}
+
+/// Split a list of capability tokens into chunks that fit into a single CAP reply line.
+pub fn chunk_capabilities(tokens: Vec<String>, max_len: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for token in tokens {
+        if !current.is_empty() && current.len() + token.len() + 1 > max_len {
+            lines.push(current);
+            current = String::new();
+        }
+
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&token);
+    }
+
+    lines.push(current);
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capabilities::{Capability, chunk_capabilities};
+
+    #[test]
+    fn test_chunk_capabilities() {
+        let tokens = vec!["aaaa".to_owned(), "bbbb".to_owned(), "cccc".to_owned()];
+
+        assert_eq!(
+            chunk_capabilities(tokens.clone(), 100),
+            vec!["aaaa bbbb cccc"]
+        );
+        assert_eq!(chunk_capabilities(tokens, 9), vec!["aaaa bbbb", "cccc"]);
+    }
+
+    #[test]
+    fn test_capability_values_need_302() {
+        let capability = Capability {
+            name: "sasl".to_owned(),
+            value: Some("PLAIN".to_owned()),
+        };
+
+        assert_eq!(capability.to_ls_string(301), "sasl");
+        assert_eq!(capability.to_ls_string(302), "sasl=PLAIN");
+    }
+}
@@ -0,0 +1,77 @@
+//! Which names count as the same nickname or channel name. The mapping is picked once at startup
+//! from the config, and every lookup goes through `casefold`.
+
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+
+static CASEMAPPING: OnceCell<Casemapping> = OnceCell::new();
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Casemapping {
+    /// Only A-Z are folded to a-z
+    Ascii,
+    /// Like `Ascii`, with `[]\~` also being the uppercase versions of `{}|^`. This is what TS6
+    /// servers use, so it's the only safe choice when linking.
+    #[default]
+    Rfc1459,
+    /// Like `Rfc1459`, without `~` and `^`
+    StrictRfc1459,
+}
+
+impl Casemapping {
+    /// The name we advertise in the `CASEMAPPING` ISUPPORT token
+    pub fn name(self) -> &'static str {
+        match self {
+            Casemapping::Ascii => "ascii",
+            Casemapping::Rfc1459 => "rfc1459",
+            Casemapping::StrictRfc1459 => "strict-rfc1459",
+        }
+    }
+
+    pub fn casefold(self, name: &str) -> String {
+        name.chars()
+            .map(|x| match (self, x) {
+                (Casemapping::Rfc1459 | Casemapping::StrictRfc1459, '[') => '{',
+                (Casemapping::Rfc1459 | Casemapping::StrictRfc1459, ']') => '}',
+                (Casemapping::Rfc1459 | Casemapping::StrictRfc1459, '\\') => '|',
+                (Casemapping::Rfc1459, '~') => '^',
+                (_, x) => x.to_ascii_lowercase(),
+            })
+            .collect()
+    }
+}
+
+/// Set the casemapping of the server. Only the first call has any effect.
+pub fn set_casemapping(casemapping: Casemapping) {
+    let _ = CASEMAPPING.set(casemapping);
+}
+
+pub fn casemapping() -> Casemapping {
+    CASEMAPPING.get().copied().unwrap_or_default()
+}
+
+/// Casefold a nickname or channel name, so names that only differ in case map to the same key.
+pub fn casefold(name: &str) -> String {
+    casemapping().casefold(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::casemapping::Casemapping;
+
+    #[test]
+    fn test_casemappings() {
+        assert_eq!(Casemapping::Rfc1459.casefold("Nick[Away]"), "nick{away}");
+        assert_eq!(Casemapping::Rfc1459.casefold("a\\b~c"), "a|b^c");
+
+        assert_eq!(
+            Casemapping::StrictRfc1459.casefold("Nick[Away]"),
+            "nick{away}"
+        );
+        assert_eq!(Casemapping::StrictRfc1459.casefold("a\\b~c"), "a|b~c");
+
+        assert_eq!(Casemapping::Ascii.casefold("Nick[Away]"), "nick[away]");
+        assert_eq!(Casemapping::Ascii.casefold("ÄÖÜ"), "ÄÖÜ");
+    }
+}
@@ -0,0 +1,506 @@
+//! Channel modes, and parsing and applying `MODE` changes to them. Everything we advertise about
+//! them (CHANMODES, RPL_MYINFO) gets built from the table here.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{casemapping::casefold, masks::normalize_mask};
+
+/// How many entries each of the +b, +e and +I lists can hold
+pub const MAXLIST: usize = 100;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug, Ord, PartialOrd)]
+pub enum Chanmode {
+    NoExternalMessages = b'n',
+    TopicLock = b't',
+    Moderated = b'm',
+    InviteOnly = b'i',
+    Key = b'k',
+    Limit = b'l',
+    Secret = b's',
+    Private = b'p',
+    ChanOp = b'o',
+    HalfOp = b'h',
+    Voice = b'v',
+    Ban = b'b',
+    BanException = b'e',
+    InviteException = b'I',
+}
+
+/// Status a member can have in a channel. Ordered from highest to lowest.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug, Ord, PartialOrd)]
+pub enum MemberStatus {
+    Op,
+    HalfOp,
+    Voice,
+}
+
+/// How a mode takes its parameter, which decides its CHANMODES group
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ModeKind {
+    /// adds to or removes from a list of masks (group A)
+    List,
+    /// always has a parameter, when setting and unsetting (group B)
+    Parameter,
+    /// only has a parameter when being set (group C)
+    ParameterWhenSet,
+    /// never has a parameter (group D)
+    Flag,
+    /// gives a member a status, with their nickname as parameter (PREFIX)
+    Status,
+}
+
+impl Chanmode {
+    pub const ALL: [Chanmode; 14] = [
+        Chanmode::NoExternalMessages,
+        Chanmode::TopicLock,
+        Chanmode::Moderated,
+        Chanmode::InviteOnly,
+        Chanmode::Key,
+        Chanmode::Limit,
+        Chanmode::Secret,
+        Chanmode::Private,
+        Chanmode::ChanOp,
+        Chanmode::HalfOp,
+        Chanmode::Voice,
+        Chanmode::Ban,
+        Chanmode::BanException,
+        Chanmode::InviteException,
+    ];
+
+    pub fn from_char(mode: char) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| char::from(*x) == mode)
+    }
+
+    pub fn kind(self) -> ModeKind {
+        match self {
+            Chanmode::Key => ModeKind::Parameter,
+            Chanmode::Limit => ModeKind::ParameterWhenSet,
+            Chanmode::ChanOp | Chanmode::HalfOp | Chanmode::Voice => ModeKind::Status,
+            Chanmode::Ban | Chanmode::BanException | Chanmode::InviteException => ModeKind::List,
+            _ => ModeKind::Flag,
+        }
+    }
+
+    pub fn member_status(self) -> Option<MemberStatus> {
+        MemberStatus::ALL
+            .into_iter()
+            .find(|status| status.mode() == self)
+    }
+
+    fn takes_parameter(self, adding: bool) -> bool {
+        match self.kind() {
+            ModeKind::Parameter | ModeKind::Status | ModeKind::List => true,
+            ModeKind::ParameterWhenSet => adding,
+            ModeKind::Flag => false,
+        }
+    }
+}
+
+impl From<Chanmode> for char {
+    fn from(value: Chanmode) -> Self {
+        value as u8 as char
+    }
+}
+
+impl MemberStatus {
+    pub const ALL: [MemberStatus; 3] =
+        [MemberStatus::Op, MemberStatus::HalfOp, MemberStatus::Voice];
+
+    pub fn mode(self) -> Chanmode {
+        match self {
+            MemberStatus::Op => Chanmode::ChanOp,
+            MemberStatus::HalfOp => Chanmode::HalfOp,
+            MemberStatus::Voice => Chanmode::Voice,
+        }
+    }
+
+    /// The symbol in front of the nickname in NAMES and the like
+    pub fn prefix(self) -> char {
+        match self {
+            MemberStatus::Op => '@',
+            MemberStatus::HalfOp => '%',
+            MemberStatus::Voice => '+',
+        }
+    }
+
+    pub fn from_prefix(prefix: char) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.prefix() == prefix)
+    }
+}
+
+/// Split a STATUSMSG target like `@#channel` into the status and the channel name.
+pub fn split_status_prefix(target: &str) -> (Option<MemberStatus>, &str) {
+    let mut chars = target.chars();
+
+    match chars.next().and_then(MemberStatus::from_prefix) {
+        Some(status) if chars.as_str().starts_with('#') => (Some(status), chars.as_str()),
+        _ => (None, target),
+    }
+}
+
+/// The PREFIX and STATUSMSG ISUPPORT tokens
+pub fn prefix_isupport() -> String {
+    let prefixes = MemberStatus::ALL
+        .map(MemberStatus::prefix)
+        .iter()
+        .collect::<String>();
+    let modes = MemberStatus::ALL
+        .map(|status| char::from(status.mode()))
+        .iter()
+        .collect::<String>();
+
+    format!("PREFIX=({modes}){prefixes} STATUSMSG={prefixes}")
+}
+
+/// Letters of every channel mode, for RPL_MYINFO
+pub fn mode_letters() -> String {
+    let mut letters = Chanmode::ALL.map(char::from);
+    letters.sort_by_key(alphabetical);
+
+    letters.iter().collect()
+}
+
+/// Sort key putting `I` right after `i`, instead of before every lowercase letter
+fn alphabetical(letter: &char) -> (char, bool) {
+    (letter.to_ascii_lowercase(), letter.is_ascii_uppercase())
+}
+
+/// The MAXLIST ISUPPORT token
+pub fn maxlist_isupport() -> String {
+    let letters = Chanmode::ALL
+        .into_iter()
+        .filter(|mode| mode.kind() == ModeKind::List)
+        .map(char::from)
+        .collect::<String>();
+
+    format!("MAXLIST={letters}:{MAXLIST}")
+}
+
+/// The CHANMODES ISUPPORT token
+pub fn chanmodes_isupport() -> String {
+    let group = |kind: ModeKind| {
+        let mut letters = Chanmode::ALL
+            .into_iter()
+            .filter(|mode| mode.kind() == kind)
+            .map(char::from)
+            .collect::<Vec<char>>();
+        letters.sort_by_key(alphabetical);
+
+        letters.into_iter().collect::<String>()
+    };
+
+    format!(
+        "CHANMODES={},{},{},{}",
+        group(ModeKind::List),
+        group(ModeKind::Parameter),
+        group(ModeKind::ParameterWhenSet),
+        group(ModeKind::Flag),
+    )
+}
+
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct ChannelModes {
+    pub flags: BTreeSet<Chanmode>,
+    pub key: Option<String>,
+    pub limit: Option<usize>,
+    /// Entries of the +b, +e and +I lists, oldest first
+    pub lists: BTreeMap<Chanmode, Vec<ListEntry>>,
+}
+
+/// A mask on one of the channel's lists, with who put it there and when.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct ListEntry {
+    pub mask: String,
+    pub setter: String,
+    pub set_at: SystemTime,
+}
+
+impl ListEntry {
+    /// When the entry was set, as a unix timestamp
+    pub fn timestamp(&self) -> u64 {
+        self.set_at
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModeChange {
+    pub adding: bool,
+    pub mode: Chanmode,
+    pub parameter: Option<String>,
+}
+
+/// Split a mode string and its parameters into single changes. Returns the letters we don't know
+/// as well, so they can be reported.
+pub fn parse_mode_changes(modestring: &str, parameters: &[String]) -> (Vec<ModeChange>, Vec<char>) {
+    let mut parameters = parameters.iter();
+    let mut changes = Vec::new();
+    let mut unknown = Vec::new();
+    let mut adding = true;
+
+    for letter in modestring.chars() {
+        match letter {
+            '+' => adding = true,
+            '-' => adding = false,
+            letter => {
+                let Some(mode) = Chanmode::from_char(letter) else {
+                    unknown.push(letter);
+                    continue;
+                };
+
+                let parameter = if mode.takes_parameter(adding) {
+                    match parameters.next() {
+                        Some(parameter) => Some(parameter.clone()),
+                        // -k without the key is fine, there's only one key to remove
+                        None if !adding => None,
+                        // and a list mode without a mask asks for the list
+                        None if mode.kind() == ModeKind::List => None,
+                        None => continue,
+                    }
+                } else {
+                    None
+                };
+
+                changes.push(ModeChange {
+                    adding,
+                    mode,
+                    parameter,
+                });
+            }
+        }
+    }
+
+    (changes, unknown)
+}
+
+/// Render changes the way they're sent in a `MODE` line, e.g. `+kl-m`, `["key", "10"]`.
+pub fn render_changes(changes: &[ModeChange]) -> (String, Vec<String>) {
+    let mut modestring = String::new();
+    let mut parameters = Vec::new();
+    let mut adding = None;
+
+    for change in changes {
+        if adding != Some(change.adding) {
+            modestring.push(if change.adding { '+' } else { '-' });
+            adding = Some(change.adding);
+        }
+
+        modestring.push(change.mode.into());
+        parameters.extend(change.parameter.clone());
+    }
+
+    (modestring, parameters)
+}
+
+impl ChannelModes {
+    /// Apply a change, returning it if it actually changed something. Member statuses aren't
+    /// stored here, see `Channel::apply_modes`. `setter` is recorded for list entries.
+    pub fn apply_one(&mut self, mut change: ModeChange, setter: &str) -> Option<ModeChange> {
+        match (change.mode, change.adding) {
+            (mode, _) if mode.kind() == ModeKind::Status => return None,
+
+            (mode, adding) if mode.kind() == ModeKind::List => {
+                let mask = normalize_mask(change.parameter.as_ref()?)?;
+                let list = self.lists.entry(mode).or_default();
+                let existing = list
+                    .iter()
+                    .position(|entry| casefold(&entry.mask) == casefold(&mask));
+
+                match (existing, adding) {
+                    (None, true) if list.len() < MAXLIST => list.push(ListEntry {
+                        mask: mask.clone(),
+                        setter: setter.to_owned(),
+                        set_at: SystemTime::now(),
+                    }),
+                    (Some(index), false) => {
+                        // report the mask as it was set, not how it was typed for removal
+                        change.parameter = Some(list.remove(index).mask);
+                        return Some(change);
+                    }
+                    _ => return None,
+                }
+
+                change.parameter = Some(mask);
+            }
+
+            (Chanmode::Key, true) => {
+                let key = change.parameter.clone()?;
+                if key.is_empty() || key.contains(',') || self.key.as_ref() == Some(&key) {
+                    return None;
+                }
+
+                self.key = Some(key);
+            }
+            (Chanmode::Key, false) => {
+                // whatever got passed, the actual key is what's being removed
+                change.parameter = Some(self.key.take()?);
+            }
+
+            (Chanmode::Limit, true) => {
+                let limit = change.parameter.as_ref()?.parse::<usize>().ok()?;
+                if limit == 0 || self.limit == Some(limit) {
+                    return None;
+                }
+
+                self.limit = Some(limit);
+                change.parameter = Some(limit.to_string());
+            }
+            (Chanmode::Limit, false) => {
+                self.limit.take()?;
+            }
+
+            (mode, true) => {
+                if !self.flags.insert(mode) {
+                    return None;
+                }
+            }
+            (mode, false) => {
+                if !self.flags.remove(&mode) {
+                    return None;
+                }
+            }
+        }
+
+        Some(change)
+    }
+
+    pub fn has(&self, mode: Chanmode) -> bool {
+        match mode {
+            Chanmode::Key => self.key.is_some(),
+            Chanmode::Limit => self.limit.is_some(),
+            mode => self.flags.contains(&mode),
+        }
+    }
+
+    /// The entries of a list mode
+    pub fn list(&self, mode: Chanmode) -> &[ListEntry] {
+        self.lists.get(&mode).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Modes for RPL_CHANNELMODEIS. The key is only shown to members.
+    pub fn to_mode_string(&self, show_key: bool) -> (String, Vec<String>) {
+        let mut modestring = String::from("+");
+        let mut parameters = Vec::new();
+
+        for mode in Chanmode::ALL {
+            if self.has(mode) {
+                modestring.push(mode.into());
+            }
+        }
+
+        if let Some(key) = &self.key {
+            parameters.push(if show_key {
+                key.clone()
+            } else {
+                "*".to_owned()
+            });
+        }
+        if let Some(limit) = self.limit {
+            parameters.push(limit.to_string());
+        }
+
+        (modestring, parameters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chanmodes::{
+        Chanmode, ChannelModes, MAXLIST, MemberStatus, ModeChange, chanmodes_isupport,
+        maxlist_isupport, parse_mode_changes, prefix_isupport, render_changes, split_status_prefix,
+    };
+
+    fn apply(modes: &mut ChannelModes, changes: Vec<ModeChange>) -> Vec<ModeChange> {
+        changes
+            .into_iter()
+            .filter_map(|change| modes.apply_one(change, "setter!~setter@host"))
+            .collect()
+    }
+
+    #[test]
+    fn test_isupport_tokens() {
+        assert_eq!(chanmodes_isupport(), "CHANMODES=beI,k,l,imnpst");
+        assert_eq!(prefix_isupport(), "PREFIX=(ohv)@%+ STATUSMSG=@%+");
+        assert_eq!(maxlist_isupport(), "MAXLIST=beI:100");
+    }
+
+    #[test]
+    fn test_split_status_prefix() {
+        assert_eq!(
+            split_status_prefix("@#chan"),
+            (Some(MemberStatus::Op), "#chan")
+        );
+        assert_eq!(split_status_prefix("#chan"), (None, "#chan"));
+        assert_eq!(split_status_prefix("+nick"), (None, "+nick"));
+    }
+
+    #[test]
+    fn test_apply_mode_changes() {
+        let mut modes = ChannelModes::default();
+        let parameters = ["secret".to_owned(), "10".to_owned(), "extra".to_owned()];
+
+        let (changes, unknown) = parse_mode_changes("+nkqlm", &parameters);
+        assert_eq!(unknown, vec!['q']);
+
+        let applied = apply(&mut modes, changes);
+        assert_eq!(
+            render_changes(&applied),
+            (
+                "+nklm".to_owned(),
+                vec!["secret".to_owned(), "10".to_owned()]
+            )
+        );
+        assert!(modes.has(Chanmode::Moderated));
+        assert_eq!(
+            modes.to_mode_string(false),
+            ("+nmkl".to_owned(), vec!["*".to_owned(), "10".to_owned()])
+        );
+
+        // setting what's already set does nothing, -k gets the real key back
+        let (changes, _) = parse_mode_changes("+n-kl+l", &["wrong".to_owned(), "x".to_owned()]);
+        assert_eq!(
+            render_changes(&apply(&mut modes, changes)),
+            ("-kl".to_owned(), vec!["secret".to_owned()])
+        );
+        assert_eq!(modes.to_mode_string(true), ("+nm".to_owned(), vec![]));
+    }
+
+    #[test]
+    fn test_list_modes() {
+        let mut modes = ChannelModes::default();
+
+        // a list mode without a mask is a query, which doesn't change anything
+        let (changes, _) = parse_mode_changes("+be", &["bad".to_owned()]);
+        assert_eq!(changes[0].parameter, Some("bad".to_owned()));
+        assert_eq!(changes[1].parameter, None);
+
+        let applied = apply(&mut modes, changes);
+        assert_eq!(
+            render_changes(&applied),
+            ("+b".to_owned(), vec!["bad!*@*".to_owned()])
+        );
+        assert_eq!(modes.list(Chanmode::Ban)[0].setter, "setter!~setter@host");
+
+        // adding it twice does nothing, removing goes by the mask as it was set
+        let (changes, _) = parse_mode_changes("+b-b", &["BAD".to_owned(), "BAD!*@*".to_owned()]);
+        assert_eq!(
+            render_changes(&apply(&mut modes, changes)),
+            ("-b".to_owned(), vec!["bad!*@*".to_owned()])
+        );
+        assert!(modes.list(Chanmode::Ban).is_empty());
+
+        let masks = (0..=MAXLIST)
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>();
+        let (changes, _) = parse_mode_changes(&"+I".repeat(masks.len()), &masks);
+        assert_eq!(apply(&mut modes, changes).len(), MAXLIST);
+    }
+}
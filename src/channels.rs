@@ -1,61 +1,359 @@
-use std::collections::BTreeSet;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use tokio::{io::BufWriter, net::TcpStream};
+use crate::{
+    chanmodes::{Chanmode, ChannelModes, MemberStatus, ModeChange},
+    connection::IrcSink,
+    error_structs::SenderError,
+    masks::{extban_channels, mask_matches, mask_matches_with},
+    sender::{IrcResponse, IrcResponseCodes},
+    state::NETWORK,
+    ts6::structs::UserId,
+    user::{User, UserUnwrapped},
+    usermodes::Usermode,
+};
 
-use crate::{error_structs::SenderError, sender::IrcResponseCodes, user::User};
+/// How many channels a user can be in at once
+pub const CHANLIMIT: usize = 50;
+/// Topics get cut off after this many bytes
+pub const TOPICLEN: usize = 390;
 
 #[derive(Clone, Debug, Hash, Eq, PartialEq)]
 pub struct Channel {
     pub name: String,
-    pub joined_users: BTreeSet<User>,
+    /// Members and the statuses they hold
+    pub members: BTreeMap<UserId, BTreeSet<MemberStatus>>,
+    pub modes: ChannelModes,
+    pub created: SystemTime,
+    pub topic: Option<Topic>,
+    /// Users invited since they last joined, who get past +i
+    pub invites: BTreeSet<UserId>,
+}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Topic {
+    pub text: String,
+    /// Hostmask of the user, or name of the server, who set it
+    pub setter: String,
+    pub set_at: SystemTime,
+}
+
+/// Why a user can't join a channel.
+#[derive(Debug, PartialEq)]
+pub enum JoinError {
+    Banned,
+    InviteOnly,
+    BadKey,
+    ChannelFull,
 }
 
 impl Channel {
-    pub fn add_user(&mut self, user: User) {
-        self.joined_users.insert(user);
-    }
+    /// New channels start out as +nt.
+    pub fn new_channel(name: String) -> Self {
+        let mut modes = ChannelModes::default();
+        modes
+            .flags
+            .extend([Chanmode::NoExternalMessages, Chanmode::TopicLock]);
 
-    pub fn new_channel(name: String, user: User) -> Self {
         Channel {
             name,
-            joined_users: BTreeSet::from([user]),
+            members: BTreeMap::new(),
+            modes,
+            created: SystemTime::now(),
+            topic: None,
+            invites: BTreeSet::new(),
         }
     }
 
-    pub async fn names_list_send(
+    /// Creation time as a unix timestamp, which is also the channel TS for TS6
+    pub fn timestamp(&self) -> u64 {
+        self.created
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or_default()
+    }
+
+    /// Whether the user may join. `banned_from` answers `$j` extbans, as this runs while the
+    /// registry holds on to the channel.
+    pub fn check_join(
         &self,
-        user: User,
-        channel: &Channel,
-        writer: &mut BufWriter<TcpStream>,
-        hostname: &str,
-    ) -> Result<(), SenderError> {
-        let mut members = Vec::new();
+        user: &UserUnwrapped,
+        key: Option<&str>,
+        banned_from: &dyn Fn(&str) -> bool,
+    ) -> Result<(), JoinError> {
+        if self.on_list_with(Chanmode::Ban, user, banned_from)
+            && !self.on_list_with(Chanmode::BanException, user, banned_from)
+        {
+            return Err(JoinError::Banned);
+        }
+
+        if self.modes.has(Chanmode::InviteOnly)
+            && !self.invites.contains(&user.user_id)
+            && !self.on_list_with(Chanmode::InviteException, user, banned_from)
+        {
+            return Err(JoinError::InviteOnly);
+        }
+
+        if let Some(channel_key) = &self.modes.key
+            && key != Some(channel_key.as_str())
+        {
+            return Err(JoinError::BadKey);
+        }
+
+        if self
+            .modes
+            .limit
+            .is_some_and(|limit| self.members.len() >= limit)
+        {
+            return Err(JoinError::ChannelFull);
+        }
+
+        Ok(())
+    }
+
+    /// Whether a user may send messages to the channel. Banned members can't, unless they have a
+    /// status.
+    pub fn can_send(&self, user: &UserUnwrapped) -> bool {
+        let is_member = self.members.contains_key(&user.user_id);
+        let has_status = self.status(&user.user_id).is_some();
+
+        (is_member || !self.modes.has(Chanmode::NoExternalMessages))
+            && (has_status || !self.modes.has(Chanmode::Moderated))
+            && (has_status || !self.is_banned(user))
+    }
+
+    /// Matches a ban, and no ban exception
+    pub fn is_banned(&self, user: &UserUnwrapped) -> bool {
+        self.on_list(Chanmode::Ban, user) && !self.on_list(Chanmode::BanException, user)
+    }
+
+    /// Whether any mask on one of the channel's lists matches the user
+    fn on_list(&self, mode: Chanmode, user: &UserUnwrapped) -> bool {
+        self.modes
+            .list(mode)
+            .iter()
+            .any(|entry| mask_matches(&entry.mask, user))
+    }
+
+    fn on_list_with(
+        &self,
+        mode: Chanmode,
+        user: &UserUnwrapped,
+        banned_from: &dyn Fn(&str) -> bool,
+    ) -> bool {
+        self.modes
+            .list(mode)
+            .iter()
+            .any(|entry| mask_matches_with(&entry.mask, user, banned_from))
+    }
+
+    /// Casefolded names of the channels `$j` extbans on the lists JOIN looks at point to
+    pub fn extban_channels(&self) -> BTreeSet<String> {
+        extban_channels(
+            [
+                Chanmode::Ban,
+                Chanmode::BanException,
+                Chanmode::InviteException,
+            ]
+            .into_iter()
+            .flat_map(|mode| self.modes.list(mode))
+            .map(|entry| entry.mask.as_str()),
+        )
+    }
+
+    /// The highest status a member has
+    pub fn status(&self, user_id: &UserId) -> Option<MemberStatus> {
+        self.members.get(user_id)?.first().copied()
+    }
+
+    /// Members with at least the given status, or everyone for `None`
+    pub fn members_with_status(&self, status: Option<MemberStatus>) -> Vec<UserId> {
+        self.members
+            .iter()
+            .filter(|(_, statuses)| {
+                status.is_none_or(|status| statuses.first().is_some_and(|x| *x <= status))
+            })
+            .map(|(user_id, _)| user_id.clone())
+            .collect()
+    }
+
+    /// Apply mode changes, returning the ones that actually changed something. `setter` is the
+    /// hostmask recorded for new list entries.
+    pub fn apply_modes(&mut self, changes: Vec<ModeChange>, setter: &str) -> Vec<ModeChange> {
+        changes
+            .into_iter()
+            .filter_map(|change| match change.mode.member_status() {
+                Some(status) => self.apply_status(status, change),
+                None => self.modes.apply_one(change, setter),
+            })
+            .collect()
+    }
+
+    /// The parameter of a status change is the member's nickname, or their UID when coming from
+    /// another server. It's always a nickname afterwards.
+    fn apply_status(&mut self, status: MemberStatus, mut change: ModeChange) -> Option<ModeChange> {
+        let target = change.parameter.as_ref()?;
+        let user_id = UserId::try_from(target.clone())
+            .ok()
+            .or_else(|| NETWORK.find_nick(target))?;
+        let statuses = self.members.get_mut(&user_id)?;
+
+        let changed = if change.adding {
+            statuses.insert(status)
+        } else {
+            statuses.remove(&status)
+        };
+        if !changed {
+            return None;
+        }
+
+        change.parameter = Some(NETWORK.get_user(&user_id)?.nickname);
 
-        for member in self.clone().joined_users {
-            members.push(member.nickname.unwrap());
+        Some(change)
+    }
+
+    /// Set the topic, or clear it with an empty text. Returns whether it changed.
+    pub fn set_topic(&mut self, text: &str, setter: &str, set_at: SystemTime) -> bool {
+        let mut end = text.len().min(TOPICLEN);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        let text = &text[..end];
+
+        if self.topic.as_ref().map(|topic| topic.text.as_str()) == Some(text)
+            || (self.topic.is_none() && text.is_empty())
+        {
+            return false;
+        }
+
+        self.topic = (!text.is_empty()).then(|| Topic {
+            text: text.to_owned(),
+            setter: setter.to_owned(),
+            set_at,
+        });
+
+        true
+    }
+
+    /// RPL_TOPIC and RPL_TOPICWHOTIME, or RPL_NOTOPIC
+    pub fn topic_replies(&self, nick: &str) -> Vec<IrcResponse> {
+        let Some(topic) = &self.topic else {
+            return vec![
+                IrcResponseCodes::NoTopic
+                    .into_irc_response(nick.to_owned(), format!("{} :No topic is set", self.name)),
+            ];
+        };
+
+        vec![
+            IrcResponseCodes::Topic
+                .into_irc_response(nick.to_owned(), format!("{} :{}", self.name, topic.text)),
+            IrcResponseCodes::TopicWhoTime.into_irc_response(
+                nick.to_owned(),
+                format!("{} {} {}", self.name, topic.setter, topic.timestamp()),
+            ),
+        ]
+    }
+
+    /// The channel type symbol in RPL_NAMREPLY
+    fn status_symbol(&self) -> char {
+        if self.modes.has(Chanmode::Secret) {
+            '@'
+        } else if self.modes.has(Chanmode::Private) {
+            '*'
+        } else {
+            '='
         }
+    }
+
+    /// Returns whether the user was a member.
+    pub fn remove_member(&mut self, user_id: &UserId) -> bool {
+        self.members.remove(user_id).is_some()
+    }
+
+    /// Members the requester may see, with their highest status prefix or all of them, as
+    /// nicknames or, with userhost-in-names, as hostmasks. Outsiders don't see invisible users.
+    fn visible_members(&self, requester: &User) -> Vec<String> {
+        let is_member = requester
+            .user_id
+            .as_ref()
+            .is_some_and(|user_id| self.members.contains_key(user_id));
+        let multi_prefix = requester.has_capability("multi-prefix");
+        let userhost_in_names = requester.has_capability("userhost-in-names");
+
+        self.members
+            .iter()
+            .filter_map(|(member, statuses)| {
+                let user = NETWORK.get_user(member)?;
+                if !is_member && user.usermodes.has(&Usermode::Invisible) {
+                    return None;
+                }
+
+                let prefixes = statuses
+                    .iter()
+                    .take(if multi_prefix { statuses.len() } else { 1 })
+                    .map(|status| status.prefix())
+                    .collect::<String>();
+
+                Some(if userhost_in_names {
+                    format!("{prefixes}{}", user.hostmask())
+                } else {
+                    format!("{prefixes}{}", user.nickname)
+                })
+            })
+            .collect()
+    }
+
+    /// RPL_NAMREPLY, split over as many lines as it takes, then RPL_ENDOFNAMES. Outsiders only
+    /// get the end of the list for secret and private channels.
+    pub fn names_replies(&self, requester: &User, hostname: &str) -> Vec<IrcResponse> {
+        let nick = requester.nickname.clone().unwrap_or("*".to_owned());
+        let is_member = requester
+            .user_id
+            .as_ref()
+            .is_some_and(|user_id| self.members.contains_key(user_id));
+        let mut replies = Vec::new();
+
+        if is_member || !(self.modes.has(Chanmode::Secret) || self.modes.has(Chanmode::Private)) {
+            let prefix = format!("{} {}", self.status_symbol(), self.name);
+            // what's left of 512 bytes after `:hostname 353 nick = #channel :` and the CRLF
+            let space = 510 - format!(":{hostname} 353 {nick} {prefix} :").len();
+            let mut lines: Vec<String> = Vec::new();
 
-        for member in channel.joined_users.clone() {
-            members.push(member.nickname.unwrap());
+            for member in self.visible_members(requester) {
+                match lines.last_mut() {
+                    Some(line) if line.len() + 1 + member.len() <= space => {
+                        line.push(' ');
+                        line.push_str(&member);
+                    }
+                    _ => lines.push(member),
+                }
+            }
+
+            replies.extend(lines.into_iter().map(|line| {
+                IrcResponseCodes::NameReply
+                    .into_irc_response(nick.clone(), format!("{prefix} :{line}"))
+            }));
         }
 
-        members.sort();
-        members.dedup();
-
-        IrcResponseCodes::NameReply
-            .into_irc_response(
-                user.nickname.clone().unwrap(),
-                format!("= {} :{}", self.name.clone(), members.join(" ")),
-            )
-            .send(hostname, writer, false)
-            .await?;
-        IrcResponseCodes::EndOfNames
-            .into_irc_response(
-                user.nickname.clone().unwrap(),
-                format!("{} :End of /NAMES list", self.name.clone()),
-            )
-            .send(hostname, writer, false)
-            .await?;
+        replies.push(
+            IrcResponseCodes::EndOfNames
+                .into_irc_response(nick, format!("{} :End of /NAMES list", self.name)),
+        );
+
+        replies
+    }
+
+    pub async fn names_list_send(
+        &self,
+        user: User,
+        writer: &mut dyn IrcSink,
+        hostname: &str,
+    ) -> Result<(), SenderError> {
+        for reply in self.names_replies(&user, hostname) {
+            reply.send(hostname, writer, false).await?;
+        }
 
         Ok(())
     }
@@ -63,17 +361,49 @@ impl Channel {
     pub async fn send_topic(
         &self,
         user: User,
-        writer: &mut BufWriter<TcpStream>,
+        writer: &mut dyn IrcSink,
         hostname: &str,
     ) -> Result<(), SenderError> {
-        IrcResponseCodes::NoTopic
-            .into_irc_response(
-                user.nickname.clone().unwrap(),
-                format!("{} :No topic is set", self.name.clone()),
-            )
-            .send(hostname, writer, false)
-            .await?;
+        for reply in self.topic_replies(&user.nickname.clone().unwrap()) {
+            reply.send(hostname, writer, false).await?;
+        }
 
         Ok(())
     }
 }
+
+impl Topic {
+    /// When the topic was set, as a unix timestamp
+    pub fn timestamp(&self) -> u64 {
+        self.set_at
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use crate::channels::{Channel, TOPICLEN};
+
+    #[test]
+    fn test_set_topic() {
+        let mut channel = Channel::new_channel("#test".to_owned());
+        let now = SystemTime::now();
+
+        assert!(!channel.set_topic("", "setter", now));
+        assert!(channel.set_topic("topic", "setter", now));
+        assert!(!channel.set_topic("topic", "someone else", now));
+        assert_eq!(channel.topic.as_ref().unwrap().setter, "setter");
+
+        // cut off at TOPICLEN, without splitting a character
+        let long = format!("a{}", "ä".repeat(TOPICLEN));
+        assert!(channel.set_topic(&long, "setter", now));
+        assert_eq!(channel.topic.as_ref().unwrap().text.len(), TOPICLEN - 1);
+
+        assert!(channel.set_topic("", "setter", now));
+        assert_eq!(channel.topic, None);
+    }
+}
@@ -0,0 +1,150 @@
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+
+use crate::{
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    sasl::{
+        MAX_SASL_RESPONSE_LENGTH, SASL_CHUNK_SIZE, SaslMechanism, SaslSession, SaslStep,
+        encode_chunks,
+    },
+    sender::{IrcResponse, IrcResponseCodes},
+    tags::Tags,
+    user::User,
+};
+
+pub struct Authenticate;
+
+#[async_trait]
+impl IrcHandler for Authenticate {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        _authenticated: bool,
+        user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        let Some(argument) = command.first() else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NeedMoreParams
+                    .into_irc_response(nick, "AUTHENTICATE :Not enough parameters".into()),
+            )];
+        };
+
+        if !user_state.has_capability("sasl") {
+            return vec![sasl_failed(nick)];
+        }
+
+        if argument == "*" {
+            return if user_state.sasl_session.take().is_some() {
+                vec![IrcAction::SendText(
+                    IrcResponseCodes::SaslAborted
+                        .into_irc_response(nick, ":SASL authentication aborted".into()),
+                )]
+            } else {
+                vec![sasl_failed(nick)]
+            };
+        }
+
+        let Some(mut session) = user_state.sasl_session.take() else {
+            if user_state.account.is_some() {
+                return vec![IrcAction::SendText(
+                    IrcResponseCodes::SaslAlready.into_irc_response(
+                        nick,
+                        ":You have already authenticated using SASL".into(),
+                    ),
+                )];
+            }
+
+            return match SaslMechanism::from_name(argument) {
+                Some(mechanism) => {
+                    user_state.sasl_session =
+                        Some(SaslSession::new(mechanism, user_state.certfp.clone()));
+
+                    vec![authenticate("+")]
+                }
+                None => vec![
+                    IrcAction::SendText(IrcResponseCodes::SaslMechs.into_irc_response(
+                        nick.clone(),
+                        format!("{} :are available SASL mechanisms", SaslMechanism::list()),
+                    )),
+                    sasl_failed(nick),
+                ],
+            };
+        };
+
+        if argument != "+" {
+            session.buffer.push_str(argument);
+        }
+
+        if argument.len() > SASL_CHUNK_SIZE || session.buffer.len() > MAX_SASL_RESPONSE_LENGTH {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::SaslTooLong
+                    .into_irc_response(nick, ":SASL message too long".into()),
+            )];
+        }
+
+        // a chunk of exactly 400 bytes means there's more to come
+        if argument.len() == SASL_CHUNK_SIZE {
+            user_state.sasl_session = Some(session);
+
+            return vec![IrcAction::DoNothing];
+        }
+
+        let Ok(response) = BASE64.decode(&session.buffer) else {
+            return vec![sasl_failed(nick)];
+        };
+        session.buffer.clear();
+
+        match session.step(&response).await {
+            SaslStep::Challenge(challenge) => {
+                user_state.sasl_session = Some(session);
+
+                encode_chunks(&challenge)
+                    .iter()
+                    .map(|chunk| authenticate(chunk))
+                    .collect()
+            }
+
+            SaslStep::Success(account) => {
+                user_state.account = Some(account.clone());
+
+                vec![
+                    IrcAction::SendText(IrcResponseCodes::LoggedIn.into_irc_response(
+                        nick.clone(),
+                        format!(
+                            "{} {account} :You are now logged in as {account}",
+                            user_state.partial_hostmask()
+                        ),
+                    )),
+                    IrcAction::SendText(
+                        IrcResponseCodes::SaslSuccess
+                            .into_irc_response(nick, ":SASL authentication successful".into()),
+                    ),
+                ]
+            }
+
+            SaslStep::Failure => vec![sasl_failed(nick)],
+        }
+    }
+}
+
+fn authenticate(payload: &str) -> IrcAction {
+    IrcAction::SendText(IrcResponse {
+        tags: Tags::new(),
+        sender: None,
+        command: "AUTHENTICATE".into(),
+        arguments: vec![payload.to_owned()],
+        receiver: None,
+        message: String::new(),
+    })
+}
+
+fn sasl_failed(nick: String) -> IrcAction {
+    IrcAction::SendText(
+        IrcResponseCodes::SaslFail.into_irc_response(nick, ":SASL authentication failed".into()),
+    )
+}
@@ -0,0 +1,109 @@
+use async_trait::async_trait;
+
+use crate::{
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    messages::{AwayMessage, Message},
+    sender::IrcResponseCodes,
+    tags::Tags,
+    user::User,
+};
+
+/// Away messages get cut off after this many bytes
+pub const AWAYLEN: usize = 200;
+
+pub struct Away;
+
+#[async_trait]
+impl IrcHandler for Away {
+    async fn handle(
+        &self,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        if !authenticated {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        }
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        // no message, or an empty one, means the user is back
+        let reply = match arguments.first().filter(|text| !text.is_empty()) {
+            Some(text) => {
+                let mut end = text.len().min(AWAYLEN);
+                while !text.is_char_boundary(end) {
+                    end -= 1;
+                }
+                user_state.away = Some(text[..end].to_owned());
+
+                IrcResponseCodes::NowAway
+                    .into_irc_response(nick, ":You have been marked as being away".into())
+            }
+            None => {
+                user_state.away = None;
+
+                IrcResponseCodes::UnAway
+                    .into_irc_response(nick, ":You are no longer marked as being away".into())
+            }
+        };
+
+        vec![
+            IrcAction::SendText(reply),
+            IrcAction::SendMessage(Box::new(Message::AwayMessage(AwayMessage {
+                user: user_state.unwrap_all(),
+            }))),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_away() {
+        let mut alice = TestClient::register_with_caps("yalice", &["away-notify"]).await;
+        let mut bob = TestClient::register("ybob").await;
+
+        alice.join_with(&mut bob, "#away").await;
+
+        bob.send("AWAY :out for lunch").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 306 ybob :You have been marked as being away")
+        );
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":ybob!~ybob@unimplement.ed AWAY :out for lunch")
+        );
+
+        alice.send("PRIVMSG ybob :are you there?").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 301 yalice ybob :out for lunch")
+        );
+        alice.send("USERHOST ybob").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 302 yalice :ybob=-~ybob@unimplement.ed")
+        );
+        alice.send("WHO ybob").await;
+        assert_eq!(
+            alice.recv_until("315").await[0],
+            ":irc.example.com 352 yalice * ~ybob unimplement.ed irc.example.com ybob G :0 ybob"
+        );
+
+        bob.send("AWAY").await;
+        bob.recv_until("PRIVMSG").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 305 ybob :You are no longer marked as being away")
+        );
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":ybob!~ybob@unimplement.ed AWAY")
+        );
+    }
+}
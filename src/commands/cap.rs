@@ -1,23 +1,197 @@
 use async_trait::async_trait;
 
 use crate::{
+    capabilities::{chunk_capabilities, get_capability, list_capabilities},
     commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    sender::{IrcResponse, IrcResponseCodes},
+    tags::Tags,
     user::User,
 };
 
 pub struct Cap;
 
+// leaves enough room for the prefix, command, target and subcommand within 512 bytes
+const MAX_CAP_LIST_LENGTH: usize = 400;
+
 #[async_trait]
 impl IrcHandler for Cap {
     async fn handle(
         &self,
-        _arguments: Vec<String>,
+        arguments: Vec<String>,
+        _tags: Tags,
         _authenticated: bool,
-        _user_state: &mut User,
-        _server_outgoing_password: String,
-        _server_incoming_passwords: Vec<String>,
-        _user_passwords: Vec<String>,
+        user_state: &mut User,
+        _config: &ServerInfo,
     ) -> Vec<super::IrcAction> {
-        vec![IrcAction::DoNothing]
+        let target = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        let Some(subcommand) = arguments.first() else {
+            return vec![invalid_cap_command(target, "")];
+        };
+
+        match subcommand.to_uppercase().as_str() {
+            "LS" => {
+                let version = arguments
+                    .get(1)
+                    .and_then(|version| version.parse::<u16>().ok())
+                    .unwrap_or(301);
+
+                user_state.cap_version = user_state.cap_version.max(version);
+                if !user_state.identified {
+                    user_state.cap_negotiating = true;
+                }
+
+                // CAP LS 302 implicitly enables cap-notify
+                if user_state.cap_version >= 302 && get_capability("cap-notify").await.is_some() {
+                    user_state.capabilities.insert("cap-notify".to_owned());
+                }
+
+                let tokens = list_capabilities()
+                    .await
+                    .iter()
+                    .map(|capability| capability.to_ls_string(user_state.cap_version))
+                    .collect();
+
+                cap_list_replies(target, "LS", tokens, user_state.cap_version)
+            }
+
+            "LIST" => {
+                let tokens = user_state.capabilities.iter().cloned().collect();
+
+                cap_list_replies(target, "LIST", tokens, user_state.cap_version)
+            }
+
+            "REQ" => {
+                let Some(requested) = arguments.get(1) else {
+                    return vec![invalid_cap_command(target, subcommand)];
+                };
+
+                if !user_state.identified {
+                    user_state.cap_negotiating = true;
+                }
+
+                let mut enable = Vec::new();
+                let mut disable = Vec::new();
+                let mut acceptable = true;
+
+                for token in requested.split_whitespace() {
+                    let (name, removing) = match token.strip_prefix('-') {
+                        Some(name) => (name, true),
+                        None => (token, false),
+                    };
+
+                    // 302 clients can't opt out of cap-notify
+                    if get_capability(name).await.is_none()
+                        || (removing && name == "cap-notify" && user_state.cap_version >= 302)
+                    {
+                        acceptable = false;
+                        break;
+                    }
+
+                    if removing {
+                        disable.push(name.to_owned());
+                    } else {
+                        enable.push(name.to_owned());
+                    }
+                }
+
+                let reply = if acceptable {
+                    for name in disable {
+                        user_state.capabilities.remove(&name);
+                    }
+                    user_state.capabilities.extend(enable);
+
+                    "ACK"
+                } else {
+                    "NAK"
+                };
+
+                vec![IrcAction::SendText(IrcResponse {
+                    tags: Tags::new(),
+                    sender: None,
+                    command: "CAP".into(),
+                    arguments: vec![target, reply.into()],
+                    receiver: None,
+                    message: format!(":{}", requested.trim()),
+                })]
+            }
+
+            "END" => {
+                user_state.cap_negotiating = false;
+
+                vec![IrcAction::DoNothing]
+            }
+
+            _ => vec![invalid_cap_command(target, subcommand)],
+        }
+    }
+}
+
+fn cap_list_replies(
+    target: String,
+    subcommand: &str,
+    tokens: Vec<String>,
+    cap_version: u16,
+) -> Vec<IrcAction> {
+    // only 302 clients understand multiline replies, everyone else gets a single line
+    let lines = if cap_version >= 302 {
+        chunk_capabilities(tokens, MAX_CAP_LIST_LENGTH)
+    } else {
+        vec![tokens.join(" ")]
+    };
+    let line_count = lines.len();
+
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(idx, line)| {
+            let mut arguments = vec![target.clone(), subcommand.to_owned()];
+            if idx + 1 < line_count {
+                arguments.push("*".to_owned());
+            }
+
+            IrcAction::SendText(IrcResponse {
+                tags: Tags::new(),
+                sender: None,
+                command: "CAP".into(),
+                arguments,
+                receiver: None,
+                message: format!(":{line}"),
+            })
+        })
+        .collect()
+}
+
+fn invalid_cap_command(target: String, subcommand: &str) -> IrcAction {
+    IrcAction::SendText(
+        IrcResponseCodes::InvalidCapCmd
+            .into_irc_response(target, format!("{subcommand} :Invalid CAP command")),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_commands_during_negotiation() {
+        let _bob = TestClient::register("zbob").await;
+        let mut alice = TestClient::connect().await;
+
+        // NICK and USER are in, but registration waits for CAP END
+        alice.send("CAP LS 302").await;
+        alice.send("NICK zalice").await;
+        alice.send("USER zalice 0 * :zalice").await;
+        for command in ["AWAY :gone", "WHO zbob", "WHOIS zbob"] {
+            alice.send(command).await;
+            assert_eq!(
+                alice.recv_until("451").await.last().map(String::as_str),
+                Some(":irc.example.com 451 zalice :You have not registered")
+            );
+        }
+
+        alice.send("CAP END").await;
+        alice.recv_until("001").await;
     }
 }
@@ -0,0 +1,149 @@
+use async_trait::async_trait;
+
+use crate::{
+    chanmodes::{Chanmode, MemberStatus},
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    messages::{InviteMessage, Message},
+    sender::IrcResponseCodes,
+    state::NETWORK,
+    tags::Tags,
+    user::User,
+};
+
+pub struct Invite;
+
+#[async_trait]
+impl IrcHandler for Invite {
+    async fn handle(
+        &self,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        let (true, Some(user_id)) = (authenticated, &user_state.user_id) else {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        };
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        let [target, name, ..] = arguments.as_slice() else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NeedMoreParams
+                    .into_irc_response(nick, "INVITE :Not enough parameters".into()),
+            )];
+        };
+
+        let Some(target) = NETWORK
+            .find_nick(target)
+            .and_then(|target_id| NETWORK.get_user(&target_id))
+        else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NoSuchNick
+                    .into_irc_response(nick, format!("{target} :No such nick/channel")),
+            )];
+        };
+        let Some(channel) = NETWORK.get_channel(name) else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NoSuchChannel
+                    .into_irc_response(nick, format!("{name} :No such channel")),
+            )];
+        };
+
+        let error = if !channel.members.contains_key(user_id) {
+            Some(IrcResponseCodes::NotOnChannel.into_irc_response(
+                nick.clone(),
+                format!("{} :You're not on that channel", channel.name),
+            ))
+        } else if channel.modes.has(Chanmode::InviteOnly)
+            && channel
+                .status(user_id)
+                .is_none_or(|status| status > MemberStatus::HalfOp)
+        {
+            Some(IrcResponseCodes::ChanOPrivsNeeded.into_irc_response(
+                nick.clone(),
+                format!("{} :You're not channel operator", channel.name),
+            ))
+        } else if channel.members.contains_key(&target.user_id) {
+            Some(IrcResponseCodes::UserOnChannel.into_irc_response(
+                nick.clone(),
+                format!(
+                    "{} {} :is already on channel",
+                    target.nickname, channel.name
+                ),
+            ))
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            return vec![IrcAction::SendText(error)];
+        }
+
+        let Some(channel) = NETWORK.invite(name, &target.user_id, |_| true) else {
+            return vec![];
+        };
+
+        vec![
+            IrcAction::SendText(
+                IrcResponseCodes::Inviting
+                    .into_irc_response(nick, format!("{} {}", target.nickname, channel.name)),
+            ),
+            IrcAction::SendMessage(Box::new(Message::InviteMessage(InviteMessage {
+                sender: user_state.unwrap_all(),
+                target,
+                channel,
+            }))),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_invite() {
+        let mut alice = TestClient::register("kalice").await;
+        let mut bob = TestClient::register("kbob").await;
+
+        alice.send("JOIN #door").await;
+        alice.recv_until("366").await;
+        alice.send("MODE #door +i").await;
+        alice.recv_until("MODE").await;
+
+        bob.send("JOIN #door").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 473 kbob #door :Cannot join channel (+i)")
+        );
+
+        alice.send("INVITE kbob #door").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 341 kalice kbob #door")
+        );
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":kalice!~kalice@unimplement.ed INVITE kbob #door")
+        );
+        bob.send("JOIN #door").await;
+        bob.recv_until("366").await;
+        alice.recv_until("JOIN").await;
+
+        alice.send("INVITE kbob #door").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 443 kalice kbob #door :is already on channel")
+        );
+
+        // the invite got used up
+        bob.send("PART #door").await;
+        bob.recv_until("PART").await;
+        bob.send("JOIN #door").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 473 kbob #door :Cannot join channel (+i)")
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use async_trait::async_trait;
+
+use crate::{
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    sender::IrcResponseCodes,
+    state::NETWORK,
+    tags::Tags,
+    user::User,
+};
+
+pub struct Ison;
+
+#[async_trait]
+impl IrcHandler for Ison {
+    async fn handle(
+        &self,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        if !authenticated {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        }
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        if arguments.is_empty() {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NeedMoreParams
+                    .into_irc_response(nick, "ISON :Not enough parameters".into()),
+            )];
+        }
+
+        // nicknames can come as separate parameters or all in the trailing one
+        let online = arguments
+            .iter()
+            .flat_map(|x| x.split_whitespace())
+            .filter_map(|target| NETWORK.get_user(&NETWORK.find_nick(target)?))
+            .map(|user| user.nickname)
+            .collect::<Vec<_>>();
+
+        vec![IrcAction::SendText(
+            IrcResponseCodes::IsOn.into_irc_response(nick, format!(":{}", online.join(" "))),
+        )]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_ison() {
+        let _alice = TestClient::register("jalice").await;
+        let _carol = TestClient::register("jcarol").await;
+        let mut bob = TestClient::register("jbob").await;
+
+        bob.send("ISON JALICE nobody :jcarol").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 303 jbob :jalice jcarol")
+        );
+    }
+}
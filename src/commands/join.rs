@@ -1,9 +1,13 @@
 use async_trait::async_trait;
 
 use crate::{
-    JOINED_CHANNELS,
-    channels::Channel,
+    casemapping::casefold,
+    channels::{CHANLIMIT, JoinError},
     commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    sender::IrcResponseCodes,
+    state::NETWORK,
+    tags::Tags,
     user::User,
 };
 
@@ -14,48 +18,74 @@ impl IrcHandler for Join {
     async fn handle(
         &self,
         arguments: Vec<String>,
+        _tags: Tags,
         authenticated: bool,
         user_state: &mut User,
-        _server_outgoing_password: String,
-        _server_incoming_passwords: Vec<String>,
-        _user_passwords: Vec<String>,
+        _config: &ServerInfo,
     ) -> Vec<super::IrcAction> {
-        let mut joined_channels = JOINED_CHANNELS.lock().await;
+        let (true, Some(user_id)) = (authenticated, &user_state.user_id) else {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        };
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        let Some(names) = arguments.first() else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NeedMoreParams
+                    .into_irc_response(nick, "JOIN :Not enough parameters".into()),
+            )];
+        };
+        let mut keys = arguments
+            .get(1)
+            .map(|keys| keys.split(',').collect::<Vec<&str>>())
+            .unwrap_or_default()
+            .into_iter();
+
         let mut channels = Vec::new();
+        let mut actions = Vec::new();
 
-        for channel in arguments[0].clone().split(',') {
-            let mut maybe_existing_channel: Option<Channel> = None;
+        for channel in names.split(',') {
+            let key = keys.next();
 
             if !channel.starts_with("#") {
                 continue;
             }
 
-            if !authenticated {
-                return vec![IrcAction::ErrorAuthenticateFirst];
+            // joining a channel you're already in does nothing
+            let joined = NETWORK.user_channels(user_id);
+            if joined.contains(&casefold(channel)) {
+                continue;
             }
 
-            for existing_channel in joined_channels.clone() {
-                if existing_channel.name == channel {
-                    maybe_existing_channel = Some(existing_channel);
-                }
+            if joined.len() >= CHANLIMIT {
+                actions.push(IrcAction::SendText(
+                    IrcResponseCodes::TooManyChannels.into_irc_response(
+                        nick.clone(),
+                        format!("{channel} :You have joined too many channels"),
+                    ),
+                ));
+                continue;
             }
 
-            if let Some(mut new_channel) = maybe_existing_channel.clone() {
-                new_channel.joined_users.insert(user_state.clone());
+            match NETWORK.join_channel(channel, &user_state.unwrap_all(), key) {
+                Ok((channel, _)) => channels.push(channel),
+                Err(error) => {
+                    let (numeric, mode) = match error {
+                        JoinError::Banned => (IrcResponseCodes::BannedFromChan, 'b'),
+                        JoinError::ChannelFull => (IrcResponseCodes::ChannelIsFull, 'l'),
+                        JoinError::InviteOnly => (IrcResponseCodes::InviteOnlyChan, 'i'),
+                        JoinError::BadKey => (IrcResponseCodes::BadChannelKey, 'k'),
+                    };
 
-                joined_channels.remove(&maybe_existing_channel.clone().unwrap());
-                joined_channels.insert(new_channel.clone());
-
-                channels.push(new_channel.clone());
-            } else {
-                let new_channel = Channel::new_channel(channel.into(), user_state.clone());
-
-                joined_channels.insert(new_channel.clone());
-
-                channels.push(new_channel.clone());
+                    actions.push(IrcAction::SendText(numeric.into_irc_response(
+                        nick.clone(),
+                        format!("{channel} :Cannot join channel (+{mode})"),
+                    )));
+                }
             }
         }
 
-        vec![IrcAction::JoinChannels(channels)]
+        actions.push(IrcAction::JoinChannels(channels));
+
+        actions
     }
 }
@@ -0,0 +1,169 @@
+use async_trait::async_trait;
+
+use crate::{
+    chanmodes::MemberStatus,
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    messages::{KickMessage, Message},
+    sender::IrcResponseCodes,
+    state::NETWORK,
+    tags::Tags,
+    user::User,
+};
+
+pub struct Kick;
+
+#[async_trait]
+impl IrcHandler for Kick {
+    async fn handle(
+        &self,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        let (true, Some(user_id)) = (authenticated, &user_state.user_id) else {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        };
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        let [name, targets, ..] = arguments.as_slice() else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NeedMoreParams
+                    .into_irc_response(nick, "KICK :Not enough parameters".into()),
+            )];
+        };
+        let reason = arguments
+            .get(2)
+            .filter(|reason| !reason.is_empty())
+            .cloned()
+            .unwrap_or(nick.clone());
+
+        let Some(channel) = NETWORK.get_channel(name) else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NoSuchChannel
+                    .into_irc_response(nick, format!("{name} :No such channel")),
+            )];
+        };
+        let Some(status) = channel
+            .members
+            .get(user_id)
+            .map(|_| channel.status(user_id))
+        else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NotOnChannel.into_irc_response(
+                    nick,
+                    format!("{} :You're not on that channel", channel.name),
+                ),
+            )];
+        };
+
+        targets
+            .split(',')
+            .map(|target| {
+                let Some(target_id) = NETWORK.find_nick(target) else {
+                    return IrcAction::SendText(IrcResponseCodes::NoSuchNick.into_irc_response(
+                        nick.clone(),
+                        format!("{target} :No such nick/channel"),
+                    ));
+                };
+                let Some(target_status) = channel
+                    .members
+                    .get(&target_id)
+                    .map(|_| channel.status(&target_id))
+                else {
+                    return IrcAction::SendText(
+                        IrcResponseCodes::UserNotInChannel.into_irc_response(
+                            nick.clone(),
+                            format!("{target} {} :They aren't on that channel", channel.name),
+                        ),
+                    );
+                };
+
+                // halfops can kick anyone below op
+                let allowed = match status {
+                    Some(MemberStatus::Op) => true,
+                    Some(MemberStatus::HalfOp) => target_status != Some(MemberStatus::Op),
+                    _ => false,
+                };
+                if !allowed {
+                    return IrcAction::SendText(
+                        IrcResponseCodes::ChanOPrivsNeeded.into_irc_response(
+                            nick.clone(),
+                            format!("{} :You're not channel operator", channel.name),
+                        ),
+                    );
+                }
+
+                match (
+                    NETWORK.get_user(&target_id),
+                    NETWORK.part_channel(name, &target_id),
+                ) {
+                    (Some(target), Ok(channel)) => {
+                        IrcAction::SendMessage(Box::new(Message::KickMessage(KickMessage {
+                            sender: user_state.unwrap_all(),
+                            channel,
+                            target,
+                            reason: reason.clone(),
+                        })))
+                    }
+                    // they left while we were looking
+                    _ => IrcAction::SendText(IrcResponseCodes::UserNotInChannel.into_irc_response(
+                        nick.clone(),
+                        format!("{target} {} :They aren't on that channel", channel.name),
+                    )),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_kick() {
+        let (mut alice, mut bob) = TestClient::pair_in_channel("kckalice", "kckbob", "#kick").await;
+        let mut carol = TestClient::register("kckcarol").await;
+        let _dave = TestClient::register("kckdave").await;
+        carol.send("JOIN #kick").await;
+        carol.recv_until("366").await;
+        alice.recv_until("JOIN").await;
+        bob.recv_until("JOIN").await;
+
+        bob.send("KICK #kick kckalice").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 482 kckbob #kick :You're not channel operator")
+        );
+        alice.send("KICK #kick kckdave").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 441 kckalice kckdave #kick :They aren't on that channel")
+        );
+        alice.send("KICK #kick nobody").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 401 kckalice nobody :No such nick/channel")
+        );
+
+        alice.send("KICK #kick kckbob,kckcarol :out").await;
+        let kicks = [
+            ":kckalice!~kckalice@unimplement.ed KICK #kick kckbob :out",
+            ":kckalice!~kckalice@unimplement.ed KICK #kick kckcarol :out",
+        ];
+        assert_eq!(alice.recv_until("KICK").await, [kicks[0]]);
+        assert_eq!(alice.recv_until("KICK").await, [kicks[1]]);
+        assert_eq!(bob.recv_until("KICK").await, [kicks[0]]);
+        assert_eq!(carol.recv_until("KICK").await, [kicks[0]]);
+        assert_eq!(carol.recv_until("KICK").await, [kicks[1]]);
+
+        alice.send("NAMES #kick").await;
+        assert_eq!(
+            alice.recv_until("353").await,
+            [":irc.example.com 353 kckalice = #kick :@kckalice"]
+        );
+    }
+}
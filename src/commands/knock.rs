@@ -0,0 +1,126 @@
+use async_trait::async_trait;
+
+use crate::{
+    chanmodes::Chanmode,
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    messages::{KnockMessage, Message},
+    sender::IrcResponseCodes,
+    state::NETWORK,
+    tags::Tags,
+    user::User,
+};
+
+pub struct Knock;
+
+#[async_trait]
+impl IrcHandler for Knock {
+    async fn handle(
+        &self,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        let (true, Some(user_id)) = (authenticated, &user_state.user_id) else {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        };
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        let Some(name) = arguments.first() else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NeedMoreParams
+                    .into_irc_response(nick, "KNOCK :Not enough parameters".into()),
+            )];
+        };
+        let Some(channel) = NETWORK.get_channel(name) else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NoSuchChannel
+                    .into_irc_response(nick, format!("{name} :No such channel")),
+            )];
+        };
+        let user = user_state.unwrap_all();
+
+        let error = if channel.members.contains_key(user_id) {
+            IrcResponseCodes::KnockOnChan.into_irc_response(
+                nick,
+                format!("{} :You're already on that channel", channel.name),
+            )
+        } else if !channel.modes.has(Chanmode::InviteOnly) {
+            IrcResponseCodes::ChanOpen
+                .into_irc_response(nick, format!("{} :Channel is open", channel.name))
+        } else if channel.is_banned(&user) {
+            // banned users would only be pestering the operators
+            IrcResponseCodes::CannotSendToChan
+                .into_irc_response(nick, format!("{} :Cannot send to channel", channel.name))
+        } else {
+            return vec![
+                IrcAction::SendText(IrcResponseCodes::KnockDelivered.into_irc_response(
+                    nick,
+                    format!("{} :Your KNOCK has been delivered", channel.name),
+                )),
+                IrcAction::SendMessage(Box::new(Message::KnockMessage(KnockMessage {
+                    sender: user,
+                    channel,
+                }))),
+            ];
+        };
+
+        vec![IrcAction::SendText(error)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_knock() {
+        let mut alice = TestClient::register("knkalice").await;
+        let mut bob = TestClient::register("knkbob").await;
+        let mut carol = TestClient::register("knkcarol").await;
+
+        alice.send("JOIN #knock").await;
+        alice.recv_until("366").await;
+
+        bob.send("KNOCK #knock").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 713 knkbob #knock :Channel is open")
+        );
+        alice.send("KNOCK #knock").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 714 knkalice #knock :You're already on that channel")
+        );
+        bob.send("KNOCK #nowhere").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 403 knkbob #nowhere :No such channel")
+        );
+
+        alice.send("MODE #knock +ib knkcarol").await;
+        alice.recv_until("MODE").await;
+
+        bob.send("KNOCK #knock").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 711 knkbob #knock :Your KNOCK has been delivered")
+        );
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(
+                ":irc.example.com 710 knkalice #knock knkbob!~knkbob@unimplement.ed :has asked for an invite"
+            )
+        );
+
+        // banned users don't get to bother the operators
+        carol.send("KNOCK #knock").await;
+        assert_eq!(
+            carol.recv().await.as_deref(),
+            Some(":irc.example.com 404 knkcarol #knock :Cannot send to channel")
+        );
+        assert_eq!(alice.recv().await, None);
+    }
+}
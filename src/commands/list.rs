@@ -0,0 +1,284 @@
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+
+use crate::{
+    chanmodes::Chanmode,
+    channels::Channel,
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    connection::IrcSink,
+    error_structs::SenderError,
+    masks::glob_match,
+    sender::IrcResponseCodes,
+    state::NETWORK,
+    tags::Tags,
+    ts6::structs::UserId,
+    user::User,
+};
+
+/// The ELIST extensions we understand
+pub const ELIST: &str = "CMNTU";
+
+pub struct List;
+
+/// One of the comma separated conditions a LIST can be given. Times are in minutes ago.
+#[derive(Clone, Debug, PartialEq)]
+enum Filter {
+    MoreUsers(usize),
+    FewerUsers(usize),
+    CreatedWithin(u64),
+    CreatedBefore(u64),
+    TopicWithin(u64),
+    TopicBefore(u64),
+    Mask(String),
+    NotMask(String),
+}
+
+/// A listing waiting to be sent. Channels are looked up one at a time as they get written out, so
+/// the channel registry is never locked for the whole listing, and the lines go straight to the
+/// socket instead of piling up in the message queue.
+pub struct ChannelList {
+    nick: String,
+    user_id: UserId,
+    filters: Vec<Filter>,
+}
+
+#[async_trait]
+impl IrcHandler for List {
+    async fn handle(
+        &self,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        let (true, Some(user_id)) = (authenticated, &user_state.user_id) else {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        };
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        let filters = arguments
+            .first()
+            .map(|x| x.split(',').filter_map(parse_filter).collect())
+            .unwrap_or_default();
+
+        vec![
+            IrcAction::SendText(
+                IrcResponseCodes::ListStart
+                    .into_irc_response(nick.clone(), "Channel :Users  Name".into()),
+            ),
+            IrcAction::SendChannelList(ChannelList {
+                nick: nick.clone(),
+                user_id: user_id.clone(),
+                filters,
+            }),
+            IrcAction::SendText(
+                IrcResponseCodes::ListEnd.into_irc_response(nick, ":End of /LIST".into()),
+            ),
+        ]
+    }
+}
+
+impl ChannelList {
+    pub async fn send(&self, hostname: &str, writer: &mut dyn IrcSink) -> Result<(), SenderError> {
+        for name in NETWORK.channel_names() {
+            // it might have gone away since
+            let Some(channel) = NETWORK.get_channel(&name) else {
+                continue;
+            };
+            let is_member = channel.members.contains_key(&self.user_id);
+            if !is_member
+                && (channel.modes.has(Chanmode::Secret) || channel.modes.has(Chanmode::Private))
+            {
+                continue;
+            }
+            if !self.filters.iter().all(|filter| filter.matches(&channel)) {
+                continue;
+            }
+
+            let (modestring, parameters) = channel.modes.to_mode_string(is_member);
+            let modes = [vec![modestring], parameters].concat().join(" ");
+            let topic = channel.topic.map(|x| x.text).unwrap_or_default();
+
+            IrcResponseCodes::List
+                .into_irc_response(
+                    self.nick.clone(),
+                    format!(
+                        "{} {} :[{modes}] {topic}",
+                        channel.name,
+                        channel.members.len()
+                    ),
+                )
+                .send(hostname, writer, false)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Filter {
+    fn matches(&self, channel: &Channel) -> bool {
+        let topic_age = channel.topic.as_ref().map(|x| minutes_ago(x.set_at));
+
+        match self {
+            Filter::MoreUsers(count) => channel.members.len() > *count,
+            Filter::FewerUsers(count) => channel.members.len() < *count,
+            Filter::CreatedWithin(minutes) => minutes_ago(channel.created) < *minutes,
+            Filter::CreatedBefore(minutes) => minutes_ago(channel.created) > *minutes,
+            // channels without a topic don't match either
+            Filter::TopicWithin(minutes) => topic_age.is_some_and(|x| x < *minutes),
+            Filter::TopicBefore(minutes) => topic_age.is_some_and(|x| x > *minutes),
+            Filter::Mask(mask) => glob_match(mask, &channel.name),
+            Filter::NotMask(mask) => !glob_match(mask, &channel.name),
+        }
+    }
+}
+
+fn minutes_ago(time: SystemTime) -> u64 {
+    SystemTime::now()
+        .duration_since(time)
+        .unwrap_or_default()
+        .as_secs()
+        / 60
+}
+
+/// Filters that don't parse get ignored
+fn parse_filter(filter: &str) -> Option<Filter> {
+    let number = |x: &str| x.parse::<u64>().ok();
+
+    match filter.split_at_checked(2).unwrap_or((filter, "")) {
+        ("C<", minutes) => number(minutes).map(Filter::CreatedWithin),
+        ("C>", minutes) => number(minutes).map(Filter::CreatedBefore),
+        ("T<", minutes) => number(minutes).map(Filter::TopicWithin),
+        ("T>", minutes) => number(minutes).map(Filter::TopicBefore),
+        _ => match filter.split_at_checked(1).unwrap_or((filter, "")) {
+            (">", count) => count.parse().ok().map(Filter::MoreUsers),
+            ("<", count) => count.parse().ok().map(Filter::FewerUsers),
+            ("!", mask) if !mask.is_empty() => Some(Filter::NotMask(mask.to_owned())),
+            _ if !filter.is_empty() => Some(Filter::Mask(filter.to_owned())),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        commands::{
+            IrcAction,
+            list::{ChannelList, Filter, parse_filter},
+        },
+        connection::StreamSink,
+        state::NETWORK,
+        test_client::TestClient,
+        user::User,
+    };
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(parse_filter(">5"), Some(Filter::MoreUsers(5)));
+        assert_eq!(parse_filter("<2"), Some(Filter::FewerUsers(2)));
+        assert_eq!(parse_filter("C<10"), Some(Filter::CreatedWithin(10)));
+        assert_eq!(parse_filter("T>60"), Some(Filter::TopicBefore(60)));
+        assert_eq!(parse_filter("#rust*"), Some(Filter::Mask("#rust*".into())));
+        assert_eq!(
+            parse_filter("!#off*"),
+            Some(Filter::NotMask("#off*".into()))
+        );
+        assert_eq!(parse_filter(">many"), None);
+        assert_eq!(parse_filter(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_list() {
+        let mut alice = TestClient::register("lalice").await;
+        let mut bob = TestClient::register("lbob").await;
+
+        alice.send("JOIN #lsta,#lstb,#lsthidden").await;
+        for _ in 0..3 {
+            alice.recv_until("366").await;
+        }
+        alice.send("TOPIC #lsta :first").await;
+        alice.recv_until("TOPIC").await;
+        alice.send("MODE #lsthidden +s").await;
+        alice.recv_until("MODE").await;
+        bob.send("JOIN #lstb").await;
+        bob.recv_until("366").await;
+        alice.recv_until("JOIN").await;
+
+        bob.send("LIST #lst*").await;
+        let listed = bob.recv_until("323").await;
+        assert_eq!(
+            listed.first().map(String::as_str),
+            Some(":irc.example.com 321 lbob Channel :Users  Name")
+        );
+        let mut channels = listed[1..listed.len() - 1].to_vec();
+        channels.sort();
+        assert_eq!(
+            channels,
+            [
+                ":irc.example.com 322 lbob #lsta 1 :[+nt] first",
+                ":irc.example.com 322 lbob #lstb 2 :[+nt] ",
+            ]
+        );
+        assert_eq!(
+            listed.last().map(String::as_str),
+            Some(":irc.example.com 323 lbob :End of /LIST")
+        );
+
+        // members still see secret channels
+        alice.send("LIST #lst*,>1").await;
+        assert_eq!(
+            alice.recv_until("323").await[1..],
+            [
+                ":irc.example.com 322 lalice #lstb 2 :[+nt] ",
+                ":irc.example.com 323 lalice :End of /LIST"
+            ]
+        );
+        alice.send("LIST #lsthidden,T<60").await;
+        assert_eq!(
+            alice.recv_until("323").await[1..],
+            [":irc.example.com 323 lalice :End of /LIST"]
+        );
+        alice.send("LIST #lst*,!#lstb,C<60,<2").await;
+        let mut channels = alice.recv_until("323").await[1..].to_vec();
+        channels.sort();
+        assert_eq!(
+            channels,
+            [
+                ":irc.example.com 322 lalice #lsta 1 :[+nt] first",
+                ":irc.example.com 322 lalice #lsthidden 1 :[+nts] ",
+                ":irc.example.com 323 lalice :End of /LIST",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_to_closed_connection() {
+        let mut alice = TestClient::register("lgone").await;
+        alice.send("JOIN #lstgone").await;
+        alice.recv_until("366").await;
+
+        let (client, server) = tokio::io::duplex(64);
+        drop(client);
+        let list = IrcAction::SendChannelList(ChannelList {
+            nick: "lgone".to_owned(),
+            user_id: NETWORK.find_nick("lgone").unwrap(),
+            filters: Vec::new(),
+        });
+
+        // the error makes it back to the connection, which cleans up after the user
+        assert!(
+            list.execute(
+                &mut StreamSink::new(server),
+                "irc.example.com",
+                &User::default()
+            )
+            .await
+            .is_err()
+        );
+    }
+}
@@ -1,36 +1,80 @@
 #![allow(dead_code)]
 use std::collections::HashMap;
 
-use async_trait::async_trait;
-use tokio::{io::BufWriter, net::TcpStream, sync::broadcast::Sender};
-
 use crate::{
-    SENDER,
     channels::Channel,
     commands::{
-        cap::Cap, join::Join, nick::Nick, pass::Pass, ping::Ping, privmsg::PrivMsg,
-        user::User as UserHandler, who::Who,
+        authenticate::Authenticate,
+        away::Away,
+        cap::Cap,
+        invite::Invite,
+        ison::Ison,
+        join::Join,
+        kick::Kick,
+        knock::Knock,
+        list::{ChannelList, List},
+        mode::Mode,
+        names::Names,
+        nick::Nick,
+        oper::Oper,
+        part::Part,
+        pass::Pass,
+        ping::Ping,
+        pong::Pong,
+        privmsg::PrivMsg,
+        quit::Quit,
+        tagmsg::TagMsg,
+        topic::Topic,
+        user::User as UserHandler,
+        userhost::{Userhost, Userip},
+        who::Who,
+        whois::Whois,
+        whowas::Whowas,
     },
     config::ServerInfo,
-    error_structs::CommandExecError,
+    connection::IrcSink,
+    error_structs::{CommandExecError, ParseError, SenderError},
     messages::{ChanJoinMessage, Message},
-    sender::IrcResponse,
+    parser::ParsedMessage,
+    routing::route,
+    sender::{IrcResponse, IrcResponseCodes},
+    tags::Tags,
     user::User,
 };
+use async_trait::async_trait;
 
+mod authenticate;
+pub mod away;
 mod cap;
+mod invite;
+mod ison;
 mod join;
-mod nick;
+mod kick;
+mod knock;
+pub mod list;
+mod mode;
+mod names;
+pub mod nick;
+mod oper;
+mod part;
 mod pass;
 mod ping;
+mod pong;
 mod privmsg;
+mod quit;
+mod tagmsg;
+mod topic;
 mod user;
+mod userhost;
 mod who;
+pub mod whois;
+mod whowas;
 
 #[derive(Debug)]
 pub struct IrcCommand {
     command: String,
     arguments: Vec<String>,
+    tags: Tags,
 }
 
 pub struct IrcMessage {
@@ -40,9 +84,12 @@ pub struct IrcMessage {
 
 pub enum IrcAction {
     SendText(IrcResponse),
-    SendMessage(Message),
+    SendMessage(Box<Message>),
     JoinChannels(Vec<Channel>),
+    SendChannelList(ChannelList),
     UpgradeToServerConn,
+    /// Close the connection, with the given quit reason
+    Quit(String),
     ErrorAuthenticateFirst,
     DoNothing,
 }
@@ -50,7 +97,7 @@ pub enum IrcAction {
 pub enum ReturnAction {
     Nothing,
     ServerConn,
-    CloseConn,
+    CloseConn(String),
 }
 
 #[async_trait]
@@ -58,98 +105,85 @@ pub trait IrcHandler: Send + Sync {
     async fn handle(
         &self,
         command: Vec<String>,
+        tags: Tags,
         authenticated: bool,
         user_state: &mut User,
-        server_outgoing_password: String,
-        server_incoming_passwords: Vec<String>,
-        user_passwords: Vec<String>,
+        config: &ServerInfo,
     ) -> Vec<IrcAction>;
 }
 
 pub struct SendMessage(Option<String>);
 
 impl IrcCommand {
-    pub async fn new(command_with_arguments: String) -> Self {
-        let mut split_command: Vec<&str> = command_with_arguments
-            .split_whitespace()
-            .into_iter()
-            .collect();
-
-        if split_command[0].starts_with(":") {
-            split_command.remove(0);
-        }
-
-        let command = split_command[0].to_owned();
-        let mut arguments = Vec::new();
-        let mut buffer: Option<String> = None;
-
-        split_command[1..]
-            .iter()
-            .for_each(|e| match (buffer.as_mut(), e.starts_with(":")) {
-                (None, false) => arguments.push(e.to_string()),
-                (None, true) => {
-                    buffer = Some(e[1..].to_string());
-                }
-                (Some(buf), starts_with_colon) => {
-                    buf.push(' ');
-                    buf.push_str(if starts_with_colon { &e[1..] } else { &e });
-                }
-            });
-
-        if let Some(buf) = buffer {
-            arguments.push(buf.to_string());
-        }
-
-        Self {
-            command: command,
-            arguments: arguments,
-        }
+    pub async fn new(command_with_arguments: String) -> Result<Self, ParseError> {
+        let message = ParsedMessage::parse(&command_with_arguments)?;
+
+        Ok(Self {
+            command: message.command.to_owned(),
+            arguments: message.owned_params(),
+            tags: message.tags(),
+        })
     }
 
     pub async fn execute(
         &self,
-        writer: &mut BufWriter<TcpStream>,
+        writer: &mut dyn IrcSink,
         hostname: &str,
         user_state: &mut User,
         config: &ServerInfo,
     ) -> Result<Vec<ReturnAction>, CommandExecError> {
         let mut command_map: HashMap<String, &dyn IrcHandler> = HashMap::new();
-        let broadcast_sender = SENDER.lock().await.clone().unwrap();
 
         // Command map is defined here
         command_map.insert("CAP".to_owned(), &Cap);
+        command_map.insert("AUTHENTICATE".to_owned(), &Authenticate);
         command_map.insert("NICK".to_owned(), &Nick);
         command_map.insert("USER".to_owned(), &UserHandler);
         command_map.insert("PRIVMSG".to_owned(), &PrivMsg);
+        command_map.insert("TAGMSG".to_owned(), &TagMsg);
         command_map.insert("PING".to_owned(), &Ping);
+        command_map.insert("PONG".to_owned(), &Pong);
+        command_map.insert("QUIT".to_owned(), &Quit);
         command_map.insert("JOIN".to_owned(), &Join);
+        command_map.insert("PART".to_owned(), &Part);
+        command_map.insert("MODE".to_owned(), &Mode);
+        command_map.insert("TOPIC".to_owned(), &Topic);
+        command_map.insert("KICK".to_owned(), &Kick);
+        command_map.insert("INVITE".to_owned(), &Invite);
+        command_map.insert("KNOCK".to_owned(), &Knock);
         command_map.insert("WHO".to_owned(), &Who);
+        command_map.insert("WHOIS".to_owned(), &Whois);
+        command_map.insert("WHOWAS".to_owned(), &Whowas);
+        command_map.insert("LIST".to_owned(), &List);
+        command_map.insert("NAMES".to_owned(), &Names);
+        command_map.insert("ISON".to_owned(), &Ison);
+        command_map.insert("USERHOST".to_owned(), &Userhost);
+        command_map.insert("USERIP".to_owned(), &Userip);
+        command_map.insert("AWAY".to_owned(), &Away);
         command_map.insert("PASS".to_owned(), &Pass);
+        command_map.insert("OPER".to_owned(), &Oper);
 
         println!("{self:#?}");
 
         let command_to_execute = command_map
             .get(&self.command.to_uppercase())
-            .map(|v| *v)
+            .copied()
             .ok_or(CommandExecError::NonexistantCommand)?;
 
         let actions = command_to_execute
             .handle(
                 self.arguments.clone(),
+                self.tags.clone(),
                 user_state.is_populated(),
                 user_state,
-                config.server_outgoing_password.clone(),
-                config.server_incoming_passwords.clone(),
-                vec![], // TODO
+                config,
             )
             .await;
 
         let mut return_actions = Vec::new();
 
         for action in actions {
-            let return_action = action
-                .execute(writer, hostname, &user_state, broadcast_sender.clone())
-                .await;
+            let return_action = action.execute(writer, hostname, user_state).await?;
 
             return_actions.push(return_action);
         }
@@ -161,14 +195,13 @@ impl IrcCommand {
 impl IrcAction {
     pub async fn execute(
         &self,
-        writer: &mut BufWriter<TcpStream>,
+        writer: &mut dyn IrcSink,
         hostname: &str,
         user_state: &User,
-        sender: Sender<Message>,
-    ) -> ReturnAction {
+    ) -> Result<ReturnAction, SenderError> {
         match self {
             IrcAction::SendText(msg) => {
-                msg.send(hostname, writer, false).await.unwrap();
+                msg.send(hostname, writer, false).await?;
             }
 
             IrcAction::JoinChannels(channels) => {
@@ -177,21 +210,40 @@ impl IrcAction {
                         sender: user_state.clone().unwrap_all(),
                         channel: channel.clone(),
                     };
-                    sender.send(Message::ChanJoinMessage(join_message)).unwrap();
+                    route(Message::ChanJoinMessage(join_message)).await;
                 }
             }
 
+            IrcAction::SendChannelList(list) => {
+                // the client might leave halfway through a long listing
+                list.send(hostname, writer).await?;
+            }
+
             IrcAction::SendMessage(msg) => {
-                sender.send(msg.clone()).unwrap();
+                route(*msg.clone()).await;
             }
 
             IrcAction::UpgradeToServerConn => {
-                return ReturnAction::ServerConn;
+                return Ok(ReturnAction::ServerConn);
+            }
+
+            IrcAction::Quit(reason) => {
+                return Ok(ReturnAction::CloseConn(reason.clone()));
+            }
+
+            IrcAction::ErrorAuthenticateFirst => {
+                IrcResponseCodes::NotRegistered
+                    .into_irc_response(
+                        user_state.nickname.clone().unwrap_or("*".to_owned()),
+                        ":You have not registered".into(),
+                    )
+                    .send(hostname, writer, false)
+                    .await?;
             }
 
             _ => {}
         }
 
-        return ReturnAction::Nothing;
+        Ok(ReturnAction::Nothing)
     }
 }
@@ -0,0 +1,541 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+
+use crate::{
+    casemapping::casefold,
+    chanmodes::{Chanmode, MAXLIST, MemberStatus, ModeChange, ModeKind, parse_mode_changes},
+    channels::Channel,
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    messages::{ChannelModeMessage, Message},
+    sender::{IrcResponse, IrcResponseCodes},
+    state::NETWORK,
+    tags::Tags,
+    user::User,
+    usermodes::Usermode,
+};
+
+pub struct Mode;
+
+#[async_trait]
+impl IrcHandler for Mode {
+    async fn handle(
+        &self,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        let (true, Some(user_id)) = (authenticated, &user_state.user_id) else {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        };
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        let Some(target) = arguments.first() else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NeedMoreParams
+                    .into_irc_response(nick, "MODE :Not enough parameters".into()),
+            )];
+        };
+
+        if !target.starts_with('#') {
+            if casefold(target) != casefold(&nick) {
+                return vec![IrcAction::SendText(
+                    IrcResponseCodes::UsersDontMatch
+                        .into_irc_response(nick, ":Can't change mode for other users".into()),
+                )];
+            }
+
+            return match arguments.get(1) {
+                Some(modestring) => change_user_modes(user_state, nick, modestring),
+                None => vec![IrcAction::SendText(
+                    IrcResponseCodes::UModeIs
+                        .into_irc_response(nick, user_state.usermodes.clone().into()),
+                )],
+            };
+        }
+
+        let Some(channel) = NETWORK.get_channel(target) else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NoSuchChannel
+                    .into_irc_response(nick, format!("{target} :No such channel")),
+            )];
+        };
+        let is_member = channel.members.contains_key(user_id);
+
+        let Some(modestring) = arguments.get(1) else {
+            let (modestring, parameters) = channel.modes.to_mode_string(is_member);
+
+            return vec![
+                IrcAction::SendText(
+                    IrcResponseCodes::ChannelModeIs.into_irc_response(
+                        nick.clone(),
+                        [vec![channel.name.clone(), modestring], parameters]
+                            .concat()
+                            .join(" "),
+                    ),
+                ),
+                IrcAction::SendText(
+                    IrcResponseCodes::CreationTime.into_irc_response(
+                        nick,
+                        format!("{} {}", channel.name, channel.timestamp()),
+                    ),
+                ),
+            ];
+        };
+
+        let (changes, unknown) = parse_mode_changes(modestring, &arguments[2..]);
+
+        // a list mode without a mask only shows the list, which anyone may look at
+        let (queries, changes): (Vec<ModeChange>, Vec<ModeChange>) = changes
+            .into_iter()
+            .partition(|change| change.mode.kind() == ModeKind::List && change.parameter.is_none());
+        let mut queried = queries
+            .into_iter()
+            .map(|change| change.mode)
+            .collect::<Vec<Chanmode>>();
+        queried.sort();
+        queried.dedup();
+
+        let mut actions = queried
+            .into_iter()
+            .flat_map(|mode| list_replies(&nick, &channel, mode))
+            .collect::<Vec<IrcAction>>();
+
+        if !changes.is_empty() && !is_member {
+            actions.push(IrcAction::SendText(
+                IrcResponseCodes::NotOnChannel.into_irc_response(
+                    nick,
+                    format!("{} :You're not on that channel", channel.name),
+                ),
+            ));
+            return actions;
+        }
+
+        actions.extend(unknown.into_iter().map(|mode| {
+            IrcAction::SendText(
+                IrcResponseCodes::UnknownMode
+                    .into_irc_response(nick.clone(), format!("{mode} :is unknown mode char to me")),
+            )
+        }));
+
+        // halfops can do everything but hand out op and halfop
+        let status = channel.status(user_id);
+        let (changes, denied): (Vec<ModeChange>, Vec<ModeChange>) =
+            changes.into_iter().partition(|change| match status {
+                Some(MemberStatus::Op) => true,
+                Some(MemberStatus::HalfOp) => !matches!(
+                    change.mode.member_status(),
+                    Some(MemberStatus::Op | MemberStatus::HalfOp)
+                ),
+                _ => false,
+            });
+
+        if !denied.is_empty() {
+            actions.push(IrcAction::SendText(
+                IrcResponseCodes::ChanOPrivsNeeded.into_irc_response(
+                    nick.clone(),
+                    format!("{} :You're not channel operator", channel.name),
+                ),
+            ));
+        }
+
+        // masks added earlier in the same line take up room too
+        let mut added = BTreeMap::<Chanmode, usize>::new();
+        let changes = changes
+            .into_iter()
+            .filter(|change| {
+                if change.adding && change.mode.kind() == ModeKind::List {
+                    let count = added.entry(change.mode).or_default();
+                    if channel.modes.list(change.mode).len() + *count < MAXLIST {
+                        *count += 1;
+                        return true;
+                    }
+
+                    actions.push(IrcAction::SendText(
+                        IrcResponseCodes::BanListFull.into_irc_response(
+                            nick.clone(),
+                            format!(
+                                "{} {} :Channel list is full",
+                                channel.name,
+                                change.parameter.clone().unwrap_or_default()
+                            ),
+                        ),
+                    ));
+                    return false;
+                }
+
+                let (Some(_), Some(target)) = (change.mode.member_status(), &change.parameter)
+                else {
+                    return true;
+                };
+
+                let error = match NETWORK.find_nick(target) {
+                    None => IrcResponseCodes::NoSuchNick
+                        .into_irc_response(nick.clone(), format!("{target} :No such nick/channel")),
+                    Some(target_id) if !channel.members.contains_key(&target_id) => {
+                        IrcResponseCodes::UserNotInChannel.into_irc_response(
+                            nick.clone(),
+                            format!("{target} {} :They aren't on that channel", channel.name),
+                        )
+                    }
+                    Some(_) => return true,
+                };
+                actions.push(IrcAction::SendText(error));
+
+                false
+            })
+            .collect::<Vec<ModeChange>>();
+
+        let setter = user_state.unwrap_all().hostmask();
+        let applied = NETWORK.update_channel(target, |channel| {
+            let applied = channel.apply_modes(changes, &setter);
+
+            (channel.clone(), applied)
+        });
+
+        if let Some((channel, changes)) = applied
+            && !changes.is_empty()
+        {
+            actions.push(IrcAction::SendMessage(Box::new(
+                Message::ChannelModeMessage(ChannelModeMessage {
+                    sender: user_state.unwrap_all(),
+                    channel,
+                    changes,
+                }),
+            )));
+        }
+
+        actions
+    }
+}
+
+/// Users can only make themselves invisible or visible again, the other modes are up to the server.
+fn change_user_modes(user_state: &mut User, nick: String, modestring: &str) -> Vec<IrcAction> {
+    let was_invisible = user_state.usermodes.has(&Usermode::Invisible);
+    let mut actions = Vec::new();
+    let mut adding = true;
+
+    for letter in modestring.chars() {
+        match (letter, Usermode::from_char(letter)) {
+            ('+', _) => adding = true,
+            ('-', _) => adding = false,
+            (_, Some(Usermode::Invisible)) if adding => {
+                user_state.usermodes.add(Usermode::Invisible);
+            }
+            (_, Some(Usermode::Invisible)) => user_state.usermodes.remove(&Usermode::Invisible),
+            (_, Some(_)) => {}
+            // once is enough, however many unknown letters there were
+            (_, None) if actions.is_empty() => {
+                actions.push(IrcAction::SendText(
+                    IrcResponseCodes::UModeUnknownFlag
+                        .into_irc_response(nick.clone(), ":Unknown MODE flag".into()),
+                ));
+            }
+            (_, None) => {}
+        }
+    }
+
+    let is_invisible = user_state.usermodes.has(&Usermode::Invisible);
+    if is_invisible != was_invisible {
+        actions.push(IrcAction::SendText(IrcResponse {
+            tags: Tags::new(),
+            sender: Some(user_state.unwrap_all().hostmask()),
+            command: "MODE".into(),
+            arguments: vec![nick],
+            receiver: None,
+            message: if is_invisible { ":+i" } else { ":-i" }.into(),
+        }));
+    }
+
+    actions
+}
+
+/// The entries of a +b, +e or +I list, followed by its end numeric
+fn list_replies(nick: &str, channel: &Channel, mode: Chanmode) -> Vec<IrcAction> {
+    let (entry_code, end_code, name) = match mode {
+        Chanmode::BanException => (
+            IrcResponseCodes::ExceptList,
+            IrcResponseCodes::EndOfExceptList,
+            "exception",
+        ),
+        Chanmode::InviteException => (
+            IrcResponseCodes::InviteList,
+            IrcResponseCodes::EndOfInviteList,
+            "invite",
+        ),
+        _ => (
+            IrcResponseCodes::BanList,
+            IrcResponseCodes::EndOfBanList,
+            "ban",
+        ),
+    };
+
+    channel
+        .modes
+        .list(mode)
+        .iter()
+        .map(|entry| {
+            IrcAction::SendText(entry_code.into_irc_response(
+                nick.to_owned(),
+                format!(
+                    "{} {} {} {}",
+                    channel.name,
+                    entry.mask,
+                    entry.setter,
+                    entry.timestamp()
+                ),
+            ))
+        })
+        .chain([IrcAction::SendText(end_code.into_irc_response(
+            nick.to_owned(),
+            format!("{} :End of channel {name} list", channel.name),
+        ))])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{chanmodes::MAXLIST, test_client::TestClient};
+
+    #[tokio::test]
+    async fn test_channel_modes() {
+        let mut alice = TestClient::register("malice").await;
+        let mut bob = TestClient::register("mbob").await;
+
+        alice.send("JOIN #modes").await;
+        alice.recv_until("366").await;
+        alice.send("MODE #modes").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 324 malice #modes +nt")
+        );
+        assert!(
+            alice.recv_until("329").await[0].starts_with(":irc.example.com 329 malice #modes ")
+        );
+
+        alice.send("MODE #modes +kq secret").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 472 malice q :is unknown mode char to me")
+        );
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":malice!~malice@unimplement.ed MODE #modes +k secret")
+        );
+
+        bob.send("PRIVMSG #modes :hi").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 404 mbob #modes :Cannot send to channel")
+        );
+        bob.send("JOIN #modes").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 475 mbob #modes :Cannot join channel (+k)")
+        );
+        bob.send("JOIN #modes secret").await;
+        bob.recv_until("366").await;
+        alice.recv_until("JOIN").await;
+
+        alice.send("MODE #modes +m").await;
+        alice.recv_until("MODE").await;
+        bob.recv_until("MODE").await;
+        bob.send("PRIVMSG #modes :hi").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 404 mbob #modes :Cannot send to channel")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_member_status() {
+        let mut alice = TestClient::register("salice").await;
+        let mut bob = TestClient::register("sbob").await;
+
+        alice.send("JOIN #status").await;
+        let names = alice.recv_until("353").await;
+        assert_eq!(
+            names.last().map(String::as_str),
+            Some(":irc.example.com 353 salice = #status :@salice")
+        );
+        bob.send("JOIN #status").await;
+        bob.recv_until("366").await;
+        alice.recv_until("JOIN").await;
+
+        bob.send("MODE #status +o sbob").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 482 sbob #status :You're not channel operator")
+        );
+
+        alice.send("MODE #status +v sbob").await;
+        for client in [&mut alice, &mut bob] {
+            assert_eq!(
+                client.recv().await.as_deref(),
+                Some(":salice!~salice@unimplement.ed MODE #status +v sbob")
+            );
+        }
+
+        // voice isn't enough for @#status
+        alice.send("PRIVMSG @#status :ops only").await;
+        assert_eq!(bob.recv().await, None);
+
+        alice.send("MODE #status +o sbob").await;
+        bob.recv_until("MODE").await;
+        alice.recv_until("MODE").await;
+        alice.send("PRIVMSG @#status :ops only").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":salice!~salice@unimplement.ed PRIVMSG @#status :ops only")
+        );
+
+        alice.send("MODE #status +v salice").await;
+        alice.recv_until("MODE").await;
+
+        let mut carol = TestClient::register_with_caps("scarol", &["multi-prefix"]).await;
+        carol.send("JOIN #status").await;
+        assert_eq!(
+            carol.recv_until("353").await.last().map(String::as_str),
+            Some(":irc.example.com 353 scarol = #status :@+salice @+sbob scarol")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ban_lists() {
+        let mut alice = TestClient::register("balice").await;
+        let mut bob = TestClient::register("bbob").await;
+
+        alice.send("JOIN #bans").await;
+        alice.recv_until("366").await;
+        alice.send("MODE #bans +b bbob").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":balice!~balice@unimplement.ed MODE #bans +b bbob!*@*")
+        );
+
+        bob.send("JOIN #bans").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 474 bbob #bans :Cannot join channel (+b)")
+        );
+
+        // anyone can look at the list
+        bob.send("MODE #bans b").await;
+        let list = bob.recv_until("368").await;
+        assert!(list[0].starts_with(
+            ":irc.example.com 367 bbob #bans bbob!*@* balice!~balice@unimplement.ed "
+        ));
+        assert_eq!(
+            list[1],
+            ":irc.example.com 368 bbob #bans :End of channel ban list"
+        );
+
+        // an exception by address lets bob in, but doesn't make the ban go away
+        alice.send("MODE #bans +e *!*@127.0.0.0/8").await;
+        alice.recv_until("MODE").await;
+        bob.send("JOIN #bans").await;
+        bob.recv_until("366").await;
+        alice.recv_until("JOIN").await;
+
+        alice
+            .send("MODE #bans -e+b *!*@127.0.0.0/8 *!*@127.*")
+            .await;
+        alice.recv_until("MODE").await;
+        bob.recv_until("MODE").await;
+        bob.send("PRIVMSG #bans :hi").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 404 bbob #bans :Cannot send to channel")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_full() {
+        let mut alice = TestClient::register("falice").await;
+        alice.send("JOIN #full").await;
+        alice.recv_until("366").await;
+
+        // leave a single slot free
+        let masks = (1..MAXLIST).map(|i| format!("f{i}")).collect::<Vec<_>>();
+        for chunk in masks.chunks(12) {
+            alice
+                .send(&format!(
+                    "MODE #full +{} {}",
+                    "b".repeat(chunk.len()),
+                    chunk.join(" ")
+                ))
+                .await;
+            alice.recv_until("MODE").await;
+        }
+
+        alice.send("MODE #full +bbb x y z").await;
+        assert_eq!(
+            alice.recv_until("MODE").await,
+            [
+                ":irc.example.com 478 falice #full y :Channel list is full",
+                ":irc.example.com 478 falice #full z :Channel list is full",
+                ":falice!~falice@unimplement.ed MODE #full +b x!*@*",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_user_modes() {
+        let mut alice = TestClient::register("valice").await;
+        let mut bob = TestClient::register("vbob").await;
+
+        // everyone starts out invisible, and bob shares no channel with alice
+        bob.send("WHO valice").await;
+        assert_eq!(
+            bob.recv_until("315").await,
+            [":irc.example.com 315 vbob valice :End of WHO list"]
+        );
+
+        alice.send("MODE vbob -i").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 502 valice :Can't change mode for other users")
+        );
+        alice.send("MODE valice -iq").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 501 valice :Unknown MODE flag")
+        );
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":valice!~valice@unimplement.ed MODE valice :-i")
+        );
+        alice.send("MODE valice").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 221 valice +x")
+        );
+
+        bob.send("WHO valice").await;
+        assert_eq!(
+            bob.recv_until("315").await,
+            [
+                ":irc.example.com 352 vbob * ~valice unimplement.ed irc.example.com valice H :0 valice",
+                ":irc.example.com 315 vbob valice :End of WHO list",
+            ]
+        );
+
+        // nothing to echo if nothing changed
+        alice.send("MODE valice +i+i").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":valice!~valice@unimplement.ed MODE valice :+i")
+        );
+        alice.send("MODE valice +i").await;
+        assert_eq!(alice.recv().await, None);
+
+        bob.send("WHO valice").await;
+        assert_eq!(
+            bob.recv_until("315").await,
+            [":irc.example.com 315 vbob valice :End of WHO list"]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use async_trait::async_trait;
+
+use crate::{
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    sender::IrcResponseCodes,
+    state::NETWORK,
+    tags::Tags,
+    user::User,
+};
+
+pub struct Names;
+
+#[async_trait]
+impl IrcHandler for Names {
+    async fn handle(
+        &self,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        if !authenticated {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        }
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        // listing every channel on the network isn't worth it, LIST is there for that
+        let Some(targets) = arguments.first() else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::EndOfNames
+                    .into_irc_response(nick, "* :End of /NAMES list".into()),
+            )];
+        };
+
+        targets
+            .split(',')
+            .filter(|target| !target.is_empty())
+            .flat_map(|target| match NETWORK.get_channel(target) {
+                Some(channel) => channel.names_replies(user_state, &config.server_hostname),
+                None => vec![
+                    IrcResponseCodes::EndOfNames
+                        .into_irc_response(nick.clone(), format!("{target} :End of /NAMES list")),
+                ],
+            })
+            .map(IrcAction::SendText)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_names() {
+        let mut alice = TestClient::register("halice").await;
+        let mut bob = TestClient::register("hbob").await;
+
+        alice.send("JOIN #names").await;
+        alice.recv_until("366").await;
+
+        // everyone is +i, so outsiders see nobody
+        bob.send("NAMES #names,#nothere").await;
+        assert_eq!(
+            bob.recv_until("366").await,
+            [":irc.example.com 366 hbob #names :End of /NAMES list"]
+        );
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 366 hbob #nothere :End of /NAMES list")
+        );
+
+        bob.send("JOIN #names").await;
+        bob.recv_until("366").await;
+        bob.send("NAMES #names").await;
+        assert_eq!(
+            bob.recv_until("366").await,
+            [
+                ":irc.example.com 353 hbob = #names :@halice hbob",
+                ":irc.example.com 366 hbob #names :End of /NAMES list",
+            ]
+        );
+
+        let mut carol = TestClient::register_with_caps("hcarol", &["userhost-in-names"]).await;
+        carol.send("JOIN #names").await;
+        assert_eq!(
+            carol.recv_until("353").await.last().map(String::as_str),
+            Some(
+                ":irc.example.com 353 hcarol = #names :@halice!~halice@unimplement.ed hbob!~hbob@unimplement.ed hcarol!~hcarol@unimplement.ed"
+            )
+        );
+    }
+}
@@ -1,10 +1,21 @@
+use std::time::SystemTime;
+
 use async_trait::async_trait;
 
 use crate::{
+    casemapping::casefold,
     commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    messages::{Message, NickChangeMessage},
+    sender::IrcResponseCodes,
+    state::NETWORK,
+    tags::Tags,
     user::User,
 };
 
+/// Longest nickname we accept
+pub const NICKLEN: usize = 30;
+
 pub struct Nick;
 
 #[async_trait]
@@ -12,28 +23,137 @@ impl IrcHandler for Nick {
     async fn handle(
         &self,
         command: Vec<String>,
+        _tags: Tags,
         _authenticated: bool,
         user_state: &mut User,
-        _server_outgoing_password: String,
-        _server_incoming_passwords: Vec<String>,
-        _user_passwords: Vec<String>,
+        _config: &ServerInfo,
     ) -> Vec<IrcAction> {
-        user_state.nickname = Some({
-            if command[0].len() > 9 {
-                String::from_utf8(
-                    command[0]
-                        .clone()
-                        .chars()
-                        .map(|x| x.clone() as u8)
-                        .collect::<Vec<u8>>()[0..8]
-                        .to_vec(),
-                )
-                .unwrap()
-            } else {
-                command[0].clone()
+        let current = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        let Some(nickname) = command.first().filter(|nickname| !nickname.is_empty()) else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NoNicknameGiven
+                    .into_irc_response(current, ":No nickname given".into()),
+            )];
+        };
+
+        if !is_valid_nickname(nickname) {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::ErroneousNickname
+                    .into_irc_response(current, format!("{nickname} :Erroneous nickname")),
+            )];
+        }
+
+        if user_state.nickname.as_ref() == Some(nickname) {
+            return vec![IrcAction::DoNothing];
+        }
+
+        let nick_in_use = || {
+            vec![IrcAction::SendText(
+                IrcResponseCodes::NicknameInUse.into_irc_response(
+                    current.clone(),
+                    format!("{nickname} :Nickname is already in use"),
+                ),
+            )]
+        };
+
+        // before registration the nick is only claimed once the user gets added to the network
+        if !user_state.identified {
+            if NETWORK.find_nick(nickname).is_some() {
+                return nick_in_use();
             }
-        });
 
-        vec![IrcAction::DoNothing]
+            user_state.nickname = Some(nickname.clone());
+
+            return vec![IrcAction::DoNothing];
+        }
+
+        let user = user_state.unwrap_all();
+        // only changing the case of your own nick keeps the timestamp
+        let timestamp = if casefold(nickname) == casefold(&user.nickname) {
+            user.timestamp
+        } else {
+            SystemTime::now()
+        };
+
+        if NETWORK.change_nick(&user, nickname, timestamp).is_err() {
+            return nick_in_use();
+        }
+
+        user_state.nickname = Some(nickname.clone());
+        user_state.timestamp = Some(timestamp);
+
+        vec![IrcAction::SendMessage(Box::new(
+            Message::NickChangeMessage(NickChangeMessage {
+                user,
+                nickname: nickname.clone(),
+                timestamp,
+            }),
+        ))]
+    }
+}
+
+/// Whether a nickname follows the grammar of RFC 2812: a letter or special character, then
+/// letters, digits, special characters and dashes.
+pub fn is_valid_nickname(nickname: &str) -> bool {
+    let is_special = |x: char| "[]\\`_^{|}".contains(x);
+    let mut chars = nickname.chars();
+
+    nickname.len() <= NICKLEN
+        && chars
+            .next()
+            .is_some_and(|x| x.is_ascii_alphabetic() || is_special(x))
+        && chars.all(|x| x.is_ascii_alphanumeric() || is_special(x) || x == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{commands::nick::is_valid_nickname, test_client::TestClient};
+
+    #[test]
+    fn test_nickname_grammar() {
+        assert!(is_valid_nickname("alice"));
+        assert!(is_valid_nickname("[away]-9"));
+        assert!(is_valid_nickname("`_^{|}"));
+
+        assert!(!is_valid_nickname(""));
+        assert!(!is_valid_nickname("9lives"));
+        assert!(!is_valid_nickname("-dash"));
+        assert!(!is_valid_nickname("with space"));
+        assert!(!is_valid_nickname("nick!user"));
+        assert!(!is_valid_nickname("#channel"));
+        assert!(!is_valid_nickname(&"a".repeat(31)));
+    }
+
+    #[tokio::test]
+    async fn test_nick_change() {
+        let (mut alice, mut bob) = TestClient::pair_in_channel("nalice", "nbob", "#nick").await;
+
+        bob.send("NICK").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 431 nbob :No nickname given")
+        );
+        bob.send("NICK 9bob").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 432 nbob 9bob :Erroneous nickname")
+        );
+        bob.send("NICK NALICE").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 433 nbob NALICE :Nickname is already in use")
+        );
+
+        bob.send("NICK nrobert").await;
+        for client in [&mut alice, &mut bob] {
+            assert_eq!(
+                client.recv().await.as_deref(),
+                Some(":nbob!~nbob@unimplement.ed NICK :nrobert")
+            );
+        }
+
+        // the old nick is free again
+        TestClient::register("nbob").await;
     }
 }
@@ -0,0 +1,123 @@
+use async_trait::async_trait;
+
+use crate::{
+    accounts::constant_time_eq,
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    sender::{IrcResponse, IrcResponseCodes},
+    tags::Tags,
+    user::User,
+    usermodes::Usermode,
+};
+
+pub struct Oper;
+
+#[async_trait]
+impl IrcHandler for Oper {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        if !authenticated {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        }
+
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        let (Some(name), password) = (command.first(), command.get(1)) else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NeedMoreParams
+                    .into_irc_response(nick, "OPER :Not enough parameters".into()),
+            )];
+        };
+
+        let Some(oper) = config.opers.iter().find(|oper| &oper.name == name) else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NoOperHost
+                    .into_irc_response(nick, ":No O-lines for your host".into()),
+            )];
+        };
+
+        // every check configured in the oper block has to pass, and there has to be at least one
+        let password_matches = match (&oper.password, password) {
+            (Some(expected), Some(password)) => {
+                constant_time_eq(expected.as_bytes(), password.as_bytes())
+            }
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let certfp_matches = match (&oper.certfp, &user_state.certfp) {
+            (Some(expected), Some(certfp)) => expected.eq_ignore_ascii_case(certfp),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+
+        if (oper.password.is_none() && oper.certfp.is_none())
+            || !password_matches
+            || !certfp_matches
+        {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::PasswdMismatch
+                    .into_irc_response(nick, ":Password incorrect".into()),
+            )];
+        }
+
+        user_state.usermodes.add(Usermode::Operator);
+
+        vec![
+            IrcAction::SendText(
+                IrcResponseCodes::YoureOper
+                    .into_irc_response(nick.clone(), ":You are now an IRC operator".into()),
+            ),
+            IrcAction::SendText(IrcResponse {
+                tags: Tags::new(),
+                sender: Some(user_state.unwrap_all().hostmask()),
+                command: "MODE".into(),
+                arguments: vec![nick],
+                receiver: None,
+                message: ":+o".into(),
+            }),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_oper() {
+        let mut alice = TestClient::register("oalice").await;
+
+        alice.send("OPER nobody hunter2").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 491 oalice :No O-lines for your host")
+        );
+        alice.send("OPER admin wrong").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 464 oalice :Password incorrect")
+        );
+
+        alice.send("OPER admin hunter2").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 381 oalice :You are now an IRC operator")
+        );
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":oalice!~oalice@unimplement.ed MODE oalice :+o")
+        );
+
+        alice.send("USERIP oalice").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 340 oalice :oalice*=+~oalice@127.0.0.1")
+        );
+    }
+}
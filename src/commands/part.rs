@@ -0,0 +1,101 @@
+use async_trait::async_trait;
+
+use crate::{
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    messages::{Message, PartMessage},
+    sender::IrcResponseCodes,
+    state::{NETWORK, PartError},
+    tags::Tags,
+    user::User,
+};
+
+pub struct Part;
+
+#[async_trait]
+impl IrcHandler for Part {
+    async fn handle(
+        &self,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        let (true, Some(user_id)) = (authenticated, &user_state.user_id) else {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        };
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        let Some(channels) = arguments.first() else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NeedMoreParams
+                    .into_irc_response(nick, "PART :Not enough parameters".into()),
+            )];
+        };
+        let reason = arguments.get(1).filter(|reason| !reason.is_empty());
+
+        channels
+            .split(',')
+            .map(|name| match NETWORK.part_channel(name, user_id) {
+                Ok(channel) => {
+                    IrcAction::SendMessage(Box::new(Message::PartMessage(PartMessage {
+                        sender: user_state.clone().unwrap_all(),
+                        channel,
+                        reason: reason.cloned(),
+                    })))
+                }
+                Err(PartError::NoSuchChannel) => IrcAction::SendText(
+                    IrcResponseCodes::NoSuchChannel
+                        .into_irc_response(nick.clone(), format!("{name} :No such channel")),
+                ),
+                Err(PartError::NotOnChannel) => {
+                    IrcAction::SendText(IrcResponseCodes::NotOnChannel.into_irc_response(
+                        nick.clone(),
+                        format!("{name} :You're not on that channel"),
+                    ))
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_part() {
+        let (mut alice, mut bob) = TestClient::pair_in_channel("palice", "pbob", "#part").await;
+
+        // numerics are written right away, the PART takes the detour through the queue
+        bob.send("PART #part,#nowhere :bye").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 403 pbob #nowhere :No such channel")
+        );
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":pbob!~pbob@unimplement.ed PART #part :bye")
+        );
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":pbob!~pbob@unimplement.ed PART #part :bye")
+        );
+
+        bob.send("PART #part").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 442 pbob #part :You're not on that channel")
+        );
+
+        // the channel is gone once the last member left
+        alice.send("PART #part").await;
+        alice.recv_until("PART").await;
+        alice.send("PART #part").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 403 palice #part :No such channel")
+        );
+    }
+}
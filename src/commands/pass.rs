@@ -2,6 +2,8 @@ use async_trait::async_trait;
 
 use crate::{
     commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    tags::Tags,
     user::User,
 };
 
@@ -12,20 +14,20 @@ impl IrcHandler for Pass {
     async fn handle(
         &self,
         command: Vec<String>,
+        _tags: Tags,
         _authenticated: bool,
         _user_state: &mut User,
-        server_outgoing_password: String,
-        server_incoming_passwords: Vec<String>,
-        _user_passwords: Vec<String>,
+        config: &ServerInfo,
     ) -> Vec<IrcAction> {
-        if server_incoming_passwords.contains(&command[0]) {
+        if config.server_incoming_passwords.contains(&command[0]) {
             vec![
                 IrcAction::SendText(crate::sender::IrcResponse {
+                    tags: Tags::new(),
                     sender: None,
                     command: "PASS".to_owned(),
                     receiver: None,
                     arguments: Vec::new(),
-                    message: server_outgoing_password.clone(),
+                    message: config.server_outgoing_password.clone(),
                 }),
                 IrcAction::UpgradeToServerConn,
             ]
@@ -2,7 +2,9 @@ use async_trait::async_trait;
 
 use crate::{
     commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
     sender::IrcResponse,
+    tags::Tags,
     user::User,
 };
 
@@ -13,14 +15,14 @@ impl IrcHandler for Ping {
     async fn handle(
         &self,
         command: Vec<String>,
+        _tags: Tags,
         authenticated: bool,
         user_state: &mut User,
-        _server_outgoing_password: String,
-        _server_incoming_passwords: Vec<String>,
-        _user_passwords: Vec<String>,
+        _config: &ServerInfo,
     ) -> Vec<IrcAction> {
         if authenticated {
             vec![IrcAction::SendText(IrcResponse {
+                tags: Tags::new(),
                 sender: None,
                 command: "PONG".into(),
                 arguments: Vec::new(),
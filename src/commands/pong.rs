@@ -0,0 +1,25 @@
+use async_trait::async_trait;
+
+use crate::{
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    tags::Tags,
+    user::User,
+};
+
+/// Answers to our keepalive pings. Any line resets the ping timer, so there's nothing else to do.
+pub struct Pong;
+
+#[async_trait]
+impl IrcHandler for Pong {
+    async fn handle(
+        &self,
+        _command: Vec<String>,
+        _tags: Tags,
+        _authenticated: bool,
+        _user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        vec![IrcAction::DoNothing]
+    }
+}
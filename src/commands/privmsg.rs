@@ -1,8 +1,13 @@
 use async_trait::async_trait;
 
 use crate::{
+    chanmodes::split_status_prefix,
     commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
     messages::{Message, PrivMessage, Receiver},
+    sender::IrcResponseCodes,
+    state::NETWORK,
+    tags::{Tags, relayed_message_tags},
     user::User,
 };
 
@@ -13,28 +18,133 @@ impl IrcHandler for PrivMsg {
     async fn handle(
         &self,
         command: Vec<String>,
+        tags: Tags,
         authenticated: bool,
         user_state: &mut User,
-        _server_outgoing_password: String,
-        _server_incoming_passwords: Vec<String>,
-        _user_passwords: Vec<String>,
+        _config: &ServerInfo,
     ) -> Vec<IrcAction> {
         if !authenticated {
             return vec![IrcAction::ErrorAuthenticateFirst];
         }
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
 
-        let receiver = if command[0].clone().starts_with("#") {
-            Receiver::ChannelName(command[0].clone())
+        let (target, text) = match command.as_slice() {
+            [] => {
+                return vec![IrcAction::SendText(
+                    IrcResponseCodes::NoRecipient
+                        .into_irc_response(nick, ":No recipient given (PRIVMSG)".into()),
+                )];
+            }
+            [target, text, ..] if !text.is_empty() => (target, text),
+            _ => {
+                return vec![IrcAction::SendText(
+                    IrcResponseCodes::NoTextToSend
+                        .into_irc_response(nick, ":No text to send".into()),
+                )];
+            }
+        };
+
+        let receiver = if split_status_prefix(target).1.starts_with('#') {
+            Receiver::ChannelName(target.clone())
         } else {
-            Receiver::Username(command[0].clone())
+            Receiver::Username(target.clone())
         };
 
+        if let Some(error) = check_target(user_state, &receiver) {
+            return vec![error];
+        }
+        if let Some(user_id) = &user_state.user_id {
+            NETWORK.touch(user_id);
+        }
+
         let message = PrivMessage {
             sender: user_state.clone().unwrap_all(),
             receiver,
-            text: command[1].clone(),
+            text: text.clone(),
+            tags: relayed_message_tags(&tags),
         };
 
-        vec![IrcAction::SendMessage(Message::PrivMessage(message))]
+        let mut actions = vec![IrcAction::SendMessage(Box::new(Message::PrivMessage(
+            message,
+        )))];
+        if let Some(reply) = away_reply(user_state, target) {
+            actions.push(reply);
+        }
+
+        actions
+    }
+}
+
+/// RPL_AWAY, if the user being messaged is away
+fn away_reply(user_state: &User, target: &str) -> Option<IrcAction> {
+    let user = NETWORK.get_user(&NETWORK.find_nick(target)?)?;
+    let away = user.away?;
+
+    Some(IrcAction::SendText(
+        IrcResponseCodes::Away.into_irc_response(
+            user_state.nickname.clone().unwrap_or("*".to_owned()),
+            format!("{} :{away}", user.nickname),
+        ),
+    ))
+}
+
+/// The error to send back if the user they're messaging doesn't exist, or they aren't allowed to
+/// send to the channel they're messaging.
+pub fn check_target(user_state: &User, receiver: &Receiver) -> Option<IrcAction> {
+    let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+    let target = match receiver {
+        Receiver::ChannelName(target) => target,
+        Receiver::Username(target) if NETWORK.find_nick(target).is_none() => {
+            return Some(IrcAction::SendText(
+                IrcResponseCodes::NoSuchNick
+                    .into_irc_response(nick, format!("{target} :No such nick/channel")),
+            ));
+        }
+        _ => return None,
+    };
+    let (_, name) = split_status_prefix(target);
+
+    match NETWORK.get_channel(name) {
+        None => Some(IrcAction::SendText(
+            IrcResponseCodes::NoSuchChannel
+                .into_irc_response(nick, format!("{name} :No such channel")),
+        )),
+        Some(channel) if !channel.can_send(&user_state.unwrap_all()) => Some(IrcAction::SendText(
+            IrcResponseCodes::CannotSendToChan
+                .into_irc_response(nick, format!("{name} :Cannot send to channel")),
+        )),
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_privmsg_errors() {
+        let mut alice = TestClient::register("galice").await;
+
+        alice.send("PRIVMSG gnobody :hello?").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 401 galice gnobody :No such nick/channel")
+        );
+
+        alice.send("PRIVMSG").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 411 galice :No recipient given (PRIVMSG)")
+        );
+        alice.send("PRIVMSG galice").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 412 galice :No text to send")
+        );
+        alice.send("PRIVMSG galice :").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 412 galice :No text to send")
+        );
     }
 }
@@ -0,0 +1,59 @@
+use async_trait::async_trait;
+
+use crate::{
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    tags::Tags,
+    user::User,
+};
+
+pub struct Quit;
+
+#[async_trait]
+impl IrcHandler for Quit {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        _authenticated: bool,
+        _user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        // prefixed, so users can't pass off their quit as e.g. a ping timeout
+        let reason = match command.first() {
+            Some(reason) if !reason.is_empty() => format!("Quit: {reason}"),
+            _ => "Client Quit".to_owned(),
+        };
+
+        vec![IrcAction::Quit(reason)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_quit() {
+        let mut alice = TestClient::register("xalice").await;
+        let mut bob = TestClient::register("xbob").await;
+
+        for channel in ["#quit1", "#quit2"] {
+            alice.join_with(&mut bob, channel).await;
+        }
+
+        bob.send("QUIT :see you").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some("ERROR :Closing Link: 127.0.0.1 (Quit: see you)")
+        );
+        assert_eq!(bob.recv().await, None);
+
+        // only one QUIT, even though they shared two channels
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":xbob!~xbob@unimplement.ed QUIT :Quit: see you")
+        );
+        assert_eq!(alice.recv().await, None);
+    }
+}
@@ -0,0 +1,53 @@
+use async_trait::async_trait;
+
+use crate::{
+    chanmodes::split_status_prefix,
+    commands::{IrcAction, IrcHandler, privmsg::check_target},
+    config::ServerInfo,
+    messages::{Message, PrivMessage, Receiver},
+    tags::{Tags, relayed_message_tags},
+    user::User,
+};
+
+pub struct TagMsg;
+
+#[async_trait]
+impl IrcHandler for TagMsg {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        if !authenticated {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        }
+
+        if command.is_empty() {
+            return vec![IrcAction::DoNothing];
+        }
+
+        let receiver = if split_status_prefix(&command[0]).1.starts_with('#') {
+            Receiver::ChannelName(command[0].clone())
+        } else {
+            Receiver::Username(command[0].clone())
+        };
+
+        if let Some(error) = check_target(user_state, &receiver) {
+            return vec![error];
+        }
+
+        let message = PrivMessage {
+            sender: user_state.clone().unwrap_all(),
+            receiver,
+            text: String::new(),
+            tags: relayed_message_tags(&tags),
+        };
+
+        vec![IrcAction::SendMessage(Box::new(Message::TagMessage(
+            message,
+        )))]
+    }
+}
@@ -0,0 +1,158 @@
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+
+use crate::{
+    chanmodes::{Chanmode, MemberStatus},
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    messages::{Message, TopicMessage},
+    sender::IrcResponseCodes,
+    state::NETWORK,
+    tags::Tags,
+    user::User,
+};
+
+pub struct Topic;
+
+#[async_trait]
+impl IrcHandler for Topic {
+    async fn handle(
+        &self,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        let (true, Some(user_id)) = (authenticated, &user_state.user_id) else {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        };
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        let Some(name) = arguments.first() else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NeedMoreParams
+                    .into_irc_response(nick, "TOPIC :Not enough parameters".into()),
+            )];
+        };
+        let Some(channel) = NETWORK.get_channel(name) else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NoSuchChannel
+                    .into_irc_response(nick, format!("{name} :No such channel")),
+            )];
+        };
+        let is_member = channel.members.contains_key(user_id);
+
+        let Some(text) = arguments.get(1) else {
+            // secret channels don't show their topic to outsiders
+            if !is_member && channel.modes.has(Chanmode::Secret) {
+                return vec![IrcAction::SendText(
+                    IrcResponseCodes::NotOnChannel.into_irc_response(
+                        nick,
+                        format!("{} :You're not on that channel", channel.name),
+                    ),
+                )];
+            }
+
+            return channel
+                .topic_replies(&nick)
+                .into_iter()
+                .map(IrcAction::SendText)
+                .collect();
+        };
+
+        if !is_member {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NotOnChannel.into_irc_response(
+                    nick,
+                    format!("{} :You're not on that channel", channel.name),
+                ),
+            )];
+        }
+
+        if channel.modes.has(Chanmode::TopicLock)
+            && channel
+                .status(user_id)
+                .is_none_or(|status| status > MemberStatus::HalfOp)
+        {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::ChanOPrivsNeeded.into_irc_response(
+                    nick,
+                    format!("{} :You're not channel operator", channel.name),
+                ),
+            )];
+        }
+
+        let setter = user_state.unwrap_all().hostmask();
+        let changed = NETWORK.update_channel(name, |channel| {
+            channel
+                .set_topic(text, &setter, SystemTime::now())
+                .then(|| channel.clone())
+        });
+
+        match changed.flatten() {
+            Some(channel) => vec![IrcAction::SendMessage(Box::new(Message::TopicMessage(
+                TopicMessage {
+                    setter,
+                    user_id: Some(user_id.clone()),
+                    channel,
+                },
+            )))],
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_topic() {
+        let mut alice = TestClient::register("talice").await;
+        let mut bob = TestClient::register("tbob").await;
+
+        alice.send("JOIN #topic").await;
+        alice.recv_until("366").await;
+        alice.send("TOPIC #topic").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":irc.example.com 331 talice #topic :No topic is set")
+        );
+
+        alice.send("TOPIC #topic :hello world").await;
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some(":talice!~talice@unimplement.ed TOPIC #topic :hello world")
+        );
+
+        bob.send("JOIN #topic").await;
+        let joined = bob.recv_until("333").await;
+        assert_eq!(
+            joined[joined.len() - 2],
+            ":irc.example.com 332 tbob #topic :hello world"
+        );
+        assert!(
+            joined[joined.len() - 1]
+                .starts_with(":irc.example.com 333 tbob #topic talice!~talice@unimplement.ed ")
+        );
+        bob.recv_until("366").await;
+        alice.recv_until("JOIN").await;
+
+        // +t is set on new channels
+        bob.send("TOPIC #topic :mine now").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 482 tbob #topic :You're not channel operator")
+        );
+
+        alice.send("TOPIC #topic :").await;
+        for client in [&mut alice, &mut bob] {
+            assert_eq!(
+                client.recv().await.as_deref(),
+                Some(":talice!~talice@unimplement.ed TOPIC #topic :")
+            );
+        }
+    }
+}
@@ -2,6 +2,8 @@ use async_trait::async_trait;
 
 use crate::{
     commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    tags::Tags,
     user::User as UserState,
 };
 
@@ -12,11 +14,10 @@ impl IrcHandler for User {
     async fn handle(
         &self,
         command: Vec<String>,
+        _tags: Tags,
         _authenticated: bool,
         user_state: &mut UserState,
-        _server_outgoing_password: String,
-        _server_incoming_passwords: Vec<String>,
-        _user_passwords: Vec<String>,
+        _config: &ServerInfo,
     ) -> Vec<IrcAction> {
         if command.len() < 4 {
             return vec![IrcAction::DoNothing]; // XXX: return an error
@@ -29,7 +30,7 @@ impl IrcHandler for User {
                     command[0]
                         .clone()
                         .chars()
-                        .map(|x| x.clone() as u8)
+                        .map(|x| x as u8)
                         .collect::<Vec<u8>>()[0..8]
                         .to_vec(),
                 )
@@ -0,0 +1,142 @@
+use async_trait::async_trait;
+
+use crate::{
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    sender::IrcResponseCodes,
+    state::NETWORK,
+    tags::Tags,
+    user::{User, UserUnwrapped},
+    usermodes::Usermode,
+};
+
+/// How many nicknames one USERHOST or USERIP looks up
+const MAX_TARGETS: usize = 5;
+
+pub struct Userhost;
+
+/// USERHOST, but with IP addresses, so only for operators.
+pub struct Userip;
+
+#[async_trait]
+impl IrcHandler for Userhost {
+    async fn handle(
+        &self,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        if !authenticated {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        }
+
+        lookup(
+            user_state,
+            &arguments,
+            "USERHOST",
+            IrcResponseCodes::UserHost,
+            |user| user.host(),
+        )
+    }
+}
+
+#[async_trait]
+impl IrcHandler for Userip {
+    async fn handle(
+        &self,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        _config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        if !authenticated {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        }
+        if !user_state.usermodes.has(&Usermode::Operator) {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NoPrivileges.into_irc_response(
+                    user_state.nickname.clone().unwrap_or("*".to_owned()),
+                    ":Permission Denied- You're not an IRC operator".into(),
+                ),
+            )];
+        }
+
+        lookup(
+            user_state,
+            &arguments,
+            "USERIP",
+            IrcResponseCodes::UserIp,
+            |user| user.ip.to_string(),
+        )
+    }
+}
+
+/// `nick[*]=+~user@host` for each nickname that's in use, with `*` for operators and `-` instead
+/// of `+` for users who are away
+fn lookup(
+    user_state: &User,
+    arguments: &[String],
+    command: &str,
+    code: IrcResponseCodes,
+    host: impl Fn(&UserUnwrapped) -> String,
+) -> Vec<IrcAction> {
+    let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+    if arguments.is_empty() {
+        return vec![IrcAction::SendText(
+            IrcResponseCodes::NeedMoreParams
+                .into_irc_response(nick, format!("{command} :Not enough parameters")),
+        )];
+    }
+
+    let replies = arguments
+        .iter()
+        .flat_map(|x| x.split_whitespace())
+        .take(MAX_TARGETS)
+        .filter_map(|target| NETWORK.get_user(&NETWORK.find_nick(target)?))
+        .map(|user| {
+            let operator = if user.usermodes.has(&Usermode::Operator) {
+                "*"
+            } else {
+                ""
+            };
+            let here = if user.away.is_some() { '-' } else { '+' };
+
+            format!(
+                "{}{operator}={here}~{}@{}",
+                user.nickname,
+                user.username,
+                host(&user)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    vec![IrcAction::SendText(
+        code.into_irc_response(nick, format!(":{}", replies.join(" "))),
+    )]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_userhost() {
+        let _alice = TestClient::register("ualice").await;
+        let mut bob = TestClient::register("ubob").await;
+
+        bob.send("USERHOST ualice nobody").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 302 ubob :ualice=+~ualice@unimplement.ed")
+        );
+        bob.send("USERIP ualice").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com 481 ubob :Permission Denied- You're not an IRC operator")
+        );
+    }
+}
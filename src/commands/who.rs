@@ -1,23 +1,302 @@
+use std::collections::BTreeSet;
+
 use async_trait::async_trait;
 
 use crate::{
+    chanmodes::Chanmode,
+    channels::Channel,
     commands::{IrcAction, IrcHandler},
-    user::User,
+    config::ServerInfo,
+    masks::{glob_match, mask_matches, normalize_mask},
+    sender::IrcResponseCodes,
+    state::NETWORK,
+    tags::Tags,
+    user::{User, UserUnwrapped},
+    usermodes::Usermode,
 };
 
+/// WHOX fields, in the order they get sent in
+const WHOX_FIELDS: &str = "tcuihsnfdlaor";
+
 pub struct Who;
 
+/// What a WHOX request asked for: the fields, and the token to send back.
+struct Whox {
+    fields: BTreeSet<char>,
+    token: Option<String>,
+}
+
 #[async_trait]
 impl IrcHandler for Who {
     async fn handle(
         &self,
-        _arguments: Vec<String>,
-        _authenticated: bool,
-        _user_state: &mut User,
-        _server_outgoing_password: String,
-        _server_incoming_passwords: Vec<String>,
-        _user_passwords: Vec<String>,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        config: &ServerInfo,
     ) -> Vec<super::IrcAction> {
-        vec![IrcAction::DoNothing] // TODO
+        if !authenticated {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        }
+        let requester = user_state.unwrap_all();
+        let mask = arguments.first().cloned().unwrap_or("*".to_owned());
+
+        // `o%fields,token`: only operators, and the WHOX fields wanted
+        let options = arguments.get(1).cloned().unwrap_or_default();
+        let (flags, whox) = match options.split_once('%') {
+            Some((flags, whox)) => {
+                let (fields, token) = whox.split_once(',').unwrap_or((whox, ""));
+
+                (
+                    flags,
+                    Some(Whox {
+                        fields: fields.chars().collect(),
+                        // the token is a number of up to three digits
+                        token: (!token.is_empty()
+                            && token.len() <= 3
+                            && token.chars().all(|x| x.is_ascii_digit()))
+                        .then(|| token.to_owned()),
+                    }),
+                )
+            }
+            None => (options.as_str(), None),
+        };
+        let operators_only = flags.contains('o');
+
+        let (channel, users) = if mask.starts_with('#') {
+            match NETWORK.get_channel(&mask) {
+                Some(channel) => {
+                    let users = channel_users(&requester, &channel);
+
+                    (Some(channel), users)
+                }
+                None => (None, Vec::new()),
+            }
+        } else {
+            (
+                None,
+                matching_users(&requester, &mask, &config.server_hostname),
+            )
+        };
+
+        let mut actions = users
+            .into_iter()
+            .filter(|user| !operators_only || user.usermodes.has(&Usermode::Operator))
+            .map(|user| {
+                let reply = reply(
+                    &requester,
+                    &user,
+                    channel.as_ref(),
+                    user_state.has_capability("multi-prefix"),
+                    &config.server_hostname,
+                );
+
+                IrcAction::SendText(match &whox {
+                    Some(whox) => IrcResponseCodes::WhoSpcRpl
+                        .into_irc_response(requester.nickname.clone(), whox.render(&reply)),
+                    None => IrcResponseCodes::WhoReply.into_irc_response(
+                        requester.nickname.clone(),
+                        format!(
+                            "{} {} {} {} {} {} :{} {}",
+                            reply.channel,
+                            reply.username,
+                            reply.user.host(),
+                            reply.server,
+                            reply.user.nickname,
+                            reply.flags,
+                            reply.user.hopcount,
+                            reply.user.realname
+                        ),
+                    ),
+                })
+            })
+            .collect::<Vec<IrcAction>>();
+
+        actions.push(IrcAction::SendText(
+            IrcResponseCodes::EndOfWho
+                .into_irc_response(requester.nickname, format!("{mask} :End of WHO list")),
+        ));
+
+        actions
+    }
+}
+
+/// Everything a WHO reply can say about one user
+struct Reply {
+    user: UserUnwrapped,
+    username: String,
+    channel: String,
+    server: String,
+    flags: String,
+    /// the IP address, if the requester may see it
+    ip: String,
+}
+
+impl Whox {
+    fn render(&self, reply: &Reply) -> String {
+        let mut fields = Vec::new();
+
+        for field in WHOX_FIELDS.chars().filter(|x| self.fields.contains(x)) {
+            fields.push(match field {
+                't' => match &self.token {
+                    Some(token) => token.clone(),
+                    None => continue,
+                },
+                'c' => reply.channel.clone(),
+                'u' => reply.username.clone(),
+                'i' => reply.ip.clone(),
+                'h' => reply.user.host(),
+                's' => reply.server.clone(),
+                'n' => reply.user.nickname.clone(),
+                'f' => reply.flags.clone(),
+                'd' => reply.user.hopcount.to_string(),
+                // TODO: track idle time
+                'l' => "0".to_owned(),
+                'a' => reply.user.account.clone().unwrap_or("0".to_owned()),
+                'o' => "n/a".to_owned(),
+                _ => format!(":{}", reply.user.realname),
+            });
+        }
+
+        fields.join(" ")
+    }
+}
+
+fn reply(
+    requester: &UserUnwrapped,
+    user: &UserUnwrapped,
+    channel: Option<&Channel>,
+    multi_prefix: bool,
+    hostname: &str,
+) -> Reply {
+    let mut flags = String::from(if user.away.is_some() { "G" } else { "H" });
+    if user.usermodes.has(&Usermode::Operator) {
+        flags.push('*');
+    }
+    if let Some(statuses) = channel.and_then(|channel| channel.members.get(&user.user_id)) {
+        flags.extend(
+            statuses
+                .iter()
+                .take(if multi_prefix { statuses.len() } else { 1 })
+                .map(|status| status.prefix()),
+        );
+    }
+
+    let ip = if requester.user_id == user.user_id || requester.usermodes.has(&Usermode::Operator) {
+        user.ip.to_string()
+    } else {
+        "255.255.255.255".to_owned()
+    };
+
+    Reply {
+        user: user.clone(),
+        // without ident lookups, as in the hostmask
+        username: format!("~{}", user.username),
+        channel: channel.map(|x| x.name.clone()).unwrap_or("*".to_owned()),
+        server: NETWORK.server_name(&user.user_id, hostname),
+        flags,
+        ip,
+    }
+}
+
+/// Members of a channel the requester may see. Outsiders only see those who aren't invisible, and
+/// nobody in a secret or private channel.
+fn channel_users(requester: &UserUnwrapped, channel: &Channel) -> Vec<UserUnwrapped> {
+    let is_member = channel.members.contains_key(&requester.user_id);
+    if !is_member && (channel.modes.has(Chanmode::Secret) || channel.modes.has(Chanmode::Private)) {
+        return Vec::new();
+    }
+
+    channel
+        .members
+        .keys()
+        .filter_map(|member| NETWORK.get_user(member))
+        .filter(|user| is_member || !user.usermodes.has(&Usermode::Invisible))
+        .collect()
+}
+
+/// Users matching a nickname, `nick!user@host` mask or any of the other fields WHO looks at,
+/// leaving out invisible users the requester doesn't share a channel with.
+fn matching_users(requester: &UserUnwrapped, mask: &str, hostname: &str) -> Vec<UserUnwrapped> {
+    let shared = NETWORK
+        .user_channels(&requester.user_id)
+        .iter()
+        .flat_map(|channel| NETWORK.channel_members(channel))
+        .collect::<BTreeSet<_>>();
+    let match_all = mask == "0" || mask == "*";
+
+    NETWORK
+        .users()
+        .into_iter()
+        .filter(|user| {
+            user.user_id == requester.user_id
+                || !user.usermodes.has(&Usermode::Invisible)
+                || shared.contains(&user.user_id)
+        })
+        .filter(|user| {
+            if match_all {
+                return true;
+            }
+            if mask.contains(['!', '@']) {
+                return normalize_mask(mask).is_some_and(|mask| mask_matches(&mask, user));
+            }
+
+            [
+                user.nickname.clone(),
+                user.username.clone(),
+                user.host(),
+                user.realname.clone(),
+                NETWORK.server_name(&user.user_id, hostname),
+            ]
+            .iter()
+            .any(|field| glob_match(mask, field))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_who() {
+        let mut alice = TestClient::register("walice").await;
+        let mut bob = TestClient::register("wbob").await;
+        let mut carol = TestClient::register("wcarol").await;
+
+        alice.send("JOIN #who").await;
+        alice.recv_until("366").await;
+        bob.send("JOIN #who").await;
+        bob.recv_until("366").await;
+
+        bob.send("WHO #who").await;
+        assert_eq!(
+            bob.recv_until("315").await,
+            vec![
+                ":irc.example.com 352 wbob #who ~walice unimplement.ed irc.example.com walice H@ :0 walice",
+                ":irc.example.com 352 wbob #who ~wbob unimplement.ed irc.example.com wbob H :0 wbob",
+                ":irc.example.com 315 wbob #who :End of WHO list",
+            ]
+        );
+
+        bob.send("WHO walice %tnaf,42").await;
+        assert_eq!(
+            bob.recv_until("315").await,
+            vec![
+                ":irc.example.com 354 wbob 42 walice H 0",
+                ":irc.example.com 315 wbob walice :End of WHO list",
+            ]
+        );
+
+        // everyone is +i, and carol shares no channel with them
+        carol.send("WHO w*").await;
+        assert_eq!(
+            carol.recv_until("315").await,
+            vec![
+                ":irc.example.com 352 wcarol * ~wcarol unimplement.ed irc.example.com wcarol H :0 wcarol",
+                ":irc.example.com 315 wcarol w* :End of WHO list",
+            ]
+        );
     }
 }
@@ -0,0 +1,250 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+
+use crate::{
+    chanmodes::Chanmode,
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    messages::{Message, WhoisRequest},
+    sender::{IrcResponse, IrcResponseCodes},
+    state::NETWORK,
+    tags::Tags,
+    user::{User, UserUnwrapped},
+    usermodes::Usermode,
+};
+
+pub struct Whois;
+
+#[async_trait]
+impl IrcHandler for Whois {
+    async fn handle(
+        &self,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        if !authenticated {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        }
+        let requester = user_state.unwrap_all();
+
+        // `WHOIS server nick` asks the server the user is on, which knows their idle time
+        let (remote, target) = match arguments.as_slice() {
+            [target] => (false, target),
+            [_, target, ..] => (true, target),
+            [] => {
+                return vec![IrcAction::SendText(
+                    IrcResponseCodes::NoNicknameGiven
+                        .into_irc_response(requester.nickname, ":No nickname given".into()),
+                )];
+            }
+        };
+        // only the first of several nicknames gets looked up
+        let target = target.split(',').next().unwrap_or_default();
+
+        let Some(target_user) = NETWORK
+            .find_nick(target)
+            .and_then(|target_id| NETWORK.get_user(&target_id))
+        else {
+            return vec![
+                IrcAction::SendText(IrcResponseCodes::NoSuchNick.into_irc_response(
+                    requester.nickname.clone(),
+                    format!("{target} :No such nick/channel"),
+                )),
+                IrcAction::SendText(IrcResponseCodes::EndOfWhois.into_irc_response(
+                    requester.nickname,
+                    format!("{target} :End of /WHOIS list"),
+                )),
+            ];
+        };
+
+        if remote && !NETWORK.is_local(&target_user.user_id) {
+            return vec![IrcAction::SendMessage(Box::new(Message::WhoisRequest(
+                WhoisRequest {
+                    sender: requester,
+                    target: target_user,
+                },
+            )))];
+        }
+
+        whois_replies(
+            &requester,
+            &target_user,
+            &requester.nickname,
+            &config.server_hostname,
+        )
+        .into_iter()
+        .map(IrcAction::SendText)
+        .collect()
+    }
+}
+
+/// Everything WHOIS tells `requester` about `target`, addressed to `recipient` (the requester's
+/// nickname, or their UID when answering another server).
+pub fn whois_replies(
+    requester: &UserUnwrapped,
+    target: &UserUnwrapped,
+    recipient: &str,
+    hostname: &str,
+) -> Vec<IrcResponse> {
+    let nick = &target.nickname;
+    let reply = |code: IrcResponseCodes, text: String| {
+        code.into_irc_response(recipient.to_owned(), format!("{nick} {text}"))
+    };
+    let is_self = requester.user_id == target.user_id;
+    let is_oper = requester.usermodes.has(&Usermode::Operator);
+
+    let mut replies = vec![reply(
+        IrcResponseCodes::WhoisUser,
+        format!(
+            "~{} {} * :{}",
+            target.username,
+            target.host(),
+            target.realname
+        ),
+    )];
+
+    // secret and private channels are only shown to those in them too
+    let channels = NETWORK
+        .user_channels(&target.user_id)
+        .iter()
+        .filter_map(|name| NETWORK.get_channel(name))
+        .filter(|channel| {
+            is_self
+                || !(channel.modes.has(Chanmode::Secret) || channel.modes.has(Chanmode::Private))
+                || channel.members.contains_key(&requester.user_id)
+        })
+        .map(|channel| {
+            let prefix = channel
+                .status(&target.user_id)
+                .map(|status| status.prefix().to_string())
+                .unwrap_or_default();
+
+            format!("{prefix}{}", channel.name)
+        })
+        .collect::<Vec<String>>();
+    if !channels.is_empty() {
+        replies.push(reply(
+            IrcResponseCodes::WhoisChannels,
+            format!(":{}", channels.join(" ")),
+        ));
+    }
+
+    let description = match NETWORK.get_server(&target.user_id.get_server_id()) {
+        Some(server) if !NETWORK.is_local(&target.user_id) => server.description,
+        _ => format!("IRS-v{}", env!("CARGO_PKG_VERSION")),
+    };
+    replies.push(reply(
+        IrcResponseCodes::WhoisServer,
+        format!(
+            "{} :{description}",
+            NETWORK.server_name(&target.user_id, hostname)
+        ),
+    ));
+
+    if let Some(away) = &target.away {
+        replies.push(reply(IrcResponseCodes::Away, format!(":{away}")));
+    }
+    if target.usermodes.has(&Usermode::Operator) {
+        replies.push(reply(
+            IrcResponseCodes::WhoisOperator,
+            ":is an IRC operator".to_owned(),
+        ));
+    }
+    if target.usermodes.has(&Usermode::Secure) {
+        replies.push(reply(
+            IrcResponseCodes::WhoisSecure,
+            ":is using a secure connection".to_owned(),
+        ));
+    }
+    if let Some(certfp) = target.certfp.as_ref().filter(|_| is_self || is_oper) {
+        replies.push(reply(
+            IrcResponseCodes::WhoisCertFp,
+            format!(":has client certificate fingerprint {certfp}"),
+        ));
+    }
+    if let Some(account) = &target.account {
+        replies.push(reply(
+            IrcResponseCodes::WhoisAccount,
+            format!("{account} :is logged in as"),
+        ));
+    }
+
+    // only known for our own users
+    if let Some(activity) = NETWORK.activity(&target.user_id) {
+        let idle = SystemTime::now()
+            .duration_since(activity.last_message)
+            .map(|x| x.as_secs())
+            .unwrap_or_default();
+        let signon = activity
+            .signon
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or_default();
+
+        replies.push(reply(
+            IrcResponseCodes::WhoisIdle,
+            format!("{idle} {signon} :seconds idle, signon time"),
+        ));
+    }
+
+    replies.push(reply(
+        IrcResponseCodes::EndOfWhois,
+        ":End of /WHOIS list".to_owned(),
+    ));
+
+    replies
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_client::TestClient;
+
+    #[tokio::test]
+    async fn test_whois_whowas() {
+        let mut alice = TestClient::register("ialice").await;
+        let mut bob = TestClient::register("ibob").await;
+
+        alice.send("JOIN #whois").await;
+        alice.recv_until("366").await;
+
+        bob.send("WHOIS IALICE").await;
+        let whois = bob.recv_until("318").await;
+        assert_eq!(
+            whois[..3],
+            [
+                ":irc.example.com 311 ibob ialice ~ialice unimplement.ed * :ialice",
+                ":irc.example.com 319 ibob ialice :@#whois",
+                &format!(
+                    ":irc.example.com 312 ibob ialice irc.example.com :IRS-v{}",
+                    env!("CARGO_PKG_VERSION")
+                ),
+            ]
+        );
+        assert!(whois[3].starts_with(":irc.example.com 317 ibob ialice 0 "));
+        assert_eq!(
+            whois[4],
+            ":irc.example.com 318 ibob ialice :End of /WHOIS list"
+        );
+
+        bob.send("WHOIS nobody").await;
+        assert_eq!(
+            bob.recv_until("318").await[0],
+            ":irc.example.com 401 ibob nobody :No such nick/channel"
+        );
+
+        alice.send("NICK ialice2").await;
+        alice.recv_until("NICK").await;
+        bob.send("WHOWAS ialice").await;
+        let whowas = bob.recv_until("369").await;
+        assert_eq!(
+            whowas[0],
+            ":irc.example.com 314 ibob ialice ~ialice unimplement.ed * :ialice"
+        );
+        assert!(whowas[1].starts_with(":irc.example.com 312 ibob ialice irc.example.com :"));
+        assert_eq!(whowas[2], ":irc.example.com 369 ibob ialice :End of WHOWAS");
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::UNIX_EPOCH;
+
+use async_trait::async_trait;
+
+use crate::{
+    commands::{IrcAction, IrcHandler},
+    config::ServerInfo,
+    sender::IrcResponseCodes,
+    state::NETWORK,
+    tags::Tags,
+    user::User,
+};
+
+pub struct Whowas;
+
+#[async_trait]
+impl IrcHandler for Whowas {
+    async fn handle(
+        &self,
+        arguments: Vec<String>,
+        _tags: Tags,
+        authenticated: bool,
+        user_state: &mut User,
+        config: &ServerInfo,
+    ) -> Vec<IrcAction> {
+        if !authenticated {
+            return vec![IrcAction::ErrorAuthenticateFirst];
+        }
+        let nick = user_state.nickname.clone().unwrap_or("*".to_owned());
+
+        let Some(target) = arguments.first() else {
+            return vec![IrcAction::SendText(
+                IrcResponseCodes::NoNicknameGiven
+                    .into_irc_response(nick, ":No nickname given".into()),
+            )];
+        };
+        // a count of zero or less means all of them
+        let count = arguments
+            .get(1)
+            .and_then(|count| count.parse::<usize>().ok())
+            .filter(|count| *count > 0)
+            .unwrap_or(usize::MAX);
+
+        let entries = NETWORK.whowas(target);
+        let mut actions = if entries.is_empty() {
+            vec![IrcAction::SendText(
+                IrcResponseCodes::WasNoSuchNick.into_irc_response(
+                    nick.clone(),
+                    format!("{target} :There was no such nickname"),
+                ),
+            )]
+        } else {
+            entries
+                .into_iter()
+                .take(count)
+                .flat_map(|entry| {
+                    let until = entry
+                        .until
+                        .duration_since(UNIX_EPOCH)
+                        .map(|x| x.as_secs())
+                        .unwrap_or_default();
+
+                    [
+                        IrcAction::SendText(IrcResponseCodes::WhowasUser.into_irc_response(
+                            nick.clone(),
+                            format!(
+                                "{} ~{} {} * :{}",
+                                entry.nickname, entry.username, entry.host, entry.realname
+                            ),
+                        )),
+                        IrcAction::SendText(IrcResponseCodes::WhoisServer.into_irc_response(
+                            nick.clone(),
+                            format!(
+                                "{} {} :{until}",
+                                entry.nickname,
+                                entry.server.unwrap_or(config.server_hostname.clone())
+                            ),
+                        )),
+                    ]
+                })
+                .collect()
+        };
+
+        actions.push(IrcAction::SendText(
+            IrcResponseCodes::EndOfWhowas
+                .into_irc_response(nick, format!("{target} :End of WHOWAS")),
+        ));
+
+        actions
+    }
+}
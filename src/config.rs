@@ -1,6 +1,6 @@
-use std::{env::home_dir, fs::read_to_string, path::PathBuf};
+use std::{env::home_dir, fs::read_to_string, net::IpAddr, path::PathBuf};
 
-use crate::error_structs::ConfigReadError;
+use crate::{casemapping::Casemapping, error_structs::ConfigReadError};
 use serde::Deserialize;
 
 #[allow(dead_code)]
@@ -13,6 +13,61 @@ pub struct ServerInfo {
     pub operators: Vec<String>,
     pub server_incoming_passwords: Vec<String>,
     pub server_outgoing_password: String,
+    /// TOML file with the accounts users can log into with SASL
+    pub accounts_file: Option<String>,
+    pub tls: Option<TlsConfig>,
+    pub websocket: Option<WebSocketConfig>,
+    #[serde(default)]
+    pub opers: Vec<OperBlock>,
+    /// which nicknames and channel names are the same, TS6 links need the default rfc1459
+    #[serde(default)]
+    pub casemapping: Casemapping,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TlsConfig {
+    pub port: u64,
+    /// PEM file with the certificate chain
+    pub cert: String,
+    /// PEM file with the private key
+    pub key: String,
+    /// ask clients for a certificate, so they can be identified by its fingerprint
+    #[serde(default)]
+    pub request_client_cert: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WebSocketConfig {
+    pub port: u64,
+    /// use the certificate from the `[tls]` section
+    #[serde(default)]
+    pub tls: bool,
+    /// origins browsers may connect from, any origin is allowed if this is empty
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// reverse proxies whose X-Forwarded-For header we believe
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl WebSocketConfig {
+    /// With an allow-list, handshakes without an Origin don't get in either.
+    pub fn origin_allowed(&self, origin: Option<&str>) -> bool {
+        self.allowed_origins.is_empty()
+            || origin.is_some_and(|origin| {
+                self.allowed_origins
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(origin))
+            })
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct OperBlock {
+    pub name: String,
+    pub password: Option<String>,
+    /// SHA-256 fingerprint of the client certificate the oper has to be connected with
+    pub certfp: Option<String>,
 }
 
 fn get_config_path() -> Result<PathBuf, ConfigReadError> {
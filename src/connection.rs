@@ -0,0 +1,288 @@
+use std::{io, net::SocketAddr, sync::Arc};
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        DigitallySignedStruct, DistinguishedName, Error as RustlsError, ServerConfig,
+        SignatureScheme,
+        client::danger::HandshakeSignatureValid,
+        crypto::{CryptoProvider, ring, verify_tls12_signature, verify_tls13_signature},
+        pki_types::{CertificateDer, PrivateKeyDer, UnixTime, pem::PemObject},
+        server::{
+            WebPkiClientVerifier,
+            danger::{ClientCertVerified, ClientCertVerifier},
+        },
+    },
+    server::TlsStream,
+};
+
+use crate::{
+    config::TlsConfig,
+    error_structs::TlsSetupError,
+    parser::{MAX_LINE_LENGTH, MAX_TAGS_LENGTH},
+};
+
+/// How much of a line gets kept in memory. Anything past this is thrown away up to the next
+/// newline, and what's left is too long for the parser, which gets the client an ERR_INPUTTOOLONG.
+const MAX_READ_LENGTH: usize = MAX_TAGS_LENGTH + MAX_LINE_LENGTH;
+
+/// Where the lines we send to a client or server end up. Commands only ever write through this,
+/// so they don't need to know what kind of transport is on the other end.
+#[async_trait]
+pub trait IrcSink: Send {
+    /// Send a single line, including its trailing CRLF.
+    async fn send_line(&mut self, line: &str) -> io::Result<()>;
+
+    async fn close(&mut self) -> io::Result<()>;
+}
+
+/// Where the lines a client or server sends us come from. Has to be cancel safe, as it gets
+/// polled in a `select!` next to the message queue.
+#[async_trait]
+pub trait IrcSource: Send {
+    /// Read the next line, `None` once the connection got closed.
+    async fn read_line(&mut self) -> io::Result<Option<String>>;
+}
+
+/// Sink for byte stream transports like TCP and TLS.
+pub struct StreamSink<W>(BufWriter<W>);
+
+/// Source for byte stream transports like TCP and TLS.
+pub struct StreamSource<R> {
+    reader: BufReader<R>,
+    // kept across calls, so a line read partially when the other branch of a `select!` wins
+    // isn't lost
+    buffer: Vec<u8>,
+}
+
+/// A connection a client or server made to us, over whichever transport.
+pub struct Connection {
+    pub source: Box<dyn IrcSource>,
+    pub sink: Box<dyn IrcSink>,
+    pub address: SocketAddr,
+    pub secure: bool,
+    /// SHA-256 fingerprint of the TLS client certificate, as lowercase hex
+    pub certfp: Option<String>,
+}
+
+impl<W: AsyncWrite + Unpin + Send> StreamSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self(BufWriter::new(writer))
+    }
+}
+
+#[async_trait]
+impl<W: AsyncWrite + Unpin + Send> IrcSink for StreamSink<W> {
+    async fn send_line(&mut self, line: &str) -> io::Result<()> {
+        self.0.write_all(line.as_bytes()).await?;
+        self.0.flush().await
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        self.0.shutdown().await
+    }
+}
+
+impl<R: AsyncRead + Unpin + Send> StreamSource<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            buffer: Vec::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: AsyncRead + Unpin + Send> IrcSource for StreamSource<R> {
+    async fn read_line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // nothing gets awaited between filling and consuming, which keeps this cancel safe
+            let available = self.reader.fill_buf().await?;
+            if available.is_empty() {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                break;
+            }
+
+            let (chunk, done) = match available.iter().position(|x| *x == b'\n') {
+                Some(end) => (&available[..=end], true),
+                None => (available, false),
+            };
+            let length = chunk.len();
+            let room = MAX_READ_LENGTH.saturating_sub(self.buffer.len());
+            self.buffer.extend_from_slice(&chunk[..length.min(room)]);
+            self.reader.consume(length);
+
+            if done {
+                break;
+            }
+        }
+
+        let line = String::from_utf8_lossy(&self.buffer).to_string();
+        self.buffer.clear();
+
+        Ok(Some(line))
+    }
+}
+
+impl Connection {
+    /// Wrap any byte stream, e.g. an in-memory `tokio::io::duplex` for tests.
+    pub fn from_stream<S>(stream: S, address: SocketAddr, secure: bool) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+
+        Self {
+            source: Box::new(StreamSource::new(reader)),
+            sink: Box::new(StreamSink::new(writer)),
+            address,
+            secure,
+            certfp: None,
+        }
+    }
+
+    pub fn plain(stream: TcpStream, address: SocketAddr) -> Self {
+        Self::from_stream(stream, address, false)
+    }
+
+    pub fn tls(stream: TlsStream<TcpStream>, address: SocketAddr) -> Self {
+        let certfp = certificate_fingerprint(&stream);
+
+        Self {
+            certfp,
+            ..Self::from_stream(stream, address, true)
+        }
+    }
+}
+
+pub fn certificate_fingerprint(stream: &TlsStream<TcpStream>) -> Option<String> {
+    let (_, connection) = stream.get_ref();
+    let certificate = connection.peer_certificates()?.first()?;
+
+    Some(
+        Sha256::digest(certificate)
+            .iter()
+            .map(|x| format!("{x:02x}"))
+            .collect(),
+    )
+}
+
+/// Build the acceptor for the TLS listener from the `[tls]` section of the config.
+pub fn tls_acceptor(config: &TlsConfig) -> Result<TlsAcceptor, TlsSetupError> {
+    let provider = Arc::new(ring::default_provider());
+
+    let certificates = CertificateDer::pem_file_iter(&config.cert)?
+        .collect::<Result<Vec<CertificateDer<'static>>, _>>()?;
+    let key = PrivateKeyDer::from_pem_file(&config.key)?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let builder = if config.request_client_cert {
+        builder.with_client_cert_verifier(Arc::new(CertFpVerifier { provider }))
+    } else {
+        builder.with_client_cert_verifier(WebPkiClientVerifier::no_client_auth())
+    };
+
+    let server_config = builder.with_single_cert(certificates, key)?;
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Asks clients for a certificate without requiring one, and accepts any certificate the client
+/// can prove it holds the key of. We don't care who issued it, only about its fingerprint.
+#[derive(Debug)]
+struct CertFpVerifier {
+    provider: Arc<CryptoProvider>,
+}
+
+impl ClientCertVerifier for CertFpVerifier {
+    fn offer_client_auth(&self) -> bool {
+        true
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        false
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, RustlsError> {
+        Ok(ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, RustlsError> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, RustlsError> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+
+    use crate::connection::{IrcSource, MAX_READ_LENGTH, StreamSource};
+
+    #[tokio::test]
+    async fn test_read_line_length_limit() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut source = StreamSource::new(server);
+
+        tokio::spawn(async move {
+            client.write_all(&[b'a'; 100_000]).await.unwrap();
+            client.write_all(b"\r\nPING :x\r\n").await.unwrap();
+        });
+
+        let line = source.read_line().await.unwrap().unwrap();
+        assert_eq!(line.len(), MAX_READ_LENGTH);
+        assert_eq!(
+            source.read_line().await.unwrap().as_deref(),
+            Some("PING :x\r\n")
+        );
+        assert_eq!(source.read_line().await.unwrap(), None);
+    }
+}
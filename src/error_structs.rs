@@ -13,6 +13,12 @@ pub enum ListenerError {
 
     #[error("user has not identified yet")]
     UserIsUnidentified,
+
+    #[error("SendQ exceeded")]
+    SendQExceeded,
+
+    #[error("killed: {0}")]
+    Killed(String),
 }
 
 #[derive(Error, Debug)]
@@ -25,6 +31,30 @@ pub enum SenderError {
 pub enum CommandExecError {
     #[error("command does not exist")]
     NonexistantCommand,
+
+    #[error("couldn't write the reply")]
+    SenderError(#[from] SenderError),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("empty message")]
+    EmptyMessage,
+
+    #[error("message is longer than 512 bytes")]
+    InputTooLong,
+
+    #[error("message tags are too long")]
+    TagsTooLong,
+
+    #[error("message has no command")]
+    MissingCommand,
+
+    #[error("invalid command")]
+    InvalidCommand,
+
+    #[error("message contains an invalid character")]
+    InvalidCharacter,
 }
 
 #[derive(Error, Debug)]
@@ -42,6 +72,18 @@ pub enum ConfigReadError {
     TomlError(#[from] toml::de::Error),
 }
 
+#[derive(Error, Debug)]
+pub enum TlsSetupError {
+    #[error("std::io error")]
+    StdIo(#[from] std::io::Error),
+
+    #[error("couldn't read PEM file")]
+    Pem(#[from] tokio_rustls::rustls::pki_types::pem::Error),
+
+    #[error("rustls error")]
+    Rustls(#[from] tokio_rustls::rustls::Error),
+}
+
 // Conversion impls here
 impl From<SenderError> for ListenerError {
     fn from(value: SenderError) -> Self {
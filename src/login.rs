@@ -1,11 +1,22 @@
-use tokio::{io::BufWriter, net::TcpStream};
+use crate::{
+    ServerInfo, chanmodes,
+    channels::{CHANLIMIT, TOPICLEN},
+    commands::{away::AWAYLEN, list::ELIST, nick::NICKLEN},
+    connection::IrcSink,
+    error_structs::SenderError,
+    masks,
+    sender::IrcResponseCodes,
+    user::User,
+    usermodes,
+};
 
-use crate::{ServerInfo, error_structs::SenderError, sender::IrcResponseCodes, user::User};
+/// ISUPPORT tokens per RPL_ISUPPORT line
+const ISUPPORT_PER_LINE: usize = 13;
 
 pub async fn send_motd(
     server_info: ServerInfo,
     user_info: User,
-    writer: &mut BufWriter<TcpStream>,
+    writer: &mut dyn IrcSink,
 ) -> Result<(), SenderError> {
     let user_info = user_info.unwrap_all();
     let server_version = &format!("IRS-v{}", env!("CARGO_PKG_VERSION")) as &str;
@@ -18,10 +29,21 @@ pub async fn send_motd(
         "Your host is {}, running version {}",
         server_info.server_hostname, server_version
     );
-    let myinfo_text = format!("{} {} i b", server_info.server_hostname, server_version);
-    let isupport_text = format!(
-        "CHANTYPES=# NETWORK={} :are supported by this server",
-        server_info.network_name
+    let myinfo_text = format!(
+        "{} {} {} {}",
+        server_info.server_hostname,
+        server_version,
+        usermodes::mode_letters(),
+        chanmodes::mode_letters()
+    );
+    let isupport = format!(
+        "AWAYLEN={AWAYLEN} CASEMAPPING={} CHANLIMIT=#:{CHANLIMIT} {} CHANTYPES=# ELIST={ELIST} {} KNOCK {} NETWORK={} NICKLEN={NICKLEN} {} SAFELIST TOPICLEN={TOPICLEN} WHOX",
+        server_info.casemapping.name(),
+        chanmodes::chanmodes_isupport(),
+        masks::extban_isupport(),
+        chanmodes::maxlist_isupport(),
+        server_info.network_name,
+        chanmodes::prefix_isupport()
     );
 
     IrcResponseCodes::Welcome
@@ -36,10 +58,20 @@ pub async fn send_motd(
         .into_irc_response(user_info.nickname.clone(), myinfo_text)
         .send(&server_info.server_hostname, writer, false)
         .await?;
-    IrcResponseCodes::ISupport
-        .into_irc_response(user_info.nickname.clone(), isupport_text)
-        .send(&server_info.server_hostname, writer, false)
-        .await?;
+    // clients only take so many parameters per line, so the tokens get spread over several
+    for tokens in isupport
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .chunks(ISUPPORT_PER_LINE)
+    {
+        IrcResponseCodes::ISupport
+            .into_irc_response(
+                user_info.nickname.clone(),
+                format!("{} :are supported by this server", tokens.join(" ")),
+            )
+            .send(&server_info.server_hostname, writer, false)
+            .await?;
+    }
     IrcResponseCodes::NoMotd
         .into_irc_response(
             user_info.username.clone(),
@@ -1,60 +1,66 @@
 use std::{
-    clone,
-    collections::HashSet,
-    net::{SocketAddr, TcpListener, TcpStream},
+    net::SocketAddr,
     str::FromStr,
+    sync::Arc,
     time::{Duration, SystemTime},
 };
 
 use anyhow::Error as AnyhowError;
 use clap::Parser;
-use once_cell::sync::Lazy;
 use tokio::{
-    io::{AsyncBufReadExt, BufReader as TokioBufReader, BufWriter as TokioBufWriter},
-    net::TcpStream as TokioTcpStream,
+    net::TcpListener as TokioTcpListener,
     spawn,
-    sync::{
-        Mutex,
-        broadcast::{self, Receiver, Sender},
-    },
-    time::sleep,
+    time::{Instant, sleep_until},
 };
+use tokio_rustls::TlsAcceptor;
 use tracing::instrument;
 
 use crate::{
-    channels::Channel,
+    accounts::{Credentials, LocalFileBackend},
+    casemapping::Casemapping,
     config::ServerInfo,
-    error_structs::{HandlerError, ListenerError},
+    connection::{Connection, IrcSink},
+    error_structs::{HandlerError, ListenerError, ParseError},
     login::send_motd,
     messages::Receiver as MsgReceiver,
-    messages::{Message, NetJoinMessage},
+    messages::{CapChangeMessage, Message, NetJoinMessage, PrivMessage},
+    routing::{CLIENT_QUEUES, Outbound, SERVER_QUEUES, route},
     sender::{IrcResponse, IrcResponseCodes},
+    state::NETWORK,
+    tags::{Tags, tags_for_user},
     ts6::{
         Ts6,
         structs::{ServerId, UserId},
     },
     user::{User, UserUnwrapped},
+    usermodes::Usermode,
 };
 
+mod accounts;
+mod capabilities;
+mod casemapping;
+mod chanmodes;
 mod channels;
 mod commands;
 mod config;
+mod connection;
 mod error_structs;
 mod login;
+mod masks;
 mod messages;
+mod parser;
+mod routing;
+mod sasl;
 mod sender;
+mod state;
+mod tags;
+#[cfg(test)]
+mod test_client;
 mod ts6;
 mod user;
 mod userid_gen;
 mod usermodes;
-
-pub static CONNECTED_USERS: Lazy<Mutex<HashSet<UserUnwrapped>>> =
-    Lazy::new(|| Mutex::new(HashSet::new()));
-pub static FOREIGN_CONNECTED_USERS: Lazy<Mutex<HashSet<UserUnwrapped>>> =
-    Lazy::new(|| Mutex::new(HashSet::new()));
-pub static JOINED_CHANNELS: Lazy<Mutex<HashSet<Channel>>> =
-    Lazy::new(|| Mutex::new(HashSet::new()));
-pub static SENDER: Lazy<Mutex<Option<Sender<Message>>>> = Lazy::new(|| Mutex::new(None));
+mod websocket;
 
 /// An IRCd written in Rust
 #[derive(Parser, Debug)]
@@ -62,11 +68,19 @@ struct Args {
     /// Path to the config file
     #[arg(short, long)]
     pub config_path: Option<String>,
+
+    /// Print an accounts file entry for the given account, reading the password from stdin
+    #[arg(long, value_name = "ACCOUNT")]
+    pub hash_password: Option<String>,
 }
 
+/// How long a client can stay silent before we ping it, and then how long it has to answer
+const PING_INTERVAL: Duration = Duration::from_secs(120);
+
 enum TcpListenerResult {
-    UpdatedUser(User),
+    UpdatedUser(Box<User>),
     ServerConnectionInit,
+    Quit(String),
 }
 
 #[tokio::main]
@@ -75,156 +89,356 @@ async fn main() -> Result<(), AnyhowError> {
     console_subscriber::init();
 
     let args = Args::parse();
+
+    if let Some(account) = args.hash_password {
+        let mut password = String::new();
+        std::io::stdin().read_line(&mut password)?;
+
+        let credentials = Credentials::new(&account, password.trim_end_matches(['\r', '\n']));
+        println!("[[accounts]]\n{}", toml::to_string(&credentials)?);
+
+        return Ok(());
+    }
+
     let info = ServerInfo::load(args.config_path).unwrap();
     // TODO: ^ pull these from a config file
 
-    let listener = TcpListener::bind(SocketAddr::from_str(&format!("{}:{}", info.ip, info.port))?)?;
-    let (tx, mut _rx) = broadcast::channel::<Message>(32);
-    let mut sender_mut = SENDER.lock().await;
-    *sender_mut = Some(tx.clone());
-    drop(sender_mut);
+    casemapping::set_casemapping(info.casemapping);
+    if info.casemapping != Casemapping::Rfc1459 && !info.server_incoming_passwords.is_empty() {
+        println!(
+            "casemapping {} differs from the rfc1459 TS6 servers use, nicks may collide across links",
+            info.casemapping.name()
+        );
+    }
 
-    for stream in listener.incoming() {
-        let stream = stream?;
-        stream.set_nonblocking(true)?;
-        let tx_thread = tx.clone();
-        let info = info.clone();
+    let listener =
+        TokioTcpListener::bind(SocketAddr::from_str(&format!("{}:{}", info.ip, info.port))?)
+            .await?;
+    capabilities::register_capability("cap-notify", None).await;
+    capabilities::register_capability("multi-prefix", None).await;
+    capabilities::register_capability("invite-notify", None).await;
+    capabilities::register_capability("userhost-in-names", None).await;
+    capabilities::register_capability("away-notify", None).await;
+    tags::register_capabilities().await;
+
+    if let Some(accounts_file) = &info.accounts_file {
+        accounts::set_credential_backend(Arc::new(LocalFileBackend::load(accounts_file)?)).await;
+        sasl::register_capabilities().await;
+    }
+
+    let acceptor = info
+        .tls
+        .as_ref()
+        .map(connection::tls_acceptor)
+        .transpose()?;
+
+    if let (Some(tls), Some(acceptor)) = (&info.tls, &acceptor) {
+        let tls_listener =
+            TokioTcpListener::bind(SocketAddr::from_str(&format!("{}:{}", info.ip, tls.port))?)
+                .await?;
+
+        spawn(accept_tls(tls_listener, acceptor.clone(), info.clone()));
+    }
+
+    if let Some(websocket) = &info.websocket {
+        let acceptor = match (websocket.tls, acceptor) {
+            (true, None) => anyhow::bail!("the websocket listener needs a [tls] section for TLS"),
+            (true, Some(acceptor)) => Some(acceptor),
+            (false, _) => None,
+        };
+        let websocket_listener = TokioTcpListener::bind(SocketAddr::from_str(&format!(
+            "{}:{}",
+            info.ip, websocket.port
+        ))?)
+        .await?;
+
+        spawn(accept_websocket(websocket_listener, acceptor, info.clone()));
+    }
+
+    accept_plain(listener, info).await?;
+
+    Ok(())
+}
+
+async fn accept_plain(listener: TokioTcpListener, info: ServerInfo) -> Result<(), HandlerError> {
+    loop {
+        let (stream, address) = listener.accept().await?;
 
         spawn(handle_connection(
-            stream, info, /*&mut rx_thread,*/ tx_thread,
+            Connection::plain(stream, address),
+            info.clone(),
         ));
     }
+}
 
-    Ok(())
+async fn accept_tls(
+    listener: TokioTcpListener,
+    acceptor: TlsAcceptor,
+    info: ServerInfo,
+) -> Result<(), HandlerError> {
+    loop {
+        let (stream, address) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let info = info.clone();
+
+        spawn(async move {
+            match acceptor.accept(stream).await {
+                Ok(stream) => handle_connection(Connection::tls(stream, address), info).await,
+                Err(error) => {
+                    println!("TLS handshake with {address} failed: {error}");
+
+                    Ok(())
+                }
+            }
+        });
+    }
 }
 
-#[instrument]
+async fn accept_websocket(
+    listener: TokioTcpListener,
+    acceptor: Option<TlsAcceptor>,
+    info: ServerInfo,
+) -> Result<(), HandlerError> {
+    loop {
+        let (stream, address) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let info = info.clone();
+
+        spawn(async move {
+            // the websocket section is always set when this listener runs
+            let config = info.websocket.clone().unwrap();
+
+            let connection = match acceptor {
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => {
+                        let certfp = connection::certificate_fingerprint(&stream);
+
+                        websocket::accept(stream, address, true, &config)
+                            .await
+                            .map(|connection| Connection {
+                                certfp,
+                                ..connection
+                            })
+                    }
+                    Err(error) => {
+                        println!("TLS handshake with {address} failed: {error}");
+
+                        return Ok(());
+                    }
+                },
+                None => websocket::accept(stream, address, false, &config).await,
+            };
+
+            match connection {
+                Ok(connection) => handle_connection(connection, info).await,
+                Err(error) => {
+                    println!("WebSocket handshake with {address} failed: {error}");
+
+                    Ok(())
+                }
+            }
+        });
+    }
+}
+
+#[instrument(skip_all, fields(address = %connection.address))]
 async fn handle_connection(
-    stream: TcpStream,
+    mut connection: Connection,
     info: ServerInfo,
-    tx: Sender<Message>,
 ) -> Result<(), HandlerError> {
-    let stream_tcp = stream.try_clone()?;
-    let mut message_receiver = tx.clone().subscribe();
-    let mut tcp_reader = TokioBufReader::new(TokioTcpStream::from_std(stream.try_clone()?)?);
-    let mut tcp_writer = TokioBufWriter::new(TokioTcpStream::from_std(stream)?);
+    let (outbound, mut inbound) = routing::queue();
+
+    let mut state = User::default();
+    state.ip = Some(connection.address.ip());
+    state.certfp = connection.certfp.clone();
 
-    'connection_handler: {
-        let mut state = User::default();
+    if connection.secure {
+        state.usermodes.add(Usermode::Secure);
+    }
 
+    let quit_reason = 'connection_handler: {
         let hostname = info.server_hostname.clone();
 
         // TODO: generate randomally and allow overriding from config
         let my_server_id = ServerId::try_from("000".to_owned()).unwrap();
 
+        let mut last_seen = Instant::now();
+        let mut ping_sent = false;
+
         loop {
             tokio::select! {
-                result = tcp_listener(&stream_tcp, state.clone(), &info, &mut tcp_reader, my_server_id.clone()) => {
-                    match result {
-                        Ok(tcp_listener_result) => {
-                            match tcp_listener_result {
-                                TcpListenerResult::UpdatedUser(user) => {
-                                    state = user;
-                                }
+                result = connection.source.read_line() => {
+                    let line = match result {
+                        Ok(Some(line)) => line,
+                        Ok(None) => break 'connection_handler "Connection closed".to_owned(),
+                        Err(error) => break 'connection_handler format!("Read error: {error}"),
+                    };
+
+                    last_seen = Instant::now();
+                    ping_sent = false;
+
+                    match tcp_listener(line, state.clone(), &info, connection.sink.as_mut(), &outbound, my_server_id.clone()).await {
+                        Ok(TcpListenerResult::UpdatedUser(user)) => {
+                            state = *user;
+                        }
 
-                                TcpListenerResult::ServerConnectionInit => {
-                                    break;
-                                }
-                            }
+                        Ok(TcpListenerResult::ServerConnectionInit) => {
+                            break;
+                        }
+
+                        Ok(TcpListenerResult::Quit(reason)) => {
+                            break 'connection_handler reason;
                         }
 
                         Err(_) => {
-                            break 'connection_handler;
+                            break 'connection_handler "Write error".to_owned();
                         }
                     }
                 },
-                result = message_listener(&state, &mut message_receiver, &mut tcp_writer, &hostname) => {
+                result = inbound.recv() => {
                     match result {
-                        Ok(_) => {},
-                        Err(err) => {
-                            match err {
-                                ListenerError::ConnectionError => {
-                                    break 'connection_handler;
+                        Ok(message) => {
+                            match message_listener(&mut state, message, connection.sink.as_mut(), &hostname).await {
+                                Err(ListenerError::ConnectionError) => {
+                                    break 'connection_handler "Write error".to_owned();
+                                }
+                                Err(ListenerError::Killed(reason)) => {
+                                    break 'connection_handler format!("Killed ({reason})");
                                 }
-
                                 _ => {}
-                            };
+                            }
+                        }
+
+                        Err(ListenerError::SendQExceeded) => {
+                            break 'connection_handler "SendQ exceeded".to_owned();
+                        }
+
+                        Err(_) => {
+                            break 'connection_handler "Connection closed".to_owned();
                         }
                     }
                 },
+                _ = sleep_until(last_seen + PING_INTERVAL) => {
+                    if ping_sent {
+                        break 'connection_handler format!(
+                            "Ping timeout: {} seconds",
+                            (PING_INTERVAL * 2).as_secs()
+                        );
+                    }
+
+                    let _ = connection.sink.send_line(&format!("PING :{hostname}\r\n")).await;
+                    last_seen = Instant::now();
+                    ping_sent = true;
+                },
             }
         }
 
         println!("upgrade to server connection");
 
         let mut ts6_server_status = Ts6::default();
+        SERVER_QUEUES
+            .lock()
+            .await
+            .insert(outbound.id, outbound.clone());
 
-        loop {
+        let reason = loop {
             tokio::select! {
-                result = ts6_server_status.tcp_listener(&stream_tcp, &info, &mut tcp_reader, &my_server_id) => {
-                    match result {
+                result = connection.source.read_line() => {
+                    let Ok(Some(line)) = result else {
+                        break "Connection closed";
+                    };
+
+                    match ts6_server_status.tcp_listener(line, &info, connection.sink.as_mut(), &my_server_id).await {
                         Ok(new_status) => {
                             println!("{new_status:#?}");
                             ts6_server_status = new_status;
                         },
                         Err(_) => {
-                            break;
+                            break "Write error";
                         }
                     }
                 },
-                result = ts6_server_status.message_listener(&mut message_receiver, &mut tcp_writer, &my_server_id, &hostname) => {
-                    match result {
-                        Ok(_) => {},
-                        Err(_) => {
-                            break;
-                        }
+                result = inbound.recv() => {
+                    let Ok(message) = result else {
+                        break "SendQ exceeded";
+                    };
+
+                    if ts6_server_status.message_listener(message, connection.sink.as_mut(), &my_server_id, &hostname).await.is_err() {
+                        break "Write error";
                     }
                 },
             }
+        };
+
+        SERVER_QUEUES.lock().await.remove(&outbound.id);
+
+        if ts6_server_status.is_identified() {
+            let split = format!("{hostname} {}", ts6_server_status.hostname);
+            for user_id in NETWORK.remove_server(&ts6_server_status.server_id) {
+                routing::quit(&user_id, &split).await;
+            }
         }
+
+        reason.to_owned()
+    };
+
+    if let Some(user_id) = &state.user_id {
+        CLIENT_QUEUES.lock().await.remove(user_id);
+        routing::quit(user_id, &quit_reason).await;
+        userid_gen::release_user_id(user_id.get_id()).await;
     }
 
-    stream_tcp.shutdown(std::net::Shutdown::Both)?;
+    let _ = connection
+        .sink
+        .send_line(&format!(
+            "ERROR :Closing Link: {} ({quit_reason})\r\n",
+            connection.address.ip()
+        ))
+        .await;
+    let _ = connection.sink.close().await;
 
     Ok(())
 }
 
 async fn tcp_listener(
-    stream: &TcpStream,
+    line: String,
     mut user_state: User,
     info: &ServerInfo,
-    reader: &mut TokioBufReader<TokioTcpStream>,
+    writer: &mut dyn IrcSink,
+    outbound: &Outbound,
     our_sid: ServerId,
 ) -> Result<TcpListenerResult, ListenerError> {
-    let mut buffer = String::new();
-
-    let mut writer = TokioBufWriter::new(TokioTcpStream::from_std(stream.try_clone()?)?);
-
-    match reader.read_line(&mut buffer).await {
-        Ok(0) => return Err(ListenerError::ConnectionError),
-        Ok(_) => {}
-
-        Err(_) => {
-            let mut conneted_users = CONNECTED_USERS.lock().await;
-            let _ = conneted_users.remove(&user_state.clone().unwrap_all());
+    let command = match commands::IrcCommand::new(line).await {
+        Ok(command) => command,
+        Err(ParseError::InputTooLong | ParseError::TagsTooLong) => {
+            IrcResponseCodes::InputTooLong
+                .into_irc_response(
+                    user_state.nickname.clone().unwrap_or("*".to_owned()),
+                    "Input line was too long".into(),
+                )
+                .send(&info.server_hostname, writer, true)
+                .await?;
 
-            return Err(ListenerError::ConnectionError);
+            return Ok(TcpListenerResult::UpdatedUser(Box::new(user_state)));
         }
-    }
+        // empty lines and garbage are silently ignored
+        Err(_) => return Ok(TcpListenerResult::UpdatedUser(Box::new(user_state))),
+    };
 
-    let command = commands::IrcCommand::new(buffer.clone()).await;
     match command
-        .execute(&mut writer, &info.server_hostname, &mut user_state, info)
+        .execute(writer, &info.server_hostname, &mut user_state, info)
         .await
     {
         Ok(return_actions) => {
-            for return_action in return_actions {
-                match return_action {
+            for action in return_actions {
+                match action {
                     commands::ReturnAction::ServerConn => {
                         return Ok(TcpListenerResult::ServerConnectionInit);
                     }
-
-                    _ => {}
+                    commands::ReturnAction::CloseConn(reason) => {
+                        return Ok(TcpListenerResult::Quit(reason));
+                    }
+                    commands::ReturnAction::Nothing => {}
                 }
             }
         }
@@ -234,15 +448,22 @@ async fn tcp_listener(
                 let error = IrcResponseCodes::UnknownCommand;
 
                 error
-                    .into_irc_response("*".into(), error_string.into())
-                    .send(&info.server_hostname, &mut writer, true)
-                    .await
-                    .unwrap();
+                    .into_irc_response("*".into(), error_string)
+                    .send(&info.server_hostname, writer, true)
+                    .await?;
             }
+            error_structs::CommandExecError::SenderError(error) => return Err(error.into()),
         },
     }
 
-    if !user_state.identified && user_state.is_populated_without_uid() {
+    if user_state.identified {
+        NETWORK.update_user(user_state.unwrap_all());
+    }
+
+    if !user_state.identified
+        && user_state.is_populated_without_uid()
+        && !user_state.cap_negotiating
+    {
         let id = userid_gen::increase_user_id()
             .await
             .unwrap()
@@ -256,153 +477,478 @@ async fn tcp_listener(
         user_state.user_id = Some(UserId::try_from(user_id).unwrap()); // XXX: error handling
         user_state.timestamp = Some(SystemTime::now());
 
-        send_motd(info.clone(), user_state.clone(), &mut writer).await?;
+        if NETWORK.add_user(user_state.unwrap_all(), true).is_err() {
+            let nickname = user_state.nickname.take().unwrap_or_default();
 
-        let broadcast_sender = SENDER.lock().await.clone().unwrap();
+            user_state.identified = false;
+            if let Some(user_id) = user_state.user_id.take() {
+                userid_gen::release_user_id(user_id.get_id()).await;
+            }
 
-        broadcast_sender
-            .send(Message::NetJoinMessage(NetJoinMessage {
-                user: user_state.clone().unwrap_all(),
-                server_id: our_sid.clone(),
-            }))
-            .unwrap();
+            IrcResponseCodes::NicknameInUse
+                .into_irc_response(
+                    "*".into(),
+                    format!("{nickname} :Nickname is already in use"),
+                )
+                .send(&info.server_hostname, writer, false)
+                .await?;
 
-        CONNECTED_USERS
+            return Ok(TcpListenerResult::UpdatedUser(Box::new(user_state)));
+        }
+
+        send_motd(info.clone(), user_state.clone(), writer).await?;
+
+        CLIENT_QUEUES
             .lock()
             .await
-            .insert(user_state.clone().unwrap_all());
+            .insert(user_state.user_id.clone().unwrap(), outbound.clone());
+
+        route(Message::NetJoinMessage(NetJoinMessage {
+            user: user_state.clone().unwrap_all(),
+            server_id: our_sid.clone(),
+        }))
+        .await;
     }
 
-    Ok(TcpListenerResult::UpdatedUser(user_state))
+    Ok(TcpListenerResult::UpdatedUser(Box::new(user_state)))
 }
 
 async fn message_listener(
-    user_wrapped: &User,
-    receiver: &mut Receiver<Message>,
-    writer: &mut TokioBufWriter<TokioTcpStream>,
+    user_wrapped: &mut User,
+    message: Message,
+    writer: &mut dyn IrcSink,
     hostname: &str,
 ) -> Result<(), ListenerError> {
-    if !user_wrapped.is_populated() {
-        sleep(Duration::from_millis(250)).await; // avoid immediate returns b'cuz they result in high
-        // cpu usage
+    if !user_wrapped.is_populated() || !user_wrapped.identified {
         return Err(ListenerError::UserIsUnidentified);
     }
 
     let user = user_wrapped.clone().unwrap_all();
-    let message: Message = receiver.recv().await.unwrap();
-    let joined_channels = JOINED_CHANNELS.lock().await;
-
-    let mut channel_name: Option<String> = None;
 
     println!("{message:#?}");
 
+    // messages only get routed here if they concern us, so there's no more filtering to do
     match message {
         Message::PrivMessage(message) => {
-            for channel in joined_channels.clone() {
-                if let MsgReceiver::ChannelName(channelname) = message.clone().receiver
-                    && channelname == channel.name
-                    && channel.joined_users.contains(user_wrapped)
-                {
-                    channel_name = Some(channel.name.clone());
-                }
+            deliver_message(&user, user_wrapped, message, "PRIVMSG", writer).await?;
+        }
+
+        Message::TagMessage(message) => {
+            // TAGMSG only makes sense to clients which understand message tags
+            if user_wrapped.has_capability("message-tags") {
+                deliver_message(&user, user_wrapped, message, "TAGMSG", writer).await?;
             }
+        }
 
-            dbg!(&message);
+        Message::ChanJoinMessage(message) => {
+            let channel = message.channel.clone();
+
+            IrcResponse {
+                tags: Tags::new(),
+                sender: Some(message.sender.hostmask().clone()),
+                command: "JOIN".into(),
+                arguments: Vec::new(),
+                message: message.channel.name.clone(),
+                receiver: None,
+            }
+            .send("", writer, true)
+            .await?;
+
+            // away-notify clients learn that someone joining is away straight away
+            if message.sender.user_id != user.user_id
+                && message.sender.away.is_some()
+                && user_wrapped.has_capability("away-notify")
+            {
+                send_away(&message.sender, writer).await?;
+            }
 
-            if match message.clone().receiver {
-                MsgReceiver::UserId(userid) => {
-                    println!("{userid} ?= {}", user.user_id);
-                    if userid == user.user_id { true } else { false }
-                }
+            // only the user who joined gets the topic and names list
+            if message.sender.user_id == user.user_id {
+                channel
+                    .send_topic(user_wrapped.clone(), writer, hostname)
+                    .await
+                    .unwrap();
 
-                MsgReceiver::Username(username) => {
-                    if username.to_lowercase() == user.username.to_lowercase() {
-                        true
-                    } else {
-                        false
-                    }
-                }
+                channel
+                    .names_list_send(user_wrapped.clone(), writer, hostname)
+                    .await
+                    .unwrap();
+            }
+        }
+
+        Message::NetJoinMessage(_) => {} // we don't care about these here :)
+
+        Message::PartMessage(message) => {
+            IrcResponse {
+                tags: Tags::new(),
+                sender: Some(message.sender.hostmask()),
+                command: "PART".into(),
+                arguments: vec![message.channel.name],
+                message: message.reason.clone().unwrap_or_default(),
+                receiver: None,
+            }
+            .send("", writer, message.reason.is_some())
+            .await?;
+        }
+
+        Message::ChannelModeMessage(message) => {
+            let (modestring, parameters) = chanmodes::render_changes(&message.changes);
+
+            IrcResponse {
+                tags: Tags::new(),
+                sender: Some(message.sender.hostmask()),
+                command: "MODE".into(),
+                arguments: [vec![message.channel.name, modestring], parameters].concat(),
+                message: String::new(),
+                receiver: None,
+            }
+            .send("", writer, false)
+            .await?;
+        }
+
+        Message::BanMaskMessage(message) => {
+            // a burst can carry a lot of masks, keep the lines short
+            for changes in message.changes.chunks(4) {
+                let (modestring, parameters) = chanmodes::render_changes(changes);
 
-                _ => false,
-            } {
                 IrcResponse {
-                    sender: Some(message.sender.hostmask()),
-                    command: "PRIVMSG".into(),
-                    arguments: Vec::new(),
-                    message: message.text,
-                    receiver: Some(user.username.clone()),
+                    tags: Tags::new(),
+                    sender: Some(message.server.clone()),
+                    command: "MODE".into(),
+                    arguments: [vec![message.channel.name.clone(), modestring], parameters]
+                        .concat(),
+                    message: String::new(),
+                    receiver: None,
                 }
-                .send("", writer, true)
+                .send("", writer, false)
                 .await?;
-            } else if let Some(channel_name) = channel_name {
-                if message.sender != user {
-                    IrcResponse {
-                        sender: Some(message.sender.hostmask()),
-                        command: "PRIVMSG".into(),
-                        arguments: Vec::new(),
-                        message: message.text,
-                        receiver: Some(channel_name),
-                    }
-                    .send("", writer, true)
-                    .await?;
-                }
             }
         }
 
-        Message::ChanJoinMessage(message) => {
-            if message.channel.joined_users.contains(user_wrapped) || message.sender == user {
-                let channel = message.channel.clone();
+        Message::KickMessage(message) => {
+            IrcResponse {
+                tags: Tags::new(),
+                sender: Some(message.sender.hostmask()),
+                command: "KICK".into(),
+                arguments: vec![message.channel.name, message.target.nickname],
+                message: message.reason,
+                receiver: None,
+            }
+            .send("", writer, true)
+            .await?;
+        }
 
+        Message::InviteMessage(message) => {
+            if message.target.user_id == user.user_id
+                || user_wrapped.has_capability("invite-notify")
+            {
                 IrcResponse {
-                    sender: Some(message.sender.hostmask().clone()),
-                    command: "JOIN".into(),
-                    arguments: Vec::new(),
-                    message: message.channel.name.clone(),
+                    tags: Tags::new(),
+                    sender: Some(message.sender.hostmask()),
+                    command: "INVITE".into(),
+                    arguments: vec![message.target.nickname],
+                    message: message.channel.name,
                     receiver: None,
                 }
-                .send("", writer, true)
+                .send("", writer, false)
                 .await?;
+            }
+        }
 
-                channel
-                    .send_topic(user_wrapped.clone(), writer, hostname)
-                    .await
-                    .unwrap();
+        Message::WhoisRequest(_) => {} // only for servers
 
-                channel
-                    .names_list_send(user_wrapped.clone(), &channel, writer, hostname)
-                    .await
-                    .unwrap();
+        Message::ServerReply(message) => {
+            let mut arguments = message.arguments;
+            let trailing = arguments.pop().unwrap_or_default();
+
+            IrcResponse {
+                tags: Tags::new(),
+                sender: Some(message.server),
+                command: message.command,
+                arguments: [vec![user.nickname.clone()], arguments].concat(),
+                message: trailing,
+                receiver: None,
             }
+            .send("", writer, true)
+            .await?;
         }
 
-        Message::NetJoinMessage(_) => {} // we don't care about these here :)
+        Message::KnockMessage(message) => {
+            IrcResponseCodes::Knock
+                .into_irc_response(
+                    user.nickname.clone(),
+                    format!(
+                        "{} {} :has asked for an invite",
+                        message.channel.name,
+                        message.sender.hostmask()
+                    ),
+                )
+                .send(hostname, writer, false)
+                .await?;
+        }
+
+        Message::TopicMessage(message) => {
+            let text = message
+                .channel
+                .topic
+                .map(|topic| topic.text)
+                .unwrap_or_default();
+
+            IrcResponse {
+                tags: Tags::new(),
+                sender: Some(message.setter),
+                command: "TOPIC".into(),
+                arguments: vec![message.channel.name],
+                message: text,
+                receiver: None,
+            }
+            .send("", writer, true)
+            .await?;
+        }
+
+        Message::NickChangeMessage(message) => {
+            IrcResponse {
+                tags: Tags::new(),
+                sender: Some(message.user.hostmask()),
+                command: "NICK".into(),
+                arguments: Vec::new(),
+                message: message.nickname,
+                receiver: None,
+            }
+            .send("", writer, true)
+            .await?;
+        }
+
+        Message::AwayMessage(message) => {
+            if user_wrapped.has_capability("away-notify") {
+                send_away(&message.user, writer).await?;
+            }
+        }
+
+        Message::QuitMessage(message) => {
+            IrcResponse {
+                tags: Tags::new(),
+                sender: Some(message.user.hostmask()),
+                command: "QUIT".into(),
+                arguments: Vec::new(),
+                message: message.reason,
+                receiver: None,
+            }
+            .send("", writer, true)
+            .await?;
+        }
+
+        Message::KillMessage(message) => {
+            if message.user.user_id == user.user_id {
+                return Err(ListenerError::Killed(message.reason));
+            }
+
+            IrcResponse {
+                tags: Tags::new(),
+                sender: Some(message.user.hostmask()),
+                command: "QUIT".into(),
+                arguments: Vec::new(),
+                message: format!("Killed ({})", message.reason),
+                receiver: None,
+            }
+            .send("", writer, true)
+            .await?;
+        }
+
+        Message::CapChangeMessage(message) => {
+            send_cap_change(user_wrapped, message, writer, hostname).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `AWAY :message` if the user is away, a bare `AWAY` if they're back
+async fn send_away(user: &UserUnwrapped, writer: &mut dyn IrcSink) -> Result<(), ListenerError> {
+    IrcResponse {
+        tags: Tags::new(),
+        sender: Some(user.hostmask()),
+        command: "AWAY".into(),
+        arguments: Vec::new(),
+        message: user
+            .away
+            .clone()
+            .map(|text| format!(":{text}"))
+            .unwrap_or_default(),
+        receiver: None,
+    }
+    .send("", writer, false)
+    .await?;
+
+    Ok(())
+}
+
+async fn deliver_message(
+    user: &UserUnwrapped,
+    user_wrapped: &User,
+    message: PrivMessage,
+    command: &str,
+    writer: &mut dyn IrcSink,
+) -> Result<(), ListenerError> {
+    let receiver = match message.clone().receiver {
+        MsgReceiver::ChannelName(channel_name) => channel_name,
+        MsgReceiver::UserId(_) | MsgReceiver::Username(_) => user.nickname.clone(),
+    };
+
+    // TAGMSG doesn't have a trailing parameter
+    let has_text = command != "TAGMSG";
+
+    IrcResponse {
+        tags: tags_for_user(user_wrapped, &message.tags),
+        sender: Some(message.sender.hostmask()),
+        command: command.into(),
+        arguments: Vec::new(),
+        message: message.text,
+        receiver: Some(receiver),
+    }
+    .send("", writer, has_text)
+    .await?;
+
+    Ok(())
+}
+
+async fn send_cap_change(
+    user: &mut User,
+    message: CapChangeMessage,
+    writer: &mut dyn IrcSink,
+    hostname: &str,
+) -> Result<(), ListenerError> {
+    if !user.has_capability("cap-notify") {
+        return Ok(());
+    }
+
+    let subcommand = if message.added { "NEW" } else { "DEL" };
+    let tokens = message
+        .capabilities
+        .iter()
+        .map(|capability| {
+            if !message.added {
+                user.capabilities.remove(&capability.name);
+            }
+
+            capability.to_ls_string(user.cap_version)
+        })
+        .collect::<Vec<String>>();
+
+    IrcResponse {
+        tags: Tags::new(),
+        sender: None,
+        command: "CAP".into(),
+        arguments: vec![
+            user.nickname.clone().unwrap_or("*".to_owned()),
+            subcommand.into(),
+        ],
+        message: tokens.join(" "),
+        receiver: None,
     }
+    .send(hostname, writer, true)
+    .await?;
 
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::userid_gen;
+    use std::time::Duration;
+
+    use tokio::time::{advance, sleep};
+
+    use crate::{PING_INTERVAL, state::NETWORK, test_client::TestClient, ts6::structs::ServerId};
 
     #[tokio::test]
-    async fn test_user_id_generator() {
-        while let Ok(userid) = userid_gen::increase_user_id().await {
-            if userid == ['A', 'B', 'C', 'D', 'E', 'F'] {
-                userid_gen::manually_set_user_id(['Z', 'Z', 'Z', 'Z', 'Z', 'Y'].to_vec()).await;
-                break;
-            }
+    async fn test_in_process_client() {
+        let mut client = TestClient::connect().await;
 
-            dbg!(userid);
-        }
+        client.send("NICK tester").await;
+        client.send("USER tester 0 * :Tester").await;
 
-        while let Ok(userid) = userid_gen::increase_user_id().await {
-            if userid == ['A', '1', '2', '3', '4', '5'] {
-                // ff a bit
-                userid_gen::manually_set_user_id(['Z', '1', '2', '3', '4', '5'].to_vec()).await;
-            }
+        let lines = client.recv_until("422").await;
+        assert!(lines[0].starts_with(":irc.example.com 001 tester "));
 
-            dbg!(userid);
+        let mut other = TestClient::register("other").await;
+
+        other.send("PING :hello").await;
+        assert_eq!(
+            other.recv().await.as_deref(),
+            Some(":irc.example.com PONG other :hello")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_registration_nick_in_use() {
+        let _first = TestClient::register("taken").await;
+        let mut second = TestClient::connect().await;
+
+        second.send("NICK TAKEN").await;
+        second.send("USER taken 0 * :Taken").await;
+        assert_eq!(
+            second.recv().await.as_deref(),
+            Some(":irc.example.com 433 * TAKEN :Nickname is already in use")
+        );
+
+        second.send("NICK free").await;
+        assert!(second.recv_until("001").await[0].starts_with(":irc.example.com 001 free "));
+    }
+
+    #[tokio::test]
+    async fn test_server_split() {
+        let mut server = TestClient::link("9D0", "split.example.com").await;
+        server
+            .send(":9D0 SID leaf.example.com 2 9D1 :behind split")
+            .await;
+        server
+            .send(":9D1 UID ealice 2 1 + ~ealice 127.0.0.1 127.0.0.1 127.0.0.1 9D1AAAAAA * :ealice")
+            .await;
+        server.send("PING :split.example.com").await;
+        server.recv_until("PONG").await;
+        assert!(NETWORK.find_nick("ealice").is_some());
+
+        // users behind a server behind the lost link go too
+        drop(server);
+        for _ in 0..20 {
+            if NETWORK.find_nick("ealice").is_none() {
+                break;
+            }
+            sleep(Duration::from_millis(50)).await;
         }
+        assert_eq!(NETWORK.find_nick("ealice"), None);
+        assert!(
+            NETWORK
+                .get_server(&ServerId::try_from("9D1".to_owned()).unwrap())
+                .is_none()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ping_timeout() {
+        let mut client = TestClient::register("sleepy").await;
+
+        advance(PING_INTERVAL).await;
+        assert_eq!(
+            client.recv().await.as_deref(),
+            Some("PING :irc.example.com")
+        );
+        client.send("PONG :irc.example.com").await;
+        // make sure the answer got read before moving on
+        client.send("PING :sync").await;
+        client.recv_until("PONG").await;
+
+        advance(PING_INTERVAL).await;
+        assert_eq!(
+            client.recv().await.as_deref(),
+            Some("PING :irc.example.com")
+        );
+
+        // no answer this time
+        advance(PING_INTERVAL).await;
+        assert_eq!(
+            client.recv().await.as_deref(),
+            Some("ERROR :Closing Link: 127.0.0.1 (Ping timeout: 240 seconds)")
+        );
     }
 }
@@ -0,0 +1,339 @@
+//! Matching users against `nick!user@host` masks, as used by ban lists and the like, and against
+//! charybdis style extbans like `$a:account`.
+
+use std::{collections::BTreeSet, net::IpAddr};
+
+use crate::{
+    casemapping::casefold, chanmodes::Chanmode, channels::Channel, state::NETWORK,
+    user::UserUnwrapped, usermodes::Usermode,
+};
+
+/// Letters of the extban types we know
+pub const EXTBAN_TYPES: &str = "ajrxz";
+
+/// The EXTBAN ISUPPORT token
+pub fn extban_isupport() -> String {
+    format!("EXTBAN=$,{EXTBAN_TYPES}")
+}
+
+/// An extban split into whether it's negated, its type and its parameter, if it's a valid one.
+fn parse_extban(mask: &str) -> Option<(bool, char, Option<&str>)> {
+    let mask = mask.strip_prefix('$')?;
+    let (negated, mask) = match mask.strip_prefix('~') {
+        Some(mask) => (true, mask),
+        None => (false, mask),
+    };
+
+    let mut chars = mask.chars();
+    let kind = chars.next()?.to_ascii_lowercase();
+    let parameter = match chars.as_str() {
+        "" => None,
+        rest => Some(rest.strip_prefix(':').filter(|x| !x.is_empty())?),
+    };
+
+    let valid = match kind {
+        'a' => true,
+        'z' => parameter.is_none(),
+        'r' | 'x' => parameter.is_some(),
+        'j' => parameter.is_some_and(|x| x.starts_with('#')),
+        _ => false,
+    };
+
+    valid.then_some((negated, kind, parameter))
+}
+
+/// Fill in the parts left out of a mask, so `nick` becomes `nick!*@*` and `user@host` becomes
+/// `*!user@host`. Extbans are kept as they are, but `None` if we don't understand them, and so
+/// are CIDR ranges that could never match.
+pub fn normalize_mask(mask: &str) -> Option<String> {
+    if mask.starts_with('$') {
+        let (negated, kind, parameter) = parse_extban(mask)?;
+
+        return Some(format!(
+            "${}{kind}{}",
+            if negated { "~" } else { "" },
+            parameter.map(|x| format!(":{x}")).unwrap_or_default()
+        ));
+    }
+
+    let (nick_user, host) = match mask.split_once('@') {
+        Some((nick_user, host)) => (nick_user, host),
+        None if mask.contains('!') || !mask.contains('.') => (mask, "*"),
+        // a bare hostname
+        None => ("*", mask),
+    };
+    let (nick, user) = match nick_user.split_once('!') {
+        Some((nick, user)) => (nick, user),
+        None if mask.contains('@') => ("*", nick_user),
+        None => (nick_user, "*"),
+    };
+    if !valid_cidr(host) {
+        return None;
+    }
+
+    let or_any = |part: &str| {
+        if part.is_empty() {
+            "*".to_owned()
+        } else {
+            part.to_owned()
+        }
+    };
+
+    Some(format!(
+        "{}!{}@{}",
+        or_any(nick),
+        or_any(user),
+        or_any(host)
+    ))
+}
+
+/// Hosts ending in `/<digits>` are taken for a CIDR range, which needs an address and a prefix
+/// length that fits it. Anything else, like `user/alice` cloaks, is fine as a glob.
+fn valid_cidr(host: &str) -> bool {
+    match host.rsplit_once('/') {
+        Some((address, prefix))
+            if !prefix.is_empty() && prefix.chars().all(|x| x.is_ascii_digit()) =>
+        {
+            match (address.parse::<IpAddr>(), prefix.parse::<u32>()) {
+                (Ok(IpAddr::V4(_)), Ok(prefix)) => prefix <= 32,
+                (Ok(IpAddr::V6(_)), Ok(prefix)) => prefix <= 128,
+                _ => false,
+            }
+        }
+        _ => true,
+    }
+}
+
+/// Glob match with `*` for any number of characters and `?` for exactly one, ignoring case.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = casefold(pattern).chars().collect::<Vec<char>>();
+    let text = casefold(text).chars().collect::<Vec<char>>();
+
+    let (mut p, mut t) = (0, 0);
+    // where the last `*` was, and how much of the text it has eaten so far
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(x) if *x == '?' || *x == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, eaten)) => {
+                    p = star + 1;
+                    t = eaten + 1;
+                    backtrack = Some((star, eaten + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|x| *x == '*')
+}
+
+/// Whether an address is inside a network like `192.0.2.0/24`.
+pub fn cidr_match(network: &str, ip: IpAddr) -> bool {
+    let Some((address, prefix)) = network.split_once('/') else {
+        return false;
+    };
+    let (Ok(address), Ok(prefix)) = (address.parse::<IpAddr>(), prefix.parse::<u32>()) else {
+        return false;
+    };
+
+    match (address, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) if prefix <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+
+            u32::from(network) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) if prefix <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+
+            u128::from(network) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Whether a (normalized) mask matches the user, by hostname, IP address or CIDR range, or
+/// whatever an extban looks at.
+pub fn mask_matches(mask: &str, user: &UserUnwrapped) -> bool {
+    mask_matches_with(mask, user, &|name| banned_from(name, user))
+}
+
+/// Like `mask_matches`, but `$j` extbans ask `banned_from` about the other channel instead of
+/// looking it up, for when the registry can't be touched.
+pub fn mask_matches_with(
+    mask: &str,
+    user: &UserUnwrapped,
+    banned_from: &dyn Fn(&str) -> bool,
+) -> bool {
+    if mask.starts_with('$') {
+        return extban_matches(mask, user, banned_from);
+    }
+
+    let Some((nick_user, host)) = mask.rsplit_once('@') else {
+        return false;
+    };
+    let hostmask = user.hostmask();
+    let Some((user_nick_user, user_host)) = hostmask.rsplit_once('@') else {
+        return false;
+    };
+
+    glob_match(nick_user, user_nick_user)
+        && (glob_match(host, user_host)
+            || glob_match(host, &user.ip.to_string())
+            || cidr_match(host, user.ip))
+}
+
+fn extban_matches(mask: &str, user: &UserUnwrapped, banned_from: &dyn Fn(&str) -> bool) -> bool {
+    let Some((negated, kind, parameter)) = parse_extban(mask) else {
+        return false;
+    };
+
+    let matches = match (kind, parameter) {
+        // any logged in user, or those logged into a matching account
+        ('a', None) => user.account.is_some(),
+        ('a', Some(pattern)) => user
+            .account
+            .as_ref()
+            .is_some_and(|account| glob_match(pattern, account)),
+        ('r', Some(pattern)) => glob_match(pattern, &user.realname),
+        ('x', Some(pattern)) => {
+            glob_match(pattern, &format!("{}#{}", user.hostmask(), user.realname))
+        }
+        ('z', _) => user.usermodes.has(&Usermode::Secure),
+        ('j', Some(name)) => banned_from(name),
+        _ => false,
+    };
+
+    matches != negated
+}
+
+/// What a `$j:#channel` extban checks: whether the user is banned from that channel. Its own `$j`
+/// bans don't count, so they can't loop.
+pub fn banned_from(name: &str, user: &UserUnwrapped) -> bool {
+    NETWORK
+        .get_channel(name)
+        .is_some_and(|channel| banned_directly(&channel, user))
+}
+
+/// Whether a ban other than a `$j` one matches the user
+pub fn banned_directly(channel: &Channel, user: &UserUnwrapped) -> bool {
+    channel
+        .modes
+        .list(Chanmode::Ban)
+        .iter()
+        .filter(|entry| parse_extban(&entry.mask).is_none_or(|(_, kind, _)| kind != 'j'))
+        .any(|entry| mask_matches(&entry.mask, user))
+}
+
+/// Casefolded names of the channels `$j` extbans among the masks point at
+pub fn extban_channels<'a>(masks: impl Iterator<Item = &'a str>) -> BTreeSet<String> {
+    masks
+        .filter_map(parse_extban)
+        .filter_map(|(_, kind, parameter)| (kind == 'j').then_some(parameter?))
+        .map(casefold)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, time::SystemTime};
+
+    use crate::{
+        masks::{cidr_match, glob_match, mask_matches, normalize_mask},
+        ts6::structs::UserId,
+        user::UserUnwrapped,
+        usermodes::{Usermode, Usermodes},
+    };
+
+    #[test]
+    fn test_normalize_mask() {
+        let normalized = |mask| normalize_mask(mask).unwrap();
+
+        assert_eq!(normalized("nick"), "nick!*@*");
+        assert_eq!(normalized("nick!user"), "nick!user@*");
+        assert_eq!(normalized("user@host"), "*!user@host");
+        assert_eq!(normalized("*.example.com"), "*!*@*.example.com");
+        assert_eq!(normalized("a!b@c"), "a!b@c");
+        assert_eq!(normalized("!@"), "*!*@*");
+        assert_eq!(normalized("*!*@192.0.2.0/24"), "*!*@192.0.2.0/24");
+        assert_eq!(normalized("*!*@2001:db8::/32"), "*!*@2001:db8::/32");
+        assert_eq!(normalized("*!*@user/alice"), "*!*@user/alice");
+        assert_eq!(normalize_mask("*!*@1.2.3.4/99"), None);
+        assert_eq!(normalize_mask("*!*@::1/200"), None);
+        assert_eq!(normalize_mask("*!*@1.2.3.999/8"), None);
+        assert_eq!(normalize_mask("*!*@*/24"), None);
+
+        assert_eq!(normalized("$~A:some*"), "$~a:some*");
+        assert_eq!(normalized("$j:#chan"), "$j:#chan");
+        assert_eq!(normalize_mask("$r"), None);
+        assert_eq!(normalize_mask("$z:x"), None);
+        assert_eq!(normalize_mask("$j:chan"), None);
+        assert_eq!(normalize_mask("$q:x"), None);
+    }
+
+    #[test]
+    fn test_extbans() {
+        let mut user = UserUnwrapped {
+            nickname: "nick".to_owned(),
+            username: "user".to_owned(),
+            realname: "Real Name".to_owned(),
+            identified: true,
+            hopcount: 0,
+            user_id: UserId::try_from("000AAAAAB".to_owned()).unwrap(),
+            usermodes: Usermodes::default(),
+            timestamp: SystemTime::now(),
+            ip: IpAddr::from([127, 0, 0, 1]),
+            account: None,
+            certfp: None,
+            away: None,
+        };
+
+        assert!(!mask_matches("$a", &user));
+        assert!(mask_matches("$~a", &user));
+        user.account = Some("Account".to_owned());
+        assert!(mask_matches("$a", &user));
+        assert!(mask_matches("$a:acc*", &user));
+        assert!(!mask_matches("$~a:acc*", &user));
+
+        assert!(mask_matches("$r:real*", &user));
+        assert!(mask_matches("$x:nick!*@*#*name", &user));
+        assert!(!mask_matches("$x:nick!*@*#other", &user));
+
+        assert!(!mask_matches("$z", &user));
+        user.usermodes.add(Usermode::Secure);
+        assert!(mask_matches("$z", &user));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*!*@*", "nick!~user@host"));
+        assert!(glob_match("Nick[m]!*@*", "nick{M}!~user@host"));
+        assert!(glob_match("n?ck!*u*r@*.ed", "nick!~user@unimplement.ed"));
+        assert!(glob_match("**a", "a"));
+
+        assert!(!glob_match("nick!*@*", "nick2!~user@host"));
+        assert!(!glob_match("?", ""));
+        assert!(!glob_match("*b", "aaa"));
+    }
+
+    #[test]
+    fn test_cidr_match() {
+        let ip = "192.0.2.77".parse::<IpAddr>().unwrap();
+
+        assert!(cidr_match("192.0.2.0/24", ip));
+        assert!(cidr_match("0.0.0.0/0", ip));
+        assert!(!cidr_match("192.0.3.0/24", ip));
+        assert!(!cidr_match("2001:db8::/32", ip));
+        assert!(cidr_match("2001:db8::/32", "2001:db8::1".parse().unwrap()));
+        assert!(!cidr_match("192.0.2.0", ip));
+    }
+}
@@ -1,5 +1,10 @@
+use std::time::SystemTime;
+
 use crate::{
+    capabilities::Capability,
+    chanmodes::ModeChange,
     channels::Channel,
+    tags::Tags,
     ts6::structs::{ServerId, UserId},
     user::UserUnwrapped,
 };
@@ -7,8 +12,23 @@ use crate::{
 #[derive(Debug, Clone)]
 pub enum Message {
     PrivMessage(PrivMessage),
+    TagMessage(PrivMessage),
     ChanJoinMessage(ChanJoinMessage),
     NetJoinMessage(NetJoinMessage),
+    CapChangeMessage(CapChangeMessage),
+    QuitMessage(QuitMessage),
+    KillMessage(KillMessage),
+    PartMessage(PartMessage),
+    NickChangeMessage(NickChangeMessage),
+    ChannelModeMessage(ChannelModeMessage),
+    BanMaskMessage(BanMaskMessage),
+    TopicMessage(TopicMessage),
+    KickMessage(KickMessage),
+    InviteMessage(InviteMessage),
+    KnockMessage(KnockMessage),
+    WhoisRequest(WhoisRequest),
+    ServerReply(ServerReply),
+    AwayMessage(AwayMessage),
 }
 
 #[allow(dead_code)]
@@ -25,12 +45,122 @@ pub struct NetJoinMessage {
     pub server_id: ServerId,
 }
 
+#[derive(Debug, Clone)]
+pub struct PartMessage {
+    pub sender: UserUnwrapped,
+    /// The channel as it was before the user left
+    pub channel: Channel,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NickChangeMessage {
+    /// The user as they were before the change
+    pub user: UserUnwrapped,
+    pub nickname: String,
+    pub timestamp: SystemTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct AwayMessage {
+    /// The user with their new away message, which is `None` once they're back
+    pub user: UserUnwrapped,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChannelModeMessage {
+    pub sender: UserUnwrapped,
+    /// The channel after the changes
+    pub channel: Channel,
+    pub changes: Vec<ModeChange>,
+}
+
+/// List entries a linked server added with BMASK
+#[derive(Debug, Clone)]
+pub struct BanMaskMessage {
+    /// Name of the server that set them
+    pub server: String,
+    /// The channel after the changes
+    pub channel: Channel,
+    pub changes: Vec<ModeChange>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TopicMessage {
+    /// Hostmask of the user, or name of the server, who changed it
+    pub setter: String,
+    /// The user who changed it, `None` if a server did
+    pub user_id: Option<UserId>,
+    /// The channel with the new topic
+    pub channel: Channel,
+}
+
+#[derive(Debug, Clone)]
+pub struct KickMessage {
+    pub sender: UserUnwrapped,
+    /// The channel as it was before the user got kicked
+    pub channel: Channel,
+    pub target: UserUnwrapped,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct InviteMessage {
+    pub sender: UserUnwrapped,
+    pub target: UserUnwrapped,
+    pub channel: Channel,
+}
+
+/// Someone asking the operators of an invite-only channel to be let in
+#[derive(Debug, Clone)]
+pub struct KnockMessage {
+    pub sender: UserUnwrapped,
+    pub channel: Channel,
+}
+
+/// A local user asking the server a remote user is on for their WHOIS
+#[derive(Debug, Clone)]
+pub struct WhoisRequest {
+    pub sender: UserUnwrapped,
+    pub target: UserUnwrapped,
+}
+
+/// A numeric reply a linked server sent to one of our users
+#[derive(Debug, Clone)]
+pub struct ServerReply {
+    pub server: String,
+    pub target: UserId,
+    pub command: String,
+    /// Parameters after the target, the last one being the trailing one
+    pub arguments: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QuitMessage {
+    pub user: UserUnwrapped,
+    pub reason: String,
+}
+
+/// A user taken off the network by force, e.g. after losing a nick collision
+#[derive(Debug, Clone)]
+pub struct KillMessage {
+    pub user: UserUnwrapped,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CapChangeMessage {
+    pub added: bool,
+    pub capabilities: Vec<Capability>,
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct PrivMessage {
     pub sender: UserUnwrapped,
     pub receiver: Receiver,
     pub text: String,
+    pub tags: Tags,
 }
 
 #[allow(dead_code)]
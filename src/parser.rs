@@ -0,0 +1,336 @@
+use crate::{
+    error_structs::ParseError,
+    tags::{Tags, parse_tags},
+};
+
+/// Maximum length of a line without its tags, including the trailing CRLF.
+pub const MAX_LINE_LENGTH: usize = 512;
+/// Maximum length of the tag section, including the leading `@` and the space after it.
+pub const MAX_TAGS_LENGTH: usize = 8191;
+pub const MAX_PARAMS: usize = 15;
+
+/// A single IRC line, split into its parts without copying anything out of the original line.
+///
+/// ```text
+/// [@tags] [:source] <command> [params...] [:trailing]
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParsedMessage<'a> {
+    /// raw tag section, without the leading `@`
+    pub tags: Option<&'a str>,
+    /// message source, without the leading `:`
+    pub source: Option<&'a str>,
+    pub command: &'a str,
+    params: [&'a str; MAX_PARAMS],
+    param_count: usize,
+    has_trailing: bool,
+}
+
+impl<'a> ParsedMessage<'a> {
+    pub fn parse(line: &'a str) -> Result<Self, ParseError> {
+        let mut rest = line.trim_end_matches(['\r', '\n']).trim_start_matches(' ');
+
+        if rest.contains(['\0', '\r', '\n']) {
+            return Err(ParseError::InvalidCharacter);
+        }
+
+        let mut tags = None;
+        if let Some(tagged) = rest.strip_prefix('@') {
+            let (raw_tags, after) = tagged.split_once(' ').unwrap_or((tagged, ""));
+
+            if raw_tags.len() + 2 > MAX_TAGS_LENGTH {
+                return Err(ParseError::TagsTooLong);
+            }
+
+            tags = Some(raw_tags);
+            rest = after.trim_start_matches(' ');
+        }
+
+        if rest.is_empty() {
+            return Err(if tags.is_some() {
+                ParseError::MissingCommand
+            } else {
+                ParseError::EmptyMessage
+            });
+        }
+
+        if rest.len() + 2 > MAX_LINE_LENGTH {
+            return Err(ParseError::InputTooLong);
+        }
+
+        let mut source = None;
+        if let Some(prefixed) = rest.strip_prefix(':') {
+            let (raw_source, after) = prefixed.split_once(' ').unwrap_or((prefixed, ""));
+
+            if raw_source.is_empty() {
+                return Err(ParseError::MissingCommand);
+            }
+
+            source = Some(raw_source);
+            rest = after.trim_start_matches(' ');
+        }
+
+        let (command, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+
+        if command.is_empty() {
+            return Err(ParseError::MissingCommand);
+        }
+
+        let is_numeric = command.len() == 3 && command.bytes().all(|x| x.is_ascii_digit());
+        if !is_numeric && !command.bytes().all(|x| x.is_ascii_alphabetic()) {
+            return Err(ParseError::InvalidCommand);
+        }
+
+        let mut params = [""; MAX_PARAMS];
+        let mut param_count = 0;
+        let mut has_trailing = false;
+
+        loop {
+            rest = rest.trim_start_matches(' ');
+
+            if rest.is_empty() {
+                break;
+            }
+
+            if let Some(trailing) = rest.strip_prefix(':') {
+                params[param_count] = trailing;
+                param_count += 1;
+                has_trailing = true;
+                break;
+            }
+
+            // the last parameter takes whatever is left, spaces included
+            if param_count == MAX_PARAMS - 1 {
+                params[param_count] = rest;
+                param_count += 1;
+                has_trailing = true;
+                break;
+            }
+
+            let (param, after) = rest.split_once(' ').unwrap_or((rest, ""));
+            params[param_count] = param;
+            param_count += 1;
+            rest = after;
+        }
+
+        Ok(Self {
+            tags,
+            source,
+            command,
+            params,
+            param_count,
+            has_trailing,
+        })
+    }
+
+    pub fn params(&self) -> &[&'a str] {
+        &self.params[..self.param_count]
+    }
+
+    /// The last parameter, if it was sent as a trailing parameter (or was the 15th one).
+    #[allow(dead_code)]
+    pub fn trailing(&self) -> Option<&'a str> {
+        if self.has_trailing {
+            self.params().last().copied()
+        } else {
+            None
+        }
+    }
+
+    pub fn tags(&self) -> Tags {
+        self.tags.map(parse_tags).unwrap_or_default()
+    }
+
+    pub fn owned_params(&self) -> Vec<String> {
+        self.params().iter().map(|x| x.to_string()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::{
+        error_structs::ParseError,
+        parser::{MAX_LINE_LENGTH, ParsedMessage},
+        sender::IrcResponse,
+        tags::Tags,
+    };
+
+    #[test]
+    fn test_simple_command() {
+        let message = ParsedMessage::parse("NICK alice\r\n").unwrap();
+
+        assert_eq!(message.tags, None);
+        assert_eq!(message.source, None);
+        assert_eq!(message.command, "NICK");
+        assert_eq!(message.params(), ["alice"]);
+        assert_eq!(message.trailing(), None);
+    }
+
+    #[test]
+    fn test_full_message() {
+        let message =
+            ParsedMessage::parse("@msgid=abc;+typing :nick!user@host PRIVMSG #chan :hello world")
+                .unwrap();
+
+        assert_eq!(message.tags, Some("msgid=abc;+typing"));
+        assert_eq!(message.tags()["msgid"], "abc");
+        assert_eq!(message.tags()["+typing"], "");
+        assert_eq!(message.source, Some("nick!user@host"));
+        assert_eq!(message.command, "PRIVMSG");
+        assert_eq!(message.params(), ["#chan", "hello world"]);
+        assert_eq!(message.trailing(), Some("hello world"));
+    }
+
+    #[test]
+    fn test_trailing_keeps_spaces_and_colons() {
+        let message = ParsedMessage::parse("PRIVMSG #chan :  spaced   out :) \r\n").unwrap();
+
+        assert_eq!(message.params(), ["#chan", "  spaced   out :) "]);
+    }
+
+    #[test]
+    fn test_empty_trailing() {
+        let message = ParsedMessage::parse("TOPIC #chan :").unwrap();
+
+        assert_eq!(message.params(), ["#chan", ""]);
+        assert_eq!(message.trailing(), Some(""));
+    }
+
+    #[test]
+    fn test_colon_only_matters_at_the_start_of_a_param() {
+        let message = ParsedMessage::parse("USER a:b 0 * :real name").unwrap();
+
+        assert_eq!(message.params(), ["a:b", "0", "*", "real name"]);
+    }
+
+    #[test]
+    fn test_multiple_spaces_between_params() {
+        let message = ParsedMessage::parse(":src   JOIN    #a,#b    key  ").unwrap();
+
+        assert_eq!(message.source, Some("src"));
+        assert_eq!(message.command, "JOIN");
+        assert_eq!(message.params(), ["#a,#b", "key"]);
+    }
+
+    #[test]
+    fn test_numeric_command() {
+        let message = ParsedMessage::parse(":000 001 alice :Welcome").unwrap();
+
+        assert_eq!(message.command, "001");
+        assert_eq!(message.params(), ["alice", "Welcome"]);
+    }
+
+    #[test]
+    fn test_fifteenth_param_takes_the_rest() {
+        let message =
+            ParsedMessage::parse("CMD 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 :17").unwrap();
+
+        assert_eq!(message.params().len(), 15);
+        assert_eq!(message.params()[14], "15 16 :17");
+        assert_eq!(message.trailing(), Some("15 16 :17"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(ParsedMessage::parse(""), Err(ParseError::EmptyMessage));
+        assert_eq!(ParsedMessage::parse("\r\n"), Err(ParseError::EmptyMessage));
+        assert_eq!(ParsedMessage::parse("   "), Err(ParseError::EmptyMessage));
+        assert_eq!(
+            ParsedMessage::parse(":source"),
+            Err(ParseError::MissingCommand)
+        );
+        assert_eq!(
+            ParsedMessage::parse(": PING"),
+            Err(ParseError::MissingCommand)
+        );
+        assert_eq!(
+            ParsedMessage::parse("@a=b"),
+            Err(ParseError::MissingCommand)
+        );
+        assert_eq!(
+            ParsedMessage::parse("PR1VMSG"),
+            Err(ParseError::InvalidCommand)
+        );
+        assert_eq!(
+            ParsedMessage::parse("0001 a"),
+            Err(ParseError::InvalidCommand)
+        );
+        assert_eq!(
+            ParsedMessage::parse("PING a\0b"),
+            Err(ParseError::InvalidCharacter)
+        );
+        assert_eq!(
+            ParsedMessage::parse("PING a\rb"),
+            Err(ParseError::InvalidCharacter)
+        );
+    }
+
+    #[test]
+    fn test_length_limits() {
+        let max_length = format!("PRIVMSG #a :{}", "a".repeat(MAX_LINE_LENGTH - 14));
+        let too_long = format!("{max_length}a");
+
+        assert!(ParsedMessage::parse(&max_length).is_ok());
+        assert_eq!(
+            ParsedMessage::parse(&too_long),
+            Err(ParseError::InputTooLong)
+        );
+
+        // tags don't count towards the 512 byte limit
+        let tagged = format!("@+a={} {max_length}", "b".repeat(4000));
+        assert!(ParsedMessage::parse(&tagged).is_ok());
+
+        let too_many_tags = format!("@+a={} PING", "b".repeat(9000));
+        assert_eq!(
+            ParsedMessage::parse(&too_many_tags),
+            Err(ParseError::TagsTooLong)
+        );
+    }
+
+    fn tag_key() -> impl Strategy<Value = String> {
+        "\\+?[a-z][a-z0-9/.-]{0,10}"
+    }
+
+    fn middle_param() -> impl Strategy<Value = String> {
+        "[^: \r\n\0][^ \r\n\0]{0,10}"
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip_through_irc_response(
+            tags in prop::collection::btree_map(tag_key(), "[^\0]{0,10}", 0..4),
+            source in "[a-zA-Z0-9!@.~]{1,20}",
+            command in "[A-Z]{1,10}|[0-9]{3}",
+            arguments in prop::collection::vec(middle_param(), 0..15),
+            trailing in prop::option::of("[^\r\n\0]{0,40}"),
+        ) {
+            let response = IrcResponse {
+                tags: tags.clone().into_iter().collect::<Tags>(),
+                sender: Some(source.clone()),
+                command: command.clone(),
+                receiver: None,
+                arguments: arguments.clone(),
+                message: trailing.clone().unwrap_or_default(),
+            };
+            let line = response.to_line("unused", trailing.is_some());
+            let message = ParsedMessage::parse(&line).unwrap();
+
+            let mut expected_params = arguments;
+            if let Some(trailing) = trailing {
+                expected_params.push(trailing);
+            }
+
+            prop_assert_eq!(message.tags(), tags.into_iter().collect::<Tags>());
+            prop_assert_eq!(message.source, Some(source.as_str()));
+            prop_assert_eq!(message.command, command.as_str());
+            prop_assert_eq!(message.owned_params(), expected_params);
+        }
+
+        #[test]
+        fn test_parser_never_panics(line in "\\PC{0,600}") {
+            let _ = ParsedMessage::parse(&line);
+        }
+    }
+}
@@ -0,0 +1,402 @@
+//! Delivery of messages to the connections they concern. Every connection has its own bounded
+//! queue, and a connection which stops reading from it gets dropped once the queue is full instead
+//! of holding up everyone else.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use once_cell::sync::Lazy;
+use tokio::sync::{
+    Mutex, Notify,
+    mpsc::{self, error::TrySendError},
+};
+
+use crate::{
+    chanmodes::{MemberStatus, split_status_prefix},
+    error_structs::ListenerError,
+    messages::{KillMessage, Message, QuitMessage, Receiver},
+    state::NETWORK,
+    ts6::structs::UserId,
+};
+
+/// How many messages can pile up for a connection before it gets disconnected
+pub const SENDQ_LENGTH: usize = 512;
+
+/// Queues of local, registered users
+pub static CLIENT_QUEUES: Lazy<Mutex<HashMap<UserId, Outbound>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+/// Queues of directly linked servers, by connection id
+pub static SERVER_QUEUES: Lazy<Mutex<HashMap<u64, Outbound>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The sending end of a connection's queue.
+#[derive(Clone, Debug)]
+pub struct Outbound {
+    pub id: u64,
+    sender: mpsc::Sender<Message>,
+    sendq_exceeded: Arc<Notify>,
+}
+
+/// The receiving end of a connection's queue, owned by the connection itself.
+pub struct Inbound {
+    receiver: mpsc::Receiver<Message>,
+    sendq_exceeded: Arc<Notify>,
+}
+
+pub fn queue() -> (Outbound, Inbound) {
+    let (sender, receiver) = mpsc::channel(SENDQ_LENGTH);
+    let sendq_exceeded = Arc::new(Notify::new());
+
+    (
+        Outbound {
+            id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            sender,
+            sendq_exceeded: sendq_exceeded.clone(),
+        },
+        Inbound {
+            receiver,
+            sendq_exceeded,
+        },
+    )
+}
+
+impl Outbound {
+    pub fn push(&self, message: Message) {
+        match self.sender.try_send(message) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => self.sendq_exceeded.notify_one(),
+            // the connection is going away anyway
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+}
+
+impl Inbound {
+    /// Next message for this connection. Cancel safe.
+    pub async fn recv(&mut self) -> Result<Message, ListenerError> {
+        tokio::select! {
+            biased;
+
+            _ = self.sendq_exceeded.notified() => Err(ListenerError::SendQExceeded),
+            message = self.receiver.recv() => message.ok_or(ListenerError::ConnectionError),
+        }
+    }
+}
+
+/// Hand a message to everyone who should see it.
+pub async fn route(message: Message) {
+    match &message {
+        Message::PrivMessage(privmsg) | Message::TagMessage(privmsg) => {
+            let from_local_user = NETWORK.is_local(&privmsg.sender.user_id);
+
+            match &privmsg.receiver {
+                Receiver::ChannelName(target) => {
+                    // @#channel only goes to the members with at least that status
+                    let (status, name) = split_status_prefix(target);
+                    let mut members = NETWORK
+                        .get_channel(name)
+                        .map(|channel| channel.members_with_status(status))
+                        .unwrap_or_default();
+                    // don't echo channel messages back to whoever sent them
+                    members.retain(|member| *member != privmsg.sender.user_id);
+
+                    send_to_users(&members, &message).await;
+
+                    if from_local_user {
+                        send_to_servers(&message).await;
+                    }
+                }
+
+                Receiver::UserId(user_id) => {
+                    if !send_to_users(std::slice::from_ref(user_id), &message).await
+                        && from_local_user
+                    {
+                        send_to_servers(&message).await;
+                    }
+                }
+
+                Receiver::Username(nickname) => match NETWORK.find_nick(nickname) {
+                    Some(user_id) if NETWORK.is_local(&user_id) => {
+                        send_to_users(&[user_id], &message).await;
+                    }
+                    Some(_) if from_local_user => send_to_servers(&message).await,
+                    _ => {}
+                },
+            }
+        }
+
+        Message::ChanJoinMessage(join) => {
+            let mut members = NETWORK.channel_members(&join.channel.name);
+            if !members.contains(&join.sender.user_id) {
+                members.push(join.sender.user_id.clone());
+            }
+
+            send_to_users(&members, &message).await;
+        }
+
+        Message::ChannelModeMessage(change) => {
+            let members = change.channel.members_with_status(None);
+            send_to_users(&members, &message).await;
+
+            if NETWORK.is_local(&change.sender.user_id) {
+                send_to_servers(&message).await;
+            }
+        }
+
+        Message::KickMessage(kick) => {
+            let members = kick.channel.members_with_status(None);
+            send_to_users(&members, &message).await;
+
+            if NETWORK.is_local(&kick.sender.user_id) {
+                send_to_servers(&message).await;
+            }
+        }
+
+        Message::InviteMessage(invite) => {
+            // members only see it with invite-notify, which gets checked on delivery
+            let mut recipients = invite.channel.members_with_status(None);
+            recipients.push(invite.target.user_id.clone());
+            send_to_users(&recipients, &message).await;
+
+            if NETWORK.is_local(&invite.sender.user_id) {
+                send_to_servers(&message).await;
+            }
+        }
+
+        Message::WhoisRequest(_) => send_to_servers(&message).await,
+
+        Message::ServerReply(reply) => {
+            send_to_users(std::slice::from_ref(&reply.target), &message).await;
+        }
+
+        Message::KnockMessage(knock) => {
+            let operators = knock
+                .channel
+                .members_with_status(Some(MemberStatus::HalfOp));
+            send_to_users(&operators, &message).await;
+        }
+
+        Message::TopicMessage(topic) => {
+            let members = topic.channel.members_with_status(None);
+            send_to_users(&members, &message).await;
+
+            if topic
+                .user_id
+                .as_ref()
+                .is_some_and(|user_id| NETWORK.is_local(user_id))
+            {
+                send_to_servers(&message).await;
+            }
+        }
+
+        // these only ever come from a server, and we don't relay between servers
+        Message::BanMaskMessage(bmask) => {
+            let members = bmask.channel.members_with_status(None);
+            send_to_users(&members, &message).await;
+        }
+
+        Message::PartMessage(part) => {
+            let members = part.channel.members_with_status(None);
+            send_to_users(&members, &message).await;
+
+            if NETWORK.is_local(&part.sender.user_id) {
+                send_to_servers(&message).await;
+            }
+        }
+
+        Message::NetJoinMessage(_) => send_to_servers(&message).await,
+
+        Message::NickChangeMessage(change) => {
+            // the user sees their own nick change too
+            let mut peers = channel_peers(&change.user.user_id);
+            peers.push(change.user.user_id.clone());
+
+            send_to_users(&peers, &message).await;
+
+            if NETWORK.is_local(&change.user.user_id) {
+                send_to_servers(&message).await;
+            }
+        }
+
+        Message::AwayMessage(away) => {
+            // peers only see it with away-notify, which gets checked on delivery
+            send_to_users(&channel_peers(&away.user.user_id), &message).await;
+
+            if NETWORK.is_local(&away.user.user_id) {
+                send_to_servers(&message).await;
+            }
+        }
+
+        Message::QuitMessage(quit) => {
+            send_to_users(&channel_peers(&quit.user.user_id), &message).await;
+
+            if NETWORK.is_local(&quit.user.user_id) {
+                send_to_servers(&message).await;
+            }
+        }
+
+        Message::KillMessage(kill) => {
+            // the user gets it too, so their connection can close
+            let mut user_ids = channel_peers(&kill.user.user_id);
+            user_ids.push(kill.user.user_id.clone());
+            send_to_users(&user_ids, &message).await;
+
+            send_to_servers(&message).await;
+        }
+
+        Message::CapChangeMessage(_) => {
+            for outbound in CLIENT_QUEUES.lock().await.values() {
+                outbound.push(message.clone());
+            }
+        }
+    }
+}
+
+/// Tell everyone sharing a channel with the user (and linked servers, if the user is ours) that
+/// they quit, then take them off the network.
+pub async fn quit(user_id: &UserId, reason: &str) {
+    let Some(user) = NETWORK.get_user(user_id) else {
+        return;
+    };
+
+    route(Message::QuitMessage(QuitMessage {
+        user,
+        reason: reason.to_owned(),
+    }))
+    .await;
+
+    NETWORK.remove_user(user_id);
+}
+
+/// Take a user off the network right away, no matter which server they're on. Everyone else sees
+/// them quit, and a local user gets disconnected.
+pub async fn kill(user_id: &UserId, reason: &str) {
+    let Some(user) = NETWORK.get_user(user_id) else {
+        return;
+    };
+
+    route(Message::KillMessage(KillMessage {
+        user,
+        reason: reason.to_owned(),
+    }))
+    .await;
+
+    NETWORK.remove_user(user_id);
+}
+
+/// Everyone sharing at least one channel with the user, each only once.
+fn channel_peers(user_id: &UserId) -> Vec<UserId> {
+    NETWORK
+        .user_channels(user_id)
+        .iter()
+        .flat_map(|channel| NETWORK.channel_members(channel))
+        .filter(|member| member != user_id)
+        .collect::<BTreeSet<UserId>>()
+        .into_iter()
+        .collect()
+}
+
+/// Returns whether any of the users was connected here.
+async fn send_to_users(user_ids: &[UserId], message: &Message) -> bool {
+    let queues = CLIENT_QUEUES.lock().await;
+    let mut delivered = false;
+
+    for user_id in user_ids {
+        if let Some(outbound) = queues.get(user_id) {
+            outbound.push(message.clone());
+            delivered = true;
+        }
+    }
+
+    delivered
+}
+
+async fn send_to_servers(message: &Message) {
+    for outbound in SERVER_QUEUES.lock().await.values() {
+        outbound.push(message.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        capabilities::Capability,
+        error_structs::ListenerError,
+        messages::{CapChangeMessage, Message},
+        routing::{SENDQ_LENGTH, queue},
+        test_client::TestClient,
+    };
+
+    #[tokio::test]
+    async fn test_targeted_delivery() {
+        let mut alice = TestClient::register("ralice").await;
+        let mut bob = TestClient::register("rbob").await;
+        let mut carol = TestClient::register("rcarol").await;
+
+        alice.send("PRIVMSG rbob :hi bob").await;
+        assert_eq!(
+            bob.recv().await.unwrap(),
+            ":ralice!~ralice@unimplement.ed PRIVMSG rbob :hi bob"
+        );
+
+        alice.join_with(&mut bob, "#routing").await;
+
+        bob.send("PRIVMSG #routing :hi channel").await;
+        assert_eq!(
+            alice.recv().await.unwrap(),
+            ":rbob!~rbob@unimplement.ed PRIVMSG #routing :hi channel"
+        );
+
+        // carol neither got the private nor the channel message
+        assert_eq!(carol.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_cleanup() {
+        let (mut alice, bob) = TestClient::pair_in_channel("qalice", "qbob", "#quit").await;
+
+        drop(bob);
+        assert_eq!(
+            alice.recv().await.unwrap(),
+            ":qbob!~qbob@unimplement.ed QUIT :Connection closed"
+        );
+
+        // the nickname is free again and the channel only has alice left
+        let mut bob = TestClient::register("qbob").await;
+        bob.send("JOIN #quit").await;
+        let names = bob.recv_until("353").await;
+        assert!(names.last().unwrap().ends_with(":@qalice qbob"));
+    }
+
+    #[tokio::test]
+    async fn test_sendq_exceeded() {
+        let (outbound, mut inbound) = queue();
+        let message = Message::CapChangeMessage(CapChangeMessage {
+            added: true,
+            capabilities: vec![Capability {
+                name: "test".to_owned(),
+                value: None,
+            }],
+        });
+
+        for _ in 0..SENDQ_LENGTH {
+            outbound.push(message.clone());
+        }
+        assert!(inbound.recv().await.is_ok());
+
+        // one more fits now, but the next one overflows
+        outbound.push(message.clone());
+        outbound.push(message.clone());
+        assert!(matches!(
+            inbound.recv().await,
+            Err(ListenerError::SendQExceeded)
+        ));
+    }
+}
@@ -0,0 +1,431 @@
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use rand::{Rng, distr::Alphanumeric};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    accounts::{
+        Credentials, constant_time_eq, get_credentials, get_credentials_by_certfp, hmac_sha256,
+    },
+    capabilities::register_capability,
+};
+
+/// AUTHENTICATE payloads get split into chunks of this size
+pub const SASL_CHUNK_SIZE: usize = 400;
+/// upper limit for a client response, after being reassembled from its chunks
+pub const MAX_SASL_RESPONSE_LENGTH: usize = 8192;
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub enum SaslMechanism {
+    Plain,
+    ScramSha256,
+    External,
+}
+
+impl SaslMechanism {
+    pub const ALL: &[SaslMechanism] = &[
+        SaslMechanism::Plain,
+        SaslMechanism::ScramSha256,
+        SaslMechanism::External,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|mechanism| mechanism.name().eq_ignore_ascii_case(name))
+            .copied()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SaslMechanism::Plain => "PLAIN",
+            SaslMechanism::ScramSha256 => "SCRAM-SHA-256",
+            SaslMechanism::External => "EXTERNAL",
+        }
+    }
+
+    /// Comma separated list of all mechanisms, as used in the `sasl` capability and
+    /// RPL_SASLMECHS
+    pub fn list() -> String {
+        Self::ALL
+            .iter()
+            .map(|mechanism| mechanism.name())
+            .collect::<Vec<&str>>()
+            .join(",")
+    }
+}
+
+/// An in-progress SASL exchange of a single connection.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct SaslSession {
+    pub mechanism: SaslMechanism,
+    /// base64 chunks received so far for the current client response
+    pub buffer: String,
+    scram: Option<ScramState>,
+    /// fingerprint of the client's TLS certificate, used by EXTERNAL
+    certfp: Option<String>,
+}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+struct ScramState {
+    gs2_header: String,
+    client_first_bare: String,
+    server_first: String,
+    nonce: String,
+    credentials: Credentials,
+    verified: bool,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum SaslStep {
+    /// send a challenge to the client and wait for its next response
+    Challenge(Vec<u8>),
+    /// the client logged into the given account
+    Success(String),
+    Failure,
+}
+
+pub async fn register_capabilities() {
+    register_capability("sasl", Some(SaslMechanism::list())).await;
+}
+
+impl SaslSession {
+    pub fn new(mechanism: SaslMechanism, certfp: Option<String>) -> Self {
+        Self {
+            mechanism,
+            buffer: String::new(),
+            scram: None,
+            certfp,
+        }
+    }
+
+    /// Feed a complete (decoded) client response into the exchange.
+    pub async fn step(&mut self, response: &[u8]) -> SaslStep {
+        match self.mechanism {
+            SaslMechanism::Plain => plain(response).await,
+            SaslMechanism::ScramSha256 => match self.scram.clone() {
+                None => {
+                    let nonce = rand::rng()
+                        .sample_iter(&Alphanumeric)
+                        .take(24)
+                        .map(char::from)
+                        .collect::<String>();
+
+                    self.scram_client_first(response, &nonce).await
+                }
+                Some(state) => self.scram_client_final(response, state),
+            },
+            SaslMechanism::External => external(response, self.certfp.as_deref()).await,
+        }
+    }
+
+    async fn scram_client_first(&mut self, response: &[u8], server_nonce: &str) -> SaslStep {
+        let Ok(response) = std::str::from_utf8(response) else {
+            return SaslStep::Failure;
+        };
+
+        // gs2-header is "n,," or "y,," optionally with an authzid, we don't do channel binding
+        let mut parts = response.splitn(3, ',');
+        let (Some(cbind_flag), Some(authzid), Some(client_first_bare)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return SaslStep::Failure;
+        };
+
+        if cbind_flag != "n" && cbind_flag != "y" {
+            return SaslStep::Failure;
+        }
+
+        let mut username = None;
+        let mut client_nonce = None;
+
+        for attribute in client_first_bare.split(',') {
+            match attribute.split_once('=') {
+                Some(("n", value)) => username = Some(scram_unescape(value)),
+                Some(("r", value)) => client_nonce = Some(value.to_owned()),
+                // mandatory extensions we don't know about
+                Some(("m", _)) => return SaslStep::Failure,
+                _ => {}
+            }
+        }
+
+        let (Some(username), Some(client_nonce)) = (username, client_nonce) else {
+            return SaslStep::Failure;
+        };
+
+        if let Some(authzid) = authzid.strip_prefix("a=")
+            && scram_unescape(authzid) != username
+        {
+            return SaslStep::Failure;
+        }
+
+        let Some(credentials) = get_credentials(&username).await else {
+            return SaslStep::Failure;
+        };
+
+        let nonce = format!("{client_nonce}{server_nonce}");
+        let server_first = format!(
+            "r={nonce},s={},i={}",
+            credentials.salt, credentials.iterations
+        );
+
+        self.scram = Some(ScramState {
+            gs2_header: format!("{cbind_flag},{authzid},"),
+            client_first_bare: client_first_bare.to_owned(),
+            server_first: server_first.clone(),
+            nonce,
+            credentials,
+            verified: false,
+        });
+
+        SaslStep::Challenge(server_first.into_bytes())
+    }
+
+    fn scram_client_final(&mut self, response: &[u8], state: ScramState) -> SaslStep {
+        // the client acknowledges our server-final-message with an empty response
+        if state.verified {
+            return if response.is_empty() {
+                SaslStep::Success(state.credentials.name)
+            } else {
+                SaslStep::Failure
+            };
+        }
+
+        let Ok(response) = std::str::from_utf8(response) else {
+            return SaslStep::Failure;
+        };
+
+        let Some((without_proof, proof)) = response.rsplit_once(",p=") else {
+            return SaslStep::Failure;
+        };
+
+        let mut channel_binding = None;
+        let mut nonce = None;
+
+        for attribute in without_proof.split(',') {
+            match attribute.split_once('=') {
+                Some(("c", value)) => channel_binding = Some(value),
+                Some(("r", value)) => nonce = Some(value),
+                _ => {}
+            }
+        }
+
+        if channel_binding != Some(BASE64.encode(&state.gs2_header).as_str())
+            || nonce != Some(state.nonce.as_str())
+        {
+            return SaslStep::Failure;
+        }
+
+        let (Ok(proof), Ok(stored_key), Ok(server_key)) = (
+            BASE64.decode(proof),
+            BASE64.decode(&state.credentials.stored_key),
+            BASE64.decode(&state.credentials.server_key),
+        ) else {
+            return SaslStep::Failure;
+        };
+
+        let auth_message = format!(
+            "{},{},{}",
+            state.client_first_bare, state.server_first, without_proof
+        );
+        let client_signature = hmac_sha256(&stored_key, auth_message.as_bytes());
+
+        if proof.len() != client_signature.len() {
+            return SaslStep::Failure;
+        }
+
+        let client_key = proof
+            .iter()
+            .zip(client_signature)
+            .map(|(x, y)| x ^ y)
+            .collect::<Vec<u8>>();
+
+        if !constant_time_eq(&Sha256::digest(client_key), &stored_key) {
+            return SaslStep::Failure;
+        }
+
+        let server_signature = hmac_sha256(&server_key, auth_message.as_bytes());
+
+        self.scram = Some(ScramState {
+            verified: true,
+            ..state
+        });
+
+        SaslStep::Challenge(format!("v={}", BASE64.encode(server_signature)).into_bytes())
+    }
+}
+
+async fn plain(response: &[u8]) -> SaslStep {
+    // authzid \0 authcid \0 password
+    let parts = response.split(|x| *x == 0).collect::<Vec<&[u8]>>();
+    let [authzid, authcid, password] = parts.as_slice() else {
+        return SaslStep::Failure;
+    };
+
+    let (Ok(authzid), Ok(authcid), Ok(password)) = (
+        std::str::from_utf8(authzid),
+        std::str::from_utf8(authcid),
+        std::str::from_utf8(password),
+    ) else {
+        return SaslStep::Failure;
+    };
+
+    if !authzid.is_empty() && authzid != authcid {
+        return SaslStep::Failure;
+    }
+
+    match get_credentials(authcid).await {
+        Some(credentials) if credentials.verify_password(password) => {
+            SaslStep::Success(credentials.name)
+        }
+        _ => SaslStep::Failure,
+    }
+}
+
+async fn external(response: &[u8], certfp: Option<&str>) -> SaslStep {
+    let (Some(certfp), Ok(authzid)) = (certfp, std::str::from_utf8(response)) else {
+        return SaslStep::Failure;
+    };
+
+    match get_credentials_by_certfp(certfp).await {
+        Some(credentials)
+            if authzid.is_empty() || authzid.eq_ignore_ascii_case(&credentials.name) =>
+        {
+            SaslStep::Success(credentials.name)
+        }
+        _ => SaslStep::Failure,
+    }
+}
+
+fn scram_unescape(value: &str) -> String {
+    value.replace("=2C", ",").replace("=3D", "=")
+}
+
+/// Split an outgoing payload into base64 AUTHENTICATE chunks. An empty payload (or one that's an
+/// exact multiple of the chunk size) is terminated with `+`.
+pub fn encode_chunks(payload: &[u8]) -> Vec<String> {
+    let encoded = BASE64.encode(payload);
+    let mut chunks = encoded
+        .as_bytes()
+        .chunks(SASL_CHUNK_SIZE)
+        .map(|chunk| String::from_utf8_lossy(chunk).to_string())
+        .collect::<Vec<String>>();
+
+    if encoded.len().is_multiple_of(SASL_CHUNK_SIZE) {
+        chunks.push("+".to_owned());
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+
+    use crate::{
+        accounts::{CredentialBackend, Credentials, set_credential_backend},
+        sasl::{SaslMechanism, SaslSession, SaslStep, encode_chunks},
+    };
+
+    struct TestBackend;
+
+    #[async_trait]
+    impl CredentialBackend for TestBackend {
+        async fn get_credentials(&self, account: &str) -> Option<Credentials> {
+            // credentials of the RFC 7677 example exchange, password "pencil"
+            (account == "user").then(|| Credentials {
+                name: "user".to_owned(),
+                salt: "W22ZaJ0SNY7soEsUEjb6gQ==".to_owned(),
+                iterations: 4096,
+                stored_key: "WG5d8oPm3OtcPnkdi4Uo7BkeZkBFzpcXkuLmtbsT4qY=".to_owned(),
+                server_key: "wfPLwcE6nTWhTAmQ7tl2KeoiWGPlZqQxSrmfPwDl2dU=".to_owned(),
+                certfps: vec!["abcdef".to_owned()],
+            })
+        }
+
+        async fn get_credentials_by_certfp(&self, certfp: &str) -> Option<Credentials> {
+            self.get_credentials("user")
+                .await
+                .filter(|credentials| credentials.certfps.iter().any(|x| x == certfp))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sasl_mechanisms() {
+        set_credential_backend(Arc::new(TestBackend)).await;
+
+        // PLAIN
+        let mut session = SaslSession::new(SaslMechanism::Plain, None);
+        assert_eq!(
+            session.step(b"\0user\0pencil").await,
+            SaslStep::Success("user".to_owned())
+        );
+        let mut session = SaslSession::new(SaslMechanism::Plain, None);
+        assert_eq!(session.step(b"\0user\0pen").await, SaslStep::Failure);
+        let mut session = SaslSession::new(SaslMechanism::Plain, None);
+        assert_eq!(
+            session.step(b"other\0user\0pencil").await,
+            SaslStep::Failure
+        );
+
+        // EXTERNAL
+        let mut session = SaslSession::new(SaslMechanism::External, Some("abcdef".to_owned()));
+        assert_eq!(
+            session.step(b"").await,
+            SaslStep::Success("user".to_owned())
+        );
+        let mut session = SaslSession::new(SaslMechanism::External, Some("abcdef".to_owned()));
+        assert_eq!(session.step(b"other").await, SaslStep::Failure);
+        let mut session = SaslSession::new(SaslMechanism::External, None);
+        assert_eq!(session.step(b"").await, SaslStep::Failure);
+
+        // SCRAM-SHA-256, using the example exchange from RFC 7677
+        let mut session = SaslSession::new(SaslMechanism::ScramSha256, None);
+        assert_eq!(
+            session
+                .scram_client_first(
+                    b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO",
+                    "%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0"
+                )
+                .await,
+            SaslStep::Challenge(
+                b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"
+                    .to_vec()
+            )
+        );
+        assert_eq!(
+            session
+                .step(b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=")
+                .await,
+            SaslStep::Challenge(b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=".to_vec())
+        );
+        assert_eq!(
+            session.step(b"").await,
+            SaslStep::Success("user".to_owned())
+        );
+
+        // wrong proof
+        let mut session = SaslSession::new(SaslMechanism::ScramSha256, None);
+        session.scram_client_first(b"n,,n=user,r=abc", "def").await;
+        assert_eq!(
+            session
+                .step(format!("c=biws,r=abcdef,p={}", BASE64.encode([0u8; 32])).as_bytes())
+                .await,
+            SaslStep::Failure
+        );
+    }
+
+    #[test]
+    fn test_encode_chunks() {
+        assert_eq!(encode_chunks(b""), vec!["+"]);
+        assert_eq!(encode_chunks(b"abc"), vec!["YWJj"]);
+
+        // 300 bytes are exactly 400 base64 characters
+        let chunks = encode_chunks(&[0u8; 300]);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].len(), 400);
+        assert_eq!(chunks[1], "+");
+    }
+}
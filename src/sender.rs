@@ -1,12 +1,12 @@
-use tokio::{
-    io::{AsyncWriteExt, BufWriter},
-    net::TcpStream,
+use crate::{
+    connection::IrcSink,
+    error_structs::SenderError,
+    tags::{Tags, serialize_tags},
 };
 
-use crate::error_structs::SenderError;
-
 #[derive(Clone, Debug)]
 pub struct IrcResponse {
+    pub tags: Tags,
     pub sender: Option<String>,
     pub command: String,
     pub receiver: Option<String>,
@@ -18,40 +18,128 @@ pub struct IrcResponse {
 #[repr(u16)]
 pub enum IrcResponseCodes {
     UnknownCommand = 421,
-    Welcome = 001,
-    YourHost = 002,
-    MyInfo = 004,
-    ISupport = 005,
+    InvalidCapCmd = 410,
+    InputTooLong = 417,
+    Welcome = 1,
+    YourHost = 2,
+    MyInfo = 4,
+    ISupport = 5,
     NoMotd = 422,
+    WhoisUser = 311,
+    WhoisServer = 312,
+    WhoisOperator = 313,
+    WhowasUser = 314,
+    EndOfWho = 315,
+    WhoisIdle = 317,
+    EndOfWhois = 318,
+    WhoisChannels = 319,
+    WhoisAccount = 330,
+    ListStart = 321,
+    List = 322,
+    ListEnd = 323,
     NoTopic = 331,
+    WhoisCertFp = 276,
+    Topic = 332,
+    TopicWhoTime = 333,
+    Inviting = 341,
+    WhoReply = 352,
     NameReply = 353,
+    WhoSpcRpl = 354,
     EndOfNames = 366,
+    EndOfWhowas = 369,
+    UModeIs = 221,
+    Away = 301,
+    UserHost = 302,
+    IsOn = 303,
+    UnAway = 305,
+    NowAway = 306,
+    ChannelModeIs = 324,
+    CreationTime = 329,
+    UserIp = 340,
+    InviteList = 346,
+    EndOfInviteList = 347,
+    ExceptList = 348,
+    EndOfExceptList = 349,
+    BanList = 367,
+    EndOfBanList = 368,
+    NoSuchNick = 401,
+    NoSuchChannel = 403,
+    CannotSendToChan = 404,
+    TooManyChannels = 405,
+    WasNoSuchNick = 406,
+    NoRecipient = 411,
+    NoTextToSend = 412,
+    NoNicknameGiven = 431,
+    ErroneousNickname = 432,
+    NicknameInUse = 433,
+    UserNotInChannel = 441,
+    NotOnChannel = 442,
+    UserOnChannel = 443,
+    NotRegistered = 451,
+    ChannelIsFull = 471,
+    UnknownMode = 472,
+    InviteOnlyChan = 473,
+    BannedFromChan = 474,
+    BadChannelKey = 475,
+    BanListFull = 478,
+    NoPrivileges = 481,
+    ChanOPrivsNeeded = 482,
+    UModeUnknownFlag = 501,
+    UsersDontMatch = 502,
+    WhoisSecure = 671,
+    Knock = 710,
+    KnockDelivered = 711,
+    ChanOpen = 713,
+    KnockOnChan = 714,
+    NeedMoreParams = 461,
+    YoureOper = 381,
+    PasswdMismatch = 464,
+    NoOperHost = 491,
+    LoggedIn = 900,
+    LoggedOut = 901,
+    NickLocked = 902,
+    SaslSuccess = 903,
+    SaslFail = 904,
+    SaslTooLong = 905,
+    SaslAborted = 906,
+    SaslAlready = 907,
+    SaslMechs = 908,
 }
 
 impl IrcResponse {
-    pub async fn send(
-        &self,
-        hostname: &str,
-        writer: &mut BufWriter<TcpStream>,
-        prepend_column: bool,
-    ) -> Result<(), SenderError> {
+    /// Serialize the response into a single line, including the trailing CRLF.
+    pub fn to_line(&self, hostname: &str, prepend_column: bool) -> String {
         let sender = format!(":{}", self.sender.clone().unwrap_or(hostname.to_string()));
         let mut full_response = Vec::new();
 
+        if !self.tags.is_empty() {
+            full_response.push(serialize_tags(&self.tags));
+        }
         full_response.push(sender);
         full_response.push(self.command.clone());
         full_response.extend_from_slice(&self.arguments);
         if let Some(receiver) = self.receiver.clone() {
             full_response.push(receiver);
         }
+        let message = self.message.trim_end_matches(['\r', '\n']);
         if prepend_column {
-            full_response.push(format!(":{}\r\n", self.message.trim_end()));
-        } else {
-            full_response.push(format!("{}\r\n", self.message.trim_end()));
+            full_response.push(format!(":{message}"));
+        } else if !message.is_empty() {
+            full_response.push(message.to_owned());
         }
 
-        writer.write_all(full_response.join(" ").as_bytes()).await?;
-        writer.flush().await?;
+        format!("{}\r\n", full_response.join(" "))
+    }
+
+    pub async fn send(
+        &self,
+        hostname: &str,
+        writer: &mut dyn IrcSink,
+        prepend_column: bool,
+    ) -> Result<(), SenderError> {
+        let full_response = self.to_line(hostname, prepend_column);
+
+        writer.send_line(&full_response).await?;
 
         println!("sending: {full_response:#?}");
 
@@ -63,15 +151,17 @@ impl From<IrcResponseCodes> for String {
     fn from(value: IrcResponseCodes) -> Self {
         let value = value as u16;
 
-        value.to_string()
+        // numerics are always sent as three digits
+        format!("{value:03}")
     }
 }
 
 impl IrcResponseCodes {
-    pub fn into_irc_response(&self, receiver: String, message: String) -> IrcResponse {
+    pub fn into_irc_response(self, receiver: String, message: String) -> IrcResponse {
         IrcResponse {
+            tags: Tags::new(),
             sender: None,
-            command: (*self).into(),
+            command: self.into(),
             arguments: Vec::new(),
             receiver: Some(receiver),
             message,
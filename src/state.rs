@@ -0,0 +1,677 @@
+//! Everything we know about the network: users (ours and those behind linked servers) and
+//! channels, with indexes for the lookups commands need. Each index is a concurrent map, so
+//! connections don't serialize on one big lock.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use dashmap::{DashMap, DashSet, mapref::entry::Entry};
+use once_cell::sync::Lazy;
+
+use crate::{
+    casemapping::casefold,
+    chanmodes::MemberStatus,
+    channels::{Channel, JoinError},
+    masks,
+    ts6::structs::{ServerId, UserId},
+    user::UserUnwrapped,
+};
+
+pub static NETWORK: Lazy<NetworkState> = Lazy::new(NetworkState::default);
+
+/// How many nicknames WHOWAS remembers
+pub const WHOWAS_LENGTH: usize = 1000;
+
+#[derive(Default)]
+pub struct NetworkState {
+    /// casefolded nickname -> UID
+    nicks: DashMap<String, UserId>,
+    /// UID -> user, for every user on the network
+    users: DashMap<UserId, UserUnwrapped>,
+    /// UIDs of the users connected to us directly
+    local_users: DashSet<UserId>,
+    /// casefolded channel name -> channel
+    channels: DashMap<String, Channel>,
+    /// UID -> casefolded names of the channels the user is in
+    memberships: DashMap<UserId, BTreeSet<String>>,
+    /// UID -> casefolded names of the channels the user is invited to
+    invites: DashMap<UserId, BTreeSet<String>>,
+    /// SID -> the linked servers we know about
+    servers: DashMap<ServerId, LinkedServer>,
+    /// UID -> when our own users connected and last spoke
+    activity: DashMap<UserId, Activity>,
+    /// Users who changed their nickname or left, newest first
+    whowas: Mutex<VecDeque<WhowasEntry>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LinkedServer {
+    pub name: String,
+    pub description: String,
+    /// The server it's linked to, `None` if it's linked to us directly
+    pub uplink: Option<ServerId>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Activity {
+    pub signon: SystemTime,
+    pub last_message: SystemTime,
+}
+
+/// A nickname someone used to have, for WHOWAS
+#[derive(Clone, Debug)]
+pub struct WhowasEntry {
+    pub nickname: String,
+    pub username: String,
+    pub host: String,
+    pub realname: String,
+    /// Name of the server they were on, `None` if it was us
+    pub server: Option<String>,
+    pub until: SystemTime,
+}
+
+/// The nickname is already taken by someone else.
+#[derive(Debug)]
+pub struct NickInUse;
+
+/// Why a user couldn't leave a channel.
+#[derive(Debug, PartialEq)]
+pub enum PartError {
+    NoSuchChannel,
+    NotOnChannel,
+}
+
+impl NetworkState {
+    /// Add a user that just registered with us (`local`) or got introduced by a linked server.
+    pub fn add_user(&self, user: UserUnwrapped, local: bool) -> Result<(), NickInUse> {
+        match self.nicks.entry(casefold(&user.nickname)) {
+            Entry::Occupied(_) => return Err(NickInUse),
+            Entry::Vacant(entry) => {
+                entry.insert(user.user_id.clone());
+            }
+        }
+
+        if local {
+            self.local_users.insert(user.user_id.clone());
+
+            let now = SystemTime::now();
+            self.activity.insert(
+                user.user_id.clone(),
+                Activity {
+                    signon: now,
+                    last_message: now,
+                },
+            );
+        }
+        self.users.insert(user.user_id.clone(), user);
+
+        Ok(())
+    }
+
+    /// Take a user off the network, out of every index and channel. Returns the user and the
+    /// channels they were in.
+    pub fn remove_user(&self, user_id: &UserId) -> Option<(UserUnwrapped, Vec<Channel>)> {
+        let (_, user) = self.users.remove(user_id)?;
+
+        self.nicks
+            .remove_if(&casefold(&user.nickname), |_, owner| owner == user_id);
+        self.record_whowas(&user);
+        self.local_users.remove(user_id);
+        self.activity.remove(user_id);
+        // UIDs get reused, the next user with this one shouldn't inherit invites
+        for name in self
+            .invites
+            .remove(user_id)
+            .map(|(_, channels)| channels)
+            .unwrap_or_default()
+        {
+            if let Some(mut channel) = self.channels.get_mut(&name) {
+                channel.invites.remove(user_id);
+            }
+        }
+
+        let channels = self
+            .memberships
+            .remove(user_id)
+            .map(|(_, channels)| channels)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|name| self.remove_member(name, user_id))
+            .collect();
+
+        Some((user, channels))
+    }
+
+    /// Move a user over to a new nickname, if nobody else holds it.
+    pub fn change_nick(
+        &self,
+        user: &UserUnwrapped,
+        nickname: &str,
+        timestamp: SystemTime,
+    ) -> Result<(), NickInUse> {
+        let old_key = casefold(&user.nickname);
+        let new_key = casefold(nickname);
+
+        match self.nicks.entry(new_key.clone()) {
+            Entry::Occupied(entry) if *entry.get() != user.user_id => return Err(NickInUse),
+            Entry::Occupied(_) => {}
+            Entry::Vacant(entry) => {
+                entry.insert(user.user_id.clone());
+            }
+        }
+
+        if old_key != new_key {
+            self.nicks
+                .remove_if(&old_key, |_, owner| *owner == user.user_id);
+        }
+        self.record_whowas(user);
+
+        if let Some(mut existing) = self.users.get_mut(&user.user_id) {
+            existing.nickname = nickname.to_owned();
+            existing.timestamp = timestamp;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_user(&self, user_id: &UserId) -> Option<UserUnwrapped> {
+        self.users.get(user_id).map(|user| user.clone())
+    }
+
+    /// Everyone on the network
+    pub fn users(&self) -> Vec<UserUnwrapped> {
+        self.users.iter().map(|user| user.clone()).collect()
+    }
+
+    pub fn add_server(&self, server_id: ServerId, server: LinkedServer) {
+        self.servers.insert(server_id, server);
+    }
+
+    pub fn servers(&self) -> Vec<(ServerId, LinkedServer)> {
+        self.servers
+            .iter()
+            .map(|server| (server.key().clone(), server.clone()))
+            .collect()
+    }
+
+    pub fn get_server(&self, server_id: &ServerId) -> Option<LinkedServer> {
+        self.servers.get(server_id).map(|server| server.clone())
+    }
+
+    /// Forget a server that split off, and every server behind it. Returns the users who were on
+    /// them, who are still on the network until they get quit.
+    pub fn remove_server(&self, server_id: &ServerId) -> Vec<UserId> {
+        let mut lost = BTreeSet::from([server_id.clone()]);
+        loop {
+            let behind = self
+                .servers
+                .iter()
+                .filter(|server| {
+                    !lost.contains(server.key())
+                        && server
+                            .uplink
+                            .as_ref()
+                            .is_some_and(|uplink| lost.contains(uplink))
+                })
+                .map(|server| server.key().clone())
+                .collect::<Vec<_>>();
+            if behind.is_empty() {
+                break;
+            }
+            lost.extend(behind);
+        }
+
+        for server_id in &lost {
+            self.servers.remove(server_id);
+        }
+
+        self.users
+            .iter()
+            .filter(|user| lost.contains(&user.key().get_server_id()))
+            .map(|user| user.key().clone())
+            .collect()
+    }
+
+    /// Name of the server a user is on. We're `my_hostname`, and servers we haven't heard the
+    /// name of go by their SID.
+    pub fn server_name(&self, user_id: &UserId, my_hostname: &str) -> String {
+        if self.is_local(user_id) {
+            return my_hostname.to_owned();
+        }
+
+        let server_id = user_id.get_server_id();
+        self.servers
+            .get(&server_id)
+            .map(|server| server.name.clone())
+            .unwrap_or(server_id.to_string())
+    }
+
+    pub fn find_nick(&self, nickname: &str) -> Option<UserId> {
+        self.nicks.get(&casefold(nickname)).map(|x| x.clone())
+    }
+
+    pub fn is_local(&self, user_id: &UserId) -> bool {
+        self.local_users.contains(user_id)
+    }
+
+    /// Replace the stored copy of a user after their state changed. The nickname is kept, as the
+    /// nick index isn't touched here.
+    pub fn update_user(&self, user: UserUnwrapped) {
+        if let Some(mut existing) = self.users.get_mut(&user.user_id) {
+            *existing = UserUnwrapped {
+                nickname: existing.nickname.clone(),
+                ..user
+            };
+        }
+    }
+
+    /// Add a user to a channel, creating it if it doesn't exist yet. Returns the channel after
+    /// joining and whether it was newly created.
+    pub fn join_channel(
+        &self,
+        name: &str,
+        user: &UserUnwrapped,
+        channel_key: Option<&str>,
+    ) -> Result<(Channel, bool), JoinError> {
+        let key = casefold(name);
+        // $j bans look at other channels, which can't happen while holding on to this one, so
+        // they get looked up beforehand, and again if new ones showed up in the meantime
+        let mut banned_from = BTreeMap::new();
+
+        let (channel, created) = loop {
+            let mut created = false;
+            let mut channel = self.channels.entry(key.clone()).or_insert_with(|| {
+                created = true;
+                Channel::new_channel(name.to_owned())
+            });
+
+            let unresolved = channel
+                .extban_channels()
+                .into_iter()
+                .filter(|other| !banned_from.contains_key(other))
+                .collect::<Vec<_>>();
+            if !unresolved.is_empty() {
+                drop(channel);
+                for other in unresolved {
+                    let banned = self
+                        .get_channel(&other)
+                        .is_some_and(|other| masks::banned_directly(&other, user));
+                    banned_from.insert(other, banned);
+                }
+                continue;
+            }
+
+            // checked while holding the channel, so nothing can change in between
+            channel.check_join(user, channel_key, &|other| {
+                banned_from
+                    .get(&casefold(other))
+                    .copied()
+                    .unwrap_or_default()
+            })?;
+
+            // whoever creates a channel gets to run it
+            let statuses = if created {
+                BTreeSet::from([MemberStatus::Op])
+            } else {
+                BTreeSet::new()
+            };
+            channel.members.insert(user.user_id.clone(), statuses);
+            channel.invites.remove(&user.user_id);
+
+            break (channel.clone(), created);
+        };
+
+        if let Some(mut invited) = self.invites.get_mut(&user.user_id) {
+            invited.remove(&key);
+        }
+        self.memberships
+            .entry(user.user_id.clone())
+            .or_default()
+            .insert(key);
+
+        Ok((channel, created))
+    }
+
+    /// Let a user past +i until they join the channel, unless `accept` turns the invite down.
+    /// Returns the channel after the invite.
+    pub fn invite(
+        &self,
+        name: &str,
+        user_id: &UserId,
+        accept: impl FnOnce(&Channel) -> bool,
+    ) -> Option<Channel> {
+        let key = casefold(name);
+
+        let channel = {
+            let mut channel = self.channels.get_mut(&key)?;
+            if !accept(&channel) {
+                return None;
+            }
+            channel.invites.insert(user_id.clone());
+
+            channel.clone()
+        };
+        self.invites.entry(user_id.clone()).or_default().insert(key);
+
+        Some(channel)
+    }
+
+    pub fn get_channel(&self, name: &str) -> Option<Channel> {
+        self.channels
+            .get(&casefold(name))
+            .map(|channel| channel.clone())
+    }
+
+    /// Names of all channels, taken without keeping the registry locked.
+    pub fn channel_names(&self) -> Vec<String> {
+        self.channels.iter().map(|x| x.name.clone()).collect()
+    }
+
+    /// Change a channel in place, returning what `change` returned, or `None` if there's no such
+    /// channel.
+    pub fn update_channel<R>(
+        &self,
+        name: &str,
+        change: impl FnOnce(&mut Channel) -> R,
+    ) -> Option<R> {
+        self.channels
+            .get_mut(&casefold(name))
+            .map(|mut channel| change(&mut channel))
+    }
+
+    /// Take a user out of a channel, deleting the channel once it's empty. Returns the channel as
+    /// it was before, so everyone who was in it can be told.
+    pub fn part_channel(&self, name: &str, user_id: &UserId) -> Result<Channel, PartError> {
+        let key = casefold(name);
+
+        if !self.channels.contains_key(&key) {
+            return Err(PartError::NoSuchChannel);
+        }
+
+        let was_member = self
+            .memberships
+            .get_mut(user_id)
+            .is_some_and(|mut channels| channels.remove(&key));
+        if !was_member {
+            return Err(PartError::NotOnChannel);
+        }
+
+        self.remove_member(&key, user_id)
+            .ok_or(PartError::NoSuchChannel)
+    }
+
+    pub fn channel_members(&self, name: &str) -> Vec<UserId> {
+        self.channels
+            .get(&casefold(name))
+            .map(|channel| channel.members.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Note that a local user sent a message, which resets their idle time.
+    pub fn touch(&self, user_id: &UserId) {
+        if let Some(mut activity) = self.activity.get_mut(user_id) {
+            activity.last_message = SystemTime::now();
+        }
+    }
+
+    pub fn activity(&self, user_id: &UserId) -> Option<Activity> {
+        self.activity.get(user_id).map(|activity| *activity)
+    }
+
+    /// Who used a nickname before, newest first
+    pub fn whowas(&self, nickname: &str) -> Vec<WhowasEntry> {
+        let nickname = casefold(nickname);
+
+        self.whowas
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| casefold(&entry.nickname) == nickname)
+            .cloned()
+            .collect()
+    }
+
+    fn record_whowas(&self, user: &UserUnwrapped) {
+        let server = (!self.is_local(&user.user_id)).then(|| self.server_name(&user.user_id, ""));
+        let mut whowas = self.whowas.lock().unwrap();
+
+        whowas.push_front(WhowasEntry {
+            nickname: user.nickname.clone(),
+            username: user.username.clone(),
+            host: user.host(),
+            realname: user.realname.clone(),
+            server,
+            until: SystemTime::now(),
+        });
+        whowas.truncate(WHOWAS_LENGTH);
+    }
+
+    /// Casefolded names of the channels a user is in
+    pub fn user_channels(&self, user_id: &UserId) -> BTreeSet<String> {
+        self.memberships
+            .get(user_id)
+            .map(|channels| channels.clone())
+            .unwrap_or_default()
+    }
+
+    /// Remove a user from a channel, deleting the channel once it's empty. Returns the channel as
+    /// it was before.
+    fn remove_member(&self, key: &str, user_id: &UserId) -> Option<Channel> {
+        let channel = {
+            let mut channel = self.channels.get_mut(key)?;
+            let before = channel.clone();
+            channel.remove_member(user_id);
+
+            before
+        };
+
+        self.channels
+            .remove_if(key, |_, channel| channel.members.is_empty());
+
+        Some(channel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, time::SystemTime};
+
+    use crate::{
+        channels::JoinError,
+        state::{LinkedServer, NetworkState, PartError},
+        ts6::structs::{ServerId, UserId},
+        user::UserUnwrapped,
+        usermodes::Usermodes,
+    };
+
+    fn user(nickname: &str, user_id: &str) -> UserUnwrapped {
+        UserUnwrapped {
+            nickname: nickname.to_owned(),
+            username: nickname.to_owned(),
+            realname: nickname.to_owned(),
+            identified: true,
+            hopcount: 0,
+            user_id: UserId::try_from(user_id.to_owned()).unwrap(),
+            usermodes: Usermodes::default(),
+            timestamp: SystemTime::now(),
+            ip: IpAddr::from([127, 0, 0, 1]),
+            account: None,
+            certfp: None,
+            away: None,
+        }
+    }
+
+    #[test]
+    fn test_network_state() {
+        let network = NetworkState::default();
+        let alice = user("Alice", "000AAAAAB");
+        let bob = user("bob", "001AAAAAC");
+
+        network.add_user(alice.clone(), true).unwrap();
+        network.add_user(bob.clone(), false).unwrap();
+        assert!(network.add_user(user("ALICE", "000AAAAAD"), true).is_err());
+
+        assert_eq!(network.find_nick("alice"), Some(alice.user_id.clone()));
+        assert!(
+            network
+                .change_nick(&alice, "BOB", SystemTime::now())
+                .is_err()
+        );
+        network
+            .change_nick(&alice, "ALICE", alice.timestamp)
+            .unwrap();
+        assert_eq!(network.get_user(&alice.user_id).unwrap().nickname, "ALICE");
+        assert_eq!(network.find_nick("alice"), Some(alice.user_id.clone()));
+        assert!(network.is_local(&alice.user_id));
+        assert!(!network.is_local(&bob.user_id));
+
+        let (_, created) = network.join_channel("#Test", &alice, None).unwrap();
+        assert!(created);
+        let (channel, created) = network.join_channel("#test", &bob, None).unwrap();
+        assert!(!created);
+        assert_eq!(channel.name, "#Test");
+        assert_eq!(network.channel_members("#TEST").len(), 2);
+
+        assert_eq!(
+            network.part_channel("#nope", &alice.user_id),
+            Err(PartError::NoSuchChannel)
+        );
+        network
+            .update_channel("#test", |channel| channel.modes.limit = Some(2))
+            .unwrap();
+        assert_eq!(
+            network.join_channel("#test", &user("carol", "000AAAAAE"), None),
+            Err(JoinError::ChannelFull)
+        );
+        network.join_channel("#other", &bob, None).unwrap();
+        assert_eq!(
+            network.part_channel("#other", &alice.user_id),
+            Err(PartError::NotOnChannel)
+        );
+        let channel = network.part_channel("#OTHER", &bob.user_id).unwrap();
+        assert_eq!(channel.members.len(), 1);
+        assert_eq!(network.channels.len(), 1);
+
+        let (_, channels) = network.remove_user(&alice.user_id).unwrap();
+        assert_eq!(channels.len(), 1);
+        assert_eq!(network.find_nick("alice"), None);
+        assert_eq!(network.channel_members("#test"), vec![bob.user_id.clone()]);
+
+        // the last one out deletes the channel
+        network.remove_user(&bob.user_id);
+        assert!(network.channel_members("#test").is_empty());
+        assert!(network.channels.is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_joins() {
+        let network = NetworkState::default();
+        let founder = user("founder", "000AAAAAB");
+        network.join_channel("#busy", &founder, None).unwrap();
+        network
+            .update_channel("#busy", |channel| channel.modes.limit = Some(5))
+            .unwrap();
+
+        let joined = std::thread::scope(|scope| {
+            let joins = (0..32)
+                .map(|x| {
+                    let network = &network;
+                    scope.spawn(move || {
+                        let joiner = user(&format!("user{x}"), &format!("000AAAB{x:02}"));
+                        network.join_channel("#busy", &joiner, None).is_ok()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            joins
+                .into_iter()
+                .map(|join| join.join().unwrap())
+                .filter(|joined| *joined)
+                .count()
+        });
+
+        // nobody gets past the limit, no matter how the joins interleave
+        assert_eq!(joined, 4);
+        assert_eq!(network.channel_members("#busy").len(), 5);
+    }
+
+    #[test]
+    fn test_invites() {
+        let network = NetworkState::default();
+        let alice = user("alice", "000AAAAAB");
+        let bob = user("bob", "000AAAAAC");
+        network.add_user(bob.clone(), true).unwrap();
+        network.join_channel("#a", &alice, None).unwrap();
+        network.join_channel("#b", &alice, None).unwrap();
+
+        assert!(network.invite("#nope", &bob.user_id, |_| true).is_none());
+        assert!(network.invite("#a", &bob.user_id, |_| false).is_none());
+        network.invite("#a", &bob.user_id, |_| true).unwrap();
+        network.invite("#B", &bob.user_id, |_| true).unwrap();
+        assert!(
+            network
+                .get_channel("#b")
+                .unwrap()
+                .invites
+                .contains(&bob.user_id)
+        );
+
+        // joining uses up the invite, leaving takes the rest along
+        network.join_channel("#a", &bob, None).unwrap();
+        assert!(network.get_channel("#a").unwrap().invites.is_empty());
+        network.remove_user(&bob.user_id).unwrap();
+        assert!(network.get_channel("#b").unwrap().invites.is_empty());
+        assert!(network.invites.is_empty());
+    }
+
+    #[test]
+    fn test_remove_server() {
+        let network = NetworkState::default();
+        let server = |name: &str, uplink: Option<&str>| LinkedServer {
+            name: name.to_owned(),
+            description: String::new(),
+            uplink: uplink.map(|x| ServerId::try_from(x.to_owned()).unwrap()),
+        };
+        network.add_server("001".to_owned().try_into().unwrap(), server("a", None));
+        network.add_server(
+            "002".to_owned().try_into().unwrap(),
+            server("b", Some("001")),
+        );
+        network.add_server(
+            "003".to_owned().try_into().unwrap(),
+            server("c", Some("002")),
+        );
+        network.add_server("004".to_owned().try_into().unwrap(), server("d", None));
+        for (nickname, user_id) in [
+            ("alice", "000AAAAAB"),
+            ("bob", "001AAAAAB"),
+            ("carol", "003AAAAAB"),
+            ("dave", "004AAAAAB"),
+        ] {
+            network.add_user(user(nickname, user_id), false).unwrap();
+        }
+
+        let mut lost = network.remove_server(&"001".to_owned().try_into().unwrap());
+        lost.sort_by_key(|x| x.to_string());
+        assert_eq!(
+            lost,
+            vec![
+                UserId::try_from("001AAAAAB".to_owned()).unwrap(),
+                UserId::try_from("003AAAAAB".to_owned()).unwrap(),
+            ]
+        );
+        assert!(
+            network
+                .get_server(&"003".to_owned().try_into().unwrap())
+                .is_none()
+        );
+        assert!(
+            network
+                .get_server(&"004".to_owned().try_into().unwrap())
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use once_cell::sync::Lazy;
+
+use crate::{capabilities::register_capability, user::User};
+
+/// IRCv3 message tags. Tags without a value are stored with an empty value, as the spec treats
+/// both the same way.
+pub type Tags = BTreeMap<String, String>;
+
+static MSGID_COUNTER: AtomicU64 = AtomicU64::new(0);
+static MSGID_PREFIX: Lazy<String> = Lazy::new(|| {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    format!("{started:x}")
+});
+
+pub async fn register_capabilities() {
+    register_capability("message-tags", None).await;
+    register_capability("server-time", None).await;
+}
+
+/// Parse the tag section of a message, without the leading `@`.
+pub fn parse_tags(raw: &str) -> Tags {
+    let mut tags = Tags::new();
+
+    for tag in raw.split(';') {
+        if tag.is_empty() {
+            continue;
+        }
+
+        let (key, value) = match tag.split_once('=') {
+            Some((key, value)) => (key, unescape_tag_value(value)),
+            None => (tag, String::new()),
+        };
+
+        if key.is_empty() || key == "+" {
+            continue;
+        }
+
+        // if a key shows up more than once, the last one wins
+        tags.insert(key.to_owned(), value);
+    }
+
+    tags
+}
+
+/// Serialize tags into the tag section of a message, including the leading `@`. Returns an empty
+/// string if there are no tags.
+pub fn serialize_tags(tags: &Tags) -> String {
+    if tags.is_empty() {
+        return String::new();
+    }
+
+    let serialized = tags
+        .iter()
+        .map(|(key, value)| {
+            if value.is_empty() {
+                key.clone()
+            } else {
+                format!("{key}={}", escape_tag_value(value))
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(";");
+
+    format!("@{serialized}")
+}
+
+pub fn escape_tag_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for char in value.chars() {
+        match char {
+            ';' => escaped.push_str("\\:"),
+            ' ' => escaped.push_str("\\s"),
+            '\\' => escaped.push_str("\\\\"),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(char),
+        }
+    }
+
+    escaped
+}
+
+pub fn unescape_tag_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            unescaped.push(char);
+            continue;
+        }
+
+        // an invalid escape drops the backslash, a trailing backslash is dropped entirely
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('\\') => unescaped.push('\\'),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+
+    unescaped
+}
+
+/// Only keep client-only tags (the ones prefixed with `+`), which are the only ones clients are
+/// allowed to send to others.
+pub fn client_only_tags(tags: &Tags) -> Tags {
+    tags.iter()
+        .filter(|(key, _)| key.starts_with('+'))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// Tags attached by the server to a message that gets relayed to other clients.
+pub fn relayed_message_tags(client_tags: &Tags) -> Tags {
+    let mut tags = client_only_tags(client_tags);
+
+    tags.insert("msgid".to_owned(), generate_msgid());
+    tags.insert("time".to_owned(), server_time(SystemTime::now()));
+
+    tags
+}
+
+/// Strip the tags a client has not negotiated the capabilities for.
+pub fn tags_for_user(user: &User, tags: &Tags) -> Tags {
+    let message_tags = user.has_capability("message-tags");
+    let server_time = user.has_capability("server-time");
+
+    tags.iter()
+        .filter(|(key, _)| match key.as_str() {
+            "time" => server_time,
+            _ => message_tags,
+        })
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+pub fn generate_msgid() -> String {
+    let count = MSGID_COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    format!("{}-{count:x}", *MSGID_PREFIX)
+}
+
+/// Format a timestamp as required by the `server-time` capability, e.g.
+/// `2011-10-19T16:40:51.620Z`.
+pub fn server_time(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let millis = since_epoch.subsec_millis();
+
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+
+    // civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{millis:03}Z",
+        secs_of_day / 3600,
+        (secs_of_day % 3600) / 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::tags::{Tags, escape_tag_value, parse_tags, serialize_tags, server_time};
+
+    #[test]
+    fn test_tag_escaping() {
+        let value = "a; b\\c\r\nd";
+
+        assert_eq!(escape_tag_value(value), "a\\:\\sb\\\\c\\r\\nd");
+        assert_eq!(
+            parse_tags(&format!("key={}", escape_tag_value(value)))["key"],
+            value
+        );
+        // invalid escapes drop the backslash, trailing backslashes are dropped
+        assert_eq!(parse_tags("a=\\b\\")["a"], "b");
+    }
+
+    #[test]
+    fn test_parse_and_serialize_tags() {
+        let tags = parse_tags("+typing=active;msgid=abc;empty=;flag;+draft/reply=1;msgid=def");
+
+        assert_eq!(tags["+typing"], "active");
+        assert_eq!(tags["msgid"], "def");
+        assert_eq!(tags["empty"], "");
+        assert_eq!(tags["flag"], "");
+        assert_eq!(
+            serialize_tags(&tags),
+            "@+draft/reply=1;+typing=active;empty;flag;msgid=def"
+        );
+        assert_eq!(serialize_tags(&Tags::new()), "");
+    }
+
+    #[test]
+    fn test_server_time() {
+        let time = UNIX_EPOCH + Duration::from_millis(1318956051620);
+
+        assert_eq!(server_time(time), "2011-10-18T16:40:51.620Z");
+        assert_eq!(server_time(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+}
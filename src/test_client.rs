@@ -0,0 +1,161 @@
+//! In-process client for tests, talking to a connection handler over an in-memory stream.
+
+use std::{net::SocketAddr, time::Duration};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, ReadHalf, WriteHalf},
+    time::timeout,
+};
+
+use crate::{
+    capabilities,
+    casemapping::Casemapping,
+    config::{OperBlock, ServerInfo},
+    connection::Connection,
+    handle_connection,
+};
+
+pub struct TestClient {
+    reader: BufReader<ReadHalf<DuplexStream>>,
+    writer: WriteHalf<DuplexStream>,
+}
+
+pub fn test_config() -> ServerInfo {
+    ServerInfo {
+        ip: "127.0.0.1".to_owned(),
+        port: 0,
+        server_hostname: "irc.example.com".to_owned(),
+        network_name: "TestNet".to_owned(),
+        operators: Vec::new(),
+        server_incoming_passwords: vec!["linkpass".to_owned()],
+        server_outgoing_password: String::new(),
+        accounts_file: None,
+        tls: None,
+        websocket: None,
+        opers: vec![OperBlock {
+            name: "admin".to_owned(),
+            password: Some("hunter2".to_owned()),
+            certfp: None,
+        }],
+        casemapping: Casemapping::default(),
+    }
+}
+
+impl TestClient {
+    pub async fn connect() -> Self {
+        let (client, server) = tokio::io::duplex(16384);
+        let address = SocketAddr::from(([127, 0, 0, 1], 0));
+
+        tokio::spawn(handle_connection(
+            Connection::from_stream(server, address, false),
+            test_config(),
+        ));
+
+        let (reader, writer) = tokio::io::split(client);
+
+        Self {
+            reader: BufReader::new(reader),
+            writer,
+        }
+    }
+
+    /// Connect and register with the given nickname, skipping the welcome burst.
+    pub async fn register(nickname: &str) -> Self {
+        let mut client = Self::connect().await;
+
+        client.send(&format!("NICK {nickname}")).await;
+        client
+            .send(&format!("USER {nickname} 0 * :{nickname}"))
+            .await;
+        client.recv_until("422").await;
+
+        client
+    }
+
+    /// Link up as a server with the given SID, skipping our side of the handshake.
+    pub async fn link(sid: &str, name: &str) -> Self {
+        let mut server = Self::connect().await;
+
+        server.send(&format!("PASS linkpass TS 6 :{sid}")).await;
+        server.send("CAPAB :QS ENCAP EX IE").await;
+        server
+            .send(&format!("SERVER {name} 1 {sid} + :{name}"))
+            .await;
+        server.send("SVINFO 6 6 0 :0").await;
+        server.recv_until("SVINFO").await;
+
+        server
+    }
+
+    /// Like `register`, but with the given capabilities requested first.
+    pub async fn register_with_caps(nickname: &str, caps: &[&str]) -> Self {
+        // capabilities get registered in main, which doesn't run here
+        for cap in caps {
+            capabilities::register_capability(cap, None).await;
+        }
+
+        let mut client = Self::connect().await;
+        client.send("CAP LS 302").await;
+        client.send(&format!("CAP REQ :{}", caps.join(" "))).await;
+        client.send("CAP END").await;
+        client.send(&format!("NICK {nickname}")).await;
+        client
+            .send(&format!("USER {nickname} 0 * :{nickname}"))
+            .await;
+        client.recv_until("422").await;
+
+        client
+    }
+
+    /// Register two clients and put both in the channel, the first one as its operator.
+    pub async fn pair_in_channel(first: &str, second: &str, channel: &str) -> (Self, Self) {
+        let mut first = Self::register(first).await;
+        let mut second = Self::register(second).await;
+        first.join_with(&mut second, channel).await;
+
+        (first, second)
+    }
+
+    /// Join the channel, then have the other client join it too, skipping what gets sent along
+    /// the way.
+    pub async fn join_with(&mut self, other: &mut Self, channel: &str) {
+        self.send(&format!("JOIN {channel}")).await;
+        self.recv_until("366").await;
+        other.send(&format!("JOIN {channel}")).await;
+        other.recv_until("366").await;
+        self.recv_until("JOIN").await;
+    }
+
+    pub async fn send(&mut self, line: &str) {
+        self.writer
+            .write_all(format!("{line}\r\n").as_bytes())
+            .await
+            .unwrap();
+    }
+
+    /// Next line from the server, `None` if nothing arrives in time or the connection is closed.
+    pub async fn recv(&mut self) -> Option<String> {
+        let mut line = String::new();
+
+        match timeout(Duration::from_secs(2), self.reader.read_line(&mut line)).await {
+            Ok(Ok(1..)) => Some(line.trim_end_matches(['\r', '\n']).to_owned()),
+            _ => None,
+        }
+    }
+
+    /// Read lines until one with the given command (or numeric) shows up, and return all of them.
+    pub async fn recv_until(&mut self, command: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        while let Some(line) = self.recv().await {
+            let done = line.split(' ').nth(1) == Some(command);
+            lines.push(line);
+
+            if done {
+                return lines;
+            }
+        }
+
+        panic!("never got {command}, only {lines:#?}");
+    }
+}
@@ -0,0 +1,222 @@
+//! What a server that just linked gets told about the rest of the network.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    chanmodes::{Chanmode, ModeKind},
+    channels::Channel,
+    sender::IrcResponse,
+    state::NETWORK,
+    tags::Tags,
+    ts6::{structs::ServerId, uid_line},
+};
+
+/// Servers, users and channels, in the order the other side needs them: servers after their
+/// uplink, users after their server and channels after their members. Whatever is behind the
+/// server we're bursting to is left out, as it knows better.
+pub fn burst(my_sid: &ServerId, peer: &ServerId) -> Vec<IrcResponse> {
+    let (mut lines, known) = servers(my_sid, peer);
+
+    lines.extend(
+        NETWORK
+            .users()
+            .iter()
+            .filter(|user| known.contains_key(&user.user_id.get_server_id()))
+            .map(uid_line),
+    );
+
+    for name in NETWORK.channel_names() {
+        // it might have gone away since
+        if let Some(channel) = NETWORK.get_channel(&name) {
+            lines.extend(channel_burst(&channel, my_sid));
+        }
+    }
+
+    lines
+}
+
+/// SID lines for the servers we know about, and the hopcount of every server that got introduced
+fn servers(my_sid: &ServerId, peer: &ServerId) -> (Vec<IrcResponse>, BTreeMap<ServerId, u16>) {
+    let mut known = BTreeMap::from([(my_sid.clone(), 0)]);
+    let mut pending = NETWORK
+        .servers()
+        .into_iter()
+        .filter(|(server_id, _)| server_id != peer)
+        .collect::<Vec<_>>();
+    let mut lines = Vec::new();
+
+    loop {
+        let (ready, rest): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|(_, server)| known.contains_key(server.uplink.as_ref().unwrap_or(my_sid)));
+        if ready.is_empty() {
+            return (lines, known);
+        }
+
+        for (server_id, server) in ready {
+            let uplink = server.uplink.unwrap_or(my_sid.clone());
+            let hopcount = known[&uplink] + 1;
+
+            lines.push(IrcResponse {
+                tags: Tags::new(),
+                sender: Some(uplink.to_string()),
+                command: "SID".to_owned(),
+                receiver: None,
+                arguments: vec![server.name, hopcount.to_string(), server_id.to_string()],
+                message: format!(":{}", server.description),
+            });
+            known.insert(server_id, hopcount);
+        }
+        pending = rest;
+    }
+}
+
+/// SJOIN with the channel's TS, modes and members, then TB for its topic and BMASK for each list
+fn channel_burst(channel: &Channel, my_sid: &ServerId) -> Vec<IrcResponse> {
+    let timestamp = channel.timestamp().to_string();
+    let (modestring, parameters) = channel.modes.to_mode_string(true);
+    let sjoin_arguments = [
+        vec![timestamp.clone(), channel.name.clone(), modestring],
+        parameters,
+    ]
+    .concat();
+
+    let members = channel.members.iter().map(|(user_id, statuses)| {
+        let prefixes = statuses
+            .iter()
+            .map(|status| status.prefix())
+            .collect::<String>();
+        format!("{prefixes}{user_id}")
+    });
+    let mut lines = chunked(members, &format!("{} :", sjoin_arguments.join(" ")), my_sid)
+        .into_iter()
+        .map(|members| IrcResponse {
+            tags: Tags::new(),
+            sender: Some(my_sid.to_string()),
+            command: "SJOIN".to_owned(),
+            receiver: None,
+            arguments: sjoin_arguments.clone(),
+            message: format!(":{members}"),
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(topic) = &channel.topic {
+        lines.push(IrcResponse {
+            tags: Tags::new(),
+            sender: Some(my_sid.to_string()),
+            command: "TB".to_owned(),
+            receiver: None,
+            arguments: vec![
+                channel.name.clone(),
+                topic.timestamp().to_string(),
+                topic.setter.clone(),
+            ],
+            message: format!(":{}", topic.text),
+        });
+    }
+
+    for mode in Chanmode::ALL
+        .into_iter()
+        .filter(|mode| mode.kind() == ModeKind::List)
+    {
+        let masks = channel.modes.list(mode).iter().map(|x| x.mask.clone());
+        let arguments = vec![
+            timestamp.clone(),
+            channel.name.clone(),
+            char::from(mode).to_string(),
+        ];
+
+        lines.extend(
+            chunked(masks, &format!("{} :", arguments.join(" ")), my_sid)
+                .into_iter()
+                .map(|masks| IrcResponse {
+                    tags: Tags::new(),
+                    sender: Some(my_sid.to_string()),
+                    command: "BMASK".to_owned(),
+                    receiver: None,
+                    arguments: arguments.clone(),
+                    message: format!(":{masks}"),
+                }),
+        );
+    }
+
+    lines
+}
+
+/// Join the items into as few lines as fit in 512 bytes after `:SID COMMAND <arguments> :`, with
+/// the command taking at most five bytes.
+fn chunked(items: impl Iterator<Item = String>, arguments: &str, my_sid: &ServerId) -> Vec<String> {
+    let space = 510 - format!(":{my_sid} SJOIN {arguments}").len();
+    let mut lines: Vec<String> = Vec::new();
+
+    for item in items {
+        match lines.last_mut() {
+            Some(line) if line.len() + 1 + item.len() <= space => {
+                line.push(' ');
+                line.push_str(&item);
+            }
+            _ => lines.push(item),
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{state::NETWORK, test_client::TestClient};
+
+    #[tokio::test]
+    async fn test_burst() {
+        let (mut alice, mut bob) = TestClient::pair_in_channel("dalice", "dbob", "#burst").await;
+        alice.send("TOPIC #burst :hello servers").await;
+        alice
+            .send("MODE #burst +vkb dbob sekrit *!*@evil.example")
+            .await;
+        alice.recv_until("MODE").await;
+        bob.recv_until("MODE").await;
+
+        let mut server = TestClient::link("9B0", "burst.example.com").await;
+        // the burst is written before the next line gets read
+        server.send("PING :burst.example.com").await;
+        let lines = server.recv_until("PONG").await;
+        let position = |start: &str| {
+            lines
+                .iter()
+                .position(|line| line.starts_with(start))
+                .unwrap_or_else(|| panic!("no {start} in {lines:#?}"))
+        };
+
+        let channel = NETWORK.get_channel("#burst").unwrap();
+        let alice_id = NETWORK.find_nick("dalice").unwrap();
+        let bob_id = NETWORK.find_nick("dbob").unwrap();
+        let timestamp = channel.timestamp();
+
+        let uid = position(":000 UID dalice 1 ");
+        let sjoin = position(&format!(":000 SJOIN {timestamp} #burst +ntk sekrit :"));
+        let members = lines[sjoin]
+            .rsplit_once(':')
+            .unwrap()
+            .1
+            .split(' ')
+            .collect::<BTreeSet<_>>();
+        assert_eq!(
+            members,
+            BTreeSet::from([
+                format!("@{alice_id}").as_str(),
+                format!("+{bob_id}").as_str()
+            ])
+        );
+        let tb = position(":000 TB #burst ");
+        assert!(lines[tb].ends_with(" dalice!~dalice@unimplement.ed :hello servers"));
+        let bmask = position(&format!(":000 BMASK {timestamp} #burst b :"));
+        assert_eq!(
+            lines[bmask],
+            format!(":000 BMASK {timestamp} #burst b :*!*@evil.example")
+        );
+
+        assert!(uid < sjoin && sjoin < tb && tb < bmask);
+    }
+}
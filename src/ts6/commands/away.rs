@@ -0,0 +1,41 @@
+use async_trait::async_trait;
+
+use crate::{
+    messages::{AwayMessage, Message},
+    state::NETWORK,
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::ServerId,
+    },
+};
+
+pub struct Away;
+
+#[async_trait]
+impl Ts6Handler for Away {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        _server_status: Ts6,
+        _my_sid: ServerId,
+        sender: Option<CommandSender>,
+        _hostname: &str,
+    ) -> Vec<Ts6Action> {
+        let Some(CommandSender::User(user_id)) = sender else {
+            return vec![];
+        };
+        let Some(mut user) = NETWORK.get_user(&user_id) else {
+            return vec![];
+        };
+
+        user.away = command.first().filter(|text| !text.is_empty()).cloned();
+        NETWORK.update_user(user.clone());
+
+        vec![Ts6Action::SendMessage(Box::new(Message::AwayMessage(
+            AwayMessage { user },
+        )))]
+    }
+}
@@ -0,0 +1,78 @@
+use async_trait::async_trait;
+
+use crate::{
+    chanmodes::{Chanmode, ModeChange, ModeKind},
+    messages::{BanMaskMessage, Message},
+    state::NETWORK,
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::ServerId,
+    },
+};
+
+pub struct Bmask;
+
+#[async_trait]
+impl Ts6Handler for Bmask {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        server_status: Ts6,
+        _my_sid: ServerId,
+        sender: Option<CommandSender>,
+        _hostname: &str,
+    ) -> Vec<Ts6Action> {
+        let Some(CommandSender::Server(_)) = sender else {
+            return vec![];
+        };
+        let [timestamp, name, mode, masks] = command.as_slice() else {
+            return vec![];
+        };
+        let (Ok(timestamp), Some(mode)) = (
+            timestamp.parse::<u64>(),
+            mode.chars().next().and_then(Chanmode::from_char),
+        ) else {
+            return vec![];
+        };
+        if mode.kind() != ModeKind::List {
+            return vec![];
+        }
+
+        // extbans are kept like any other mask
+        let changes = masks
+            .split_whitespace()
+            .map(|mask| ModeChange {
+                adding: true,
+                mode,
+                parameter: Some(mask.to_owned()),
+            })
+            .collect::<Vec<ModeChange>>();
+
+        let applied = NETWORK.update_channel(name, |channel| {
+            // masks from a younger channel lose
+            if timestamp > channel.timestamp() {
+                return None;
+            }
+
+            let applied = channel.apply_modes(changes, &server_status.hostname);
+
+            Some((channel.clone(), applied))
+        });
+
+        match applied.flatten() {
+            Some((channel, changes)) if !changes.is_empty() => {
+                vec![Ts6Action::SendMessage(Box::new(Message::BanMaskMessage(
+                    BanMaskMessage {
+                        server: server_status.hostname,
+                        channel,
+                        changes,
+                    },
+                )))]
+            }
+            _ => vec![],
+        }
+    }
+}
@@ -1,6 +1,9 @@
-use crate::ts6::{
-    ServerId, Ts6,
-    commands::{CommandSender, Ts6Action, Ts6Handler},
+use crate::{
+    tags::Tags,
+    ts6::{
+        ServerId, Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+    },
 };
 use async_trait::async_trait;
 
@@ -13,6 +16,7 @@ impl Ts6Handler for Capab {
     async fn handle(
         &self,
         command: Vec<String>,
+        _tags: Tags,
         _server_status: Ts6,
         _my_sid: ServerId,
         sender: Option<CommandSender>,
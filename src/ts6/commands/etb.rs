@@ -0,0 +1,74 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use async_trait::async_trait;
+
+use crate::{
+    messages::{Message, TopicMessage},
+    state::NETWORK,
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::ServerId,
+    },
+};
+
+/// Extended topic burst: `ETB channelts #channel topicts setter :topic`
+pub struct Etb;
+
+#[async_trait]
+impl Ts6Handler for Etb {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        server_status: Ts6,
+        _my_sid: ServerId,
+        sender: Option<CommandSender>,
+        _hostname: &str,
+    ) -> Vec<Ts6Action> {
+        let source = match &sender {
+            Some(CommandSender::User(user_id)) => NETWORK.get_user(user_id).map(|x| x.hostmask()),
+            Some(CommandSender::Server(_)) => Some(server_status.hostname.clone()),
+            None => None,
+        };
+        let (Some(source), [channel_ts, name, topic_ts, setter, text]) =
+            (source, command.as_slice())
+        else {
+            return vec![];
+        };
+        let (Ok(channel_ts), Ok(topic_ts)) = (channel_ts.parse::<u64>(), topic_ts.parse::<u64>())
+        else {
+            return vec![];
+        };
+
+        let changed = NETWORK.update_channel(name, |channel| {
+            // an older channel always wins, for the same channel the newer topic does
+            let accept = channel.topic.is_none()
+                || channel_ts < channel.timestamp()
+                || (channel_ts == channel.timestamp()
+                    && channel
+                        .topic
+                        .as_ref()
+                        .is_some_and(|topic| topic_ts > topic.timestamp()));
+            if !accept {
+                return None;
+            }
+
+            channel
+                .set_topic(text, setter, UNIX_EPOCH + Duration::from_secs(topic_ts))
+                .then(|| channel.clone())
+        });
+
+        match changed.flatten() {
+            Some(channel) => vec![Ts6Action::SendMessage(Box::new(Message::TopicMessage(
+                TopicMessage {
+                    setter: source,
+                    user_id: None,
+                    channel,
+                },
+            )))],
+            None => vec![],
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use async_trait::async_trait;
+
+use crate::{
+    messages::{InviteMessage, Message},
+    state::NETWORK,
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::{ServerId, UserId},
+    },
+};
+
+pub struct Invite;
+
+#[async_trait]
+impl Ts6Handler for Invite {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        _server_status: Ts6,
+        _my_sid: ServerId,
+        sender: Option<CommandSender>,
+        _hostname: &str,
+    ) -> Vec<Ts6Action> {
+        let Some(CommandSender::User(user_id)) = sender else {
+            return vec![];
+        };
+        let (Some(user), [target, name, rest @ ..]) =
+            (NETWORK.get_user(&user_id), command.as_slice())
+        else {
+            return vec![];
+        };
+        let Some(target) = UserId::try_from(target.clone())
+            .ok()
+            .and_then(|target_id| NETWORK.get_user(&target_id))
+        else {
+            return vec![];
+        };
+        let timestamp = rest
+            .first()
+            .and_then(|timestamp| timestamp.parse::<u64>().ok());
+
+        // an invite for an older incarnation of the channel is stale
+        let invited = NETWORK.invite(name, &target.user_id, |channel| {
+            timestamp.is_none_or(|timestamp| timestamp <= channel.timestamp())
+        });
+
+        match invited {
+            Some(channel) => vec![Ts6Action::SendMessage(Box::new(Message::InviteMessage(
+                InviteMessage {
+                    sender: user,
+                    target,
+                    channel,
+                },
+            )))],
+            None => vec![],
+        }
+    }
+}
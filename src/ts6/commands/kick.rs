@@ -0,0 +1,59 @@
+use async_trait::async_trait;
+
+use crate::{
+    messages::{KickMessage, Message},
+    state::NETWORK,
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::{ServerId, UserId},
+    },
+};
+
+pub struct Kick;
+
+#[async_trait]
+impl Ts6Handler for Kick {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        _server_status: Ts6,
+        _my_sid: ServerId,
+        sender: Option<CommandSender>,
+        _hostname: &str,
+    ) -> Vec<Ts6Action> {
+        // TODO: kicks by servers
+        let Some(CommandSender::User(user_id)) = sender else {
+            return vec![];
+        };
+        let (Some(user), [name, target, ..]) = (NETWORK.get_user(&user_id), command.as_slice())
+        else {
+            return vec![];
+        };
+        let Some(target) = UserId::try_from(target.clone())
+            .ok()
+            .and_then(|target_id| NETWORK.get_user(&target_id))
+        else {
+            return vec![];
+        };
+        let reason = command
+            .get(2)
+            .filter(|reason| !reason.is_empty())
+            .cloned()
+            .unwrap_or(user.nickname.clone());
+
+        match NETWORK.part_channel(name, &target.user_id) {
+            Ok(channel) => vec![Ts6Action::SendMessage(Box::new(Message::KickMessage(
+                KickMessage {
+                    sender: user,
+                    channel,
+                    target,
+                    reason,
+                },
+            )))],
+            Err(_) => vec![],
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use async_trait::async_trait;
+
+use crate::{
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::{ServerId, UserId},
+    },
+};
+
+pub struct Kill;
+
+#[async_trait]
+impl Ts6Handler for Kill {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        _server_status: Ts6,
+        _my_sid: ServerId,
+        _sender: Option<CommandSender>,
+        _hostname: &str,
+    ) -> Vec<Ts6Action> {
+        let Some(Ok(user_id)) = command.first().map(|x| UserId::try_from(x.clone())) else {
+            return vec![];
+        };
+
+        vec![Ts6Action::KillUser {
+            user_id,
+            reason: command.get(1).cloned().unwrap_or_default(),
+        }]
+    }
+}
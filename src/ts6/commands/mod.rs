@@ -1,28 +1,48 @@
 use std::collections::HashMap;
 
 use crate::{
-    SENDER,
-    commands::IrcMessage,
+    connection::IrcSink,
+    error_structs::ParseError,
     messages::Message,
+    parser::ParsedMessage,
+    routing::{self, route},
     sender::IrcResponse,
+    tags::Tags,
     ts6::{
         ServerId, Ts6,
         commands::{
-            capab::Capab, ping::Ping, privmsg::Privmsg, server::Server, svinfo::Svinfo, uid::Uid,
+            away::Away, bmask::Bmask, capab::Capab, etb::Etb, invite::Invite, kick::Kick,
+            kill::Kill, nick::Nick, numeric::Numeric, part::Part, ping::Ping, privmsg::Privmsg,
+            quit::Quit, server::Server, sid::Sid, svinfo::Svinfo, tb::Tb, tmode::Tmode,
+            topic::Topic, uid::Uid, whois::Whois,
         },
         structs::UserId,
     },
 };
 use anyhow::anyhow;
 use async_trait::async_trait;
-use tokio::{io::BufWriter, net::TcpStream};
 
+mod away;
+mod bmask;
 mod capab;
+mod etb;
+mod invite;
+mod kick;
+mod kill;
+mod nick;
+mod numeric;
+mod part;
 mod ping;
 mod privmsg;
+mod quit;
 mod server;
+mod sid;
 mod svinfo;
+mod tb;
+mod tmode;
+mod topic;
 mod uid;
+mod whois;
 
 #[derive(Clone, Debug)]
 pub struct Ts6Info {
@@ -38,7 +58,17 @@ pub struct Ts6Info {
 pub enum Ts6Action {
     SetInfo(Ts6Info),
     SendText(IrcResponse),
-    SendMessage(Message),
+    SendMessage(Box<Message>),
+    /// A user behind this server left the network
+    QuitUser {
+        user_id: UserId,
+        reason: String,
+    },
+    /// A user, wherever they are, got taken off the network
+    KillUser {
+        user_id: UserId,
+        reason: String,
+    },
     DoNothing,
 }
 
@@ -47,6 +77,7 @@ pub trait Ts6Handler: Send + Sync {
     async fn handle(
         &self,
         command: Vec<String>,
+        tags: Tags,
         server_status: Ts6,
         my_sid: ServerId,
         sender: Option<CommandSender>,
@@ -58,6 +89,7 @@ pub trait Ts6Handler: Send + Sync {
 pub struct Ts6Command {
     command: String,
     arguments: Vec<String>,
+    tags: Tags,
     sender: Option<CommandSender>,
 }
 
@@ -68,27 +100,20 @@ pub enum CommandSender {
 }
 
 impl Ts6Command {
-    pub async fn new(command_with_arguments: String) -> Self {
+    pub async fn new(command_with_arguments: String) -> Result<Self, ParseError> {
+        let message = ParsedMessage::parse(&command_with_arguments)?;
         let mut command_sender = None;
-        let mut split_command: Vec<&str> = command_with_arguments
-            .split_whitespace()
-            .into_iter()
-            .collect();
-
-        if split_command[0].starts_with(":") {
-            let sender = split_command.remove(0).to_string().replace(":", "");
-
-            dbg!(&sender);
 
+        if let Some(sender) = message.source {
             match sender.len() {
                 3 => {
-                    if let Ok(sid) = ServerId::try_from(sender) {
+                    if let Ok(sid) = ServerId::try_from(sender.to_owned()) {
                         command_sender = Some(CommandSender::Server(sid));
                     }
                 }
 
                 9 => {
-                    if let Ok(uid) = UserId::try_from(sender) {
+                    if let Ok(uid) = UserId::try_from(sender.to_owned()) {
                         command_sender = Some(CommandSender::User(uid));
                     }
                 }
@@ -97,32 +122,12 @@ impl Ts6Command {
             }
         }
 
-        let command = split_command[0].to_owned();
-        let mut arguments = Vec::new();
-        let mut buffer: Option<String> = None;
-
-        split_command[1..]
-            .iter()
-            .for_each(|e| match (buffer.as_mut(), e.starts_with(":")) {
-                (None, false) => arguments.push(e.to_string()),
-                (None, true) => {
-                    buffer = Some(e[1..].to_string());
-                }
-                (Some(buf), starts_with_colon) => {
-                    buf.push(' ');
-                    buf.push_str(if starts_with_colon { &e[1..] } else { &e });
-                }
-            });
-
-        if let Some(buf) = buffer {
-            arguments.push(buf.to_string());
-        }
-
-        Self {
-            command: command,
-            arguments: arguments,
+        Ok(Self {
+            command: message.command.to_owned(),
+            arguments: message.owned_params(),
+            tags: message.tags(),
             sender: command_sender,
-        }
+        })
     }
 
     pub async fn execute(
@@ -130,28 +135,47 @@ impl Ts6Command {
         ts6_status: &mut Ts6,
         hostname: &str,
         my_sid: &ServerId,
-        writer: &mut BufWriter<TcpStream>,
+        writer: &mut dyn IrcSink,
     ) -> Result<(), anyhow::Error> {
         let mut command_map: HashMap<String, &dyn Ts6Handler> = HashMap::new();
-        let message_sender = SENDER.lock().await.clone().unwrap();
 
         command_map.insert("CAPAB".to_owned(), &Capab);
         command_map.insert("SERVER".to_owned(), &Server);
+        command_map.insert("SID".to_owned(), &Sid);
         command_map.insert("PING".to_owned(), &Ping);
         command_map.insert("SVINFO".to_owned(), &Svinfo);
         command_map.insert("UID".to_owned(), &Uid);
         command_map.insert("PRIVMSG".to_owned(), &Privmsg);
+        command_map.insert("PART".to_owned(), &Part);
+        command_map.insert("NICK".to_owned(), &Nick);
+        command_map.insert("TMODE".to_owned(), &Tmode);
+        command_map.insert("BMASK".to_owned(), &Bmask);
+        command_map.insert("TOPIC".to_owned(), &Topic);
+        command_map.insert("TB".to_owned(), &Tb);
+        command_map.insert("ETB".to_owned(), &Etb);
+        command_map.insert("KICK".to_owned(), &Kick);
+        command_map.insert("INVITE".to_owned(), &Invite);
+        command_map.insert("WHOIS".to_owned(), &Whois);
+        command_map.insert("QUIT".to_owned(), &Quit);
+        command_map.insert("KILL".to_owned(), &Kill);
+        command_map.insert("AWAY".to_owned(), &Away);
+
+        let numeric = Numeric(self.command.clone());
+        if self.command.len() == 3 && self.command.chars().all(|x| x.is_ascii_digit()) {
+            command_map.insert(self.command.clone(), &numeric);
+        }
 
         let command_to_execute = command_map
             .get(&self.command.to_uppercase())
-            .map(|v| *v)
+            .copied()
             .ok_or(anyhow!("error"))?; // TODO: error handling!!!
 
         let actions = command_to_execute
             .handle(
                 self.arguments.clone(),
+                self.tags.clone(),
                 ts6_status.clone(),
-                ServerId::try_from(my_sid.clone().to_owned()).unwrap(),
+                my_sid.clone(),
                 self.sender.clone(),
                 hostname,
             )
@@ -164,23 +188,23 @@ impl Ts6Command {
                 Ts6Action::DoNothing => {}
                 Ts6Action::SetInfo(new_info) => {
                     if let Some(sid) = new_info.sid {
-                        (*ts6_status).server_id = sid;
+                        ts6_status.server_id = sid;
                     };
 
                     if let Some(hopcount) = new_info.hopcount {
-                        (*ts6_status).hopcount = hopcount;
+                        ts6_status.hopcount = hopcount;
                     };
 
                     if let Some(name) = new_info.name {
-                        (*ts6_status).hostname = name;
+                        ts6_status.hostname = name;
                     };
 
                     if let Some(description) = new_info.description {
-                        (*ts6_status).description = description;
+                        ts6_status.description = description;
                     };
 
                     if let Some(identified) = new_info.identified {
-                        (*ts6_status).identified = identified;
+                        ts6_status.identified = identified;
                     }
                 }
                 Ts6Action::SendText(response) => {
@@ -191,7 +215,13 @@ impl Ts6Command {
                     // TODO: error handling
                 }
                 Ts6Action::SendMessage(message) => {
-                    message_sender.send(message.clone()).unwrap();
+                    route(*message).await;
+                }
+                Ts6Action::QuitUser { user_id, reason } => {
+                    routing::quit(&user_id, &reason).await;
+                }
+                Ts6Action::KillUser { user_id, reason } => {
+                    routing::kill(&user_id, &reason).await;
                 }
             }
         }
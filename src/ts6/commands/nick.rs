@@ -0,0 +1,72 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use async_trait::async_trait;
+
+use crate::{
+    messages::{Message, NickChangeMessage},
+    routing,
+    state::NETWORK,
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::ServerId,
+    },
+};
+
+pub struct Nick;
+
+#[async_trait]
+impl Ts6Handler for Nick {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        _server_status: Ts6,
+        _my_sid: ServerId,
+        sender: Option<CommandSender>,
+        hostname: &str,
+    ) -> Vec<Ts6Action> {
+        let Some(CommandSender::User(user_id)) = sender else {
+            return vec![];
+        };
+        let (Some(user), Some(nickname)) = (NETWORK.get_user(&user_id), command.first()) else {
+            return vec![];
+        };
+        let timestamp = command
+            .get(1)
+            .and_then(|timestamp| timestamp.parse::<u64>().ok())
+            .map(|timestamp| UNIX_EPOCH + Duration::new(timestamp, 0))
+            .unwrap_or(user.timestamp);
+
+        let reason = format!("{hostname} (Nick collision)");
+        if let Some(existing) = NETWORK
+            .find_nick(nickname)
+            .filter(|owner| *owner != user.user_id)
+            .and_then(|owner| NETWORK.get_user(&owner))
+        {
+            // the newer one loses, both of them if they're the same age
+            if existing.timestamp >= timestamp {
+                routing::kill(&existing.user_id, &reason).await;
+            }
+            if existing.timestamp <= timestamp {
+                routing::kill(&user.user_id, &reason).await;
+                return vec![];
+            }
+        }
+
+        // the nick might have been taken again in the meantime
+        if NETWORK.change_nick(&user, nickname, timestamp).is_err() {
+            routing::kill(&user.user_id, &reason).await;
+            return vec![];
+        }
+
+        vec![Ts6Action::SendMessage(Box::new(
+            Message::NickChangeMessage(NickChangeMessage {
+                user,
+                nickname: nickname.clone(),
+                timestamp,
+            }),
+        ))]
+    }
+}
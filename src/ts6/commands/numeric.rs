@@ -0,0 +1,55 @@
+use async_trait::async_trait;
+
+use crate::{
+    messages::{Message, ServerReply},
+    state::NETWORK,
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::{ServerId, UserId},
+    },
+};
+
+/// Numeric replies, e.g. to a remote WHOIS, which get passed on to the user they're for.
+pub struct Numeric(pub String);
+
+#[async_trait]
+impl Ts6Handler for Numeric {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        server_status: Ts6,
+        _my_sid: ServerId,
+        sender: Option<CommandSender>,
+        _hostname: &str,
+    ) -> Vec<Ts6Action> {
+        let Some(CommandSender::Server(server_id)) = sender else {
+            return vec![];
+        };
+        let Some((target, arguments)) = command.split_first() else {
+            return vec![];
+        };
+        let Some(target) = UserId::try_from(target.clone())
+            .ok()
+            .filter(|target| NETWORK.is_local(target))
+        else {
+            return vec![];
+        };
+
+        let server = NETWORK
+            .get_server(&server_id)
+            .map(|server| server.name)
+            .unwrap_or(server_status.hostname);
+
+        vec![Ts6Action::SendMessage(Box::new(Message::ServerReply(
+            ServerReply {
+                server,
+                target,
+                command: self.0.clone(),
+                arguments: arguments.to_vec(),
+            },
+        )))]
+    }
+}
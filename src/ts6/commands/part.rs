@@ -0,0 +1,47 @@
+use async_trait::async_trait;
+
+use crate::{
+    messages::{Message, PartMessage},
+    state::NETWORK,
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::ServerId,
+    },
+};
+
+pub struct Part;
+
+#[async_trait]
+impl Ts6Handler for Part {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        _server_status: Ts6,
+        _my_sid: ServerId,
+        sender: Option<CommandSender>,
+        _hostname: &str,
+    ) -> Vec<Ts6Action> {
+        let Some(CommandSender::User(user_id)) = sender else {
+            return vec![];
+        };
+        let (Some(user), Some(channels)) = (NETWORK.get_user(&user_id), command.first()) else {
+            return vec![];
+        };
+        let reason = command.get(1).filter(|reason| !reason.is_empty());
+
+        channels
+            .split(',')
+            .filter_map(|name| NETWORK.part_channel(name, &user_id).ok())
+            .map(|channel| {
+                Ts6Action::SendMessage(Box::new(Message::PartMessage(PartMessage {
+                    sender: user.clone(),
+                    channel,
+                    reason: reason.cloned(),
+                })))
+            })
+            .collect()
+    }
+}
@@ -2,6 +2,7 @@ use async_trait::async_trait;
 
 use crate::{
     sender::IrcResponse,
+    tags::Tags,
     ts6::{
         ServerId, Ts6,
         commands::{CommandSender, Ts6Action, Ts6Handler},
@@ -15,12 +16,14 @@ impl Ts6Handler for Ping {
     async fn handle(
         &self,
         command: Vec<String>,
+        _tags: Tags,
         _server_status: Ts6,
         my_sid: ServerId,
         sender: Option<CommandSender>,
         _hostname: &str,
     ) -> Vec<Ts6Action> {
         vec![Ts6Action::SendText(IrcResponse {
+            tags: Tags::new(),
             sender: None,
             command: "PONG".into(),
             arguments: Vec::new(),
@@ -1,14 +1,15 @@
 use async_trait::async_trait;
 
 use crate::{
-    FOREIGN_CONNECTED_USERS,
+    chanmodes::split_status_prefix,
     messages::{PrivMessage, Receiver},
+    state::NETWORK,
+    tags::{Tags, relayed_message_tags},
     ts6::{
         Ts6,
         commands::{CommandSender, Ts6Action, Ts6Handler},
         structs::{ServerId, UserId},
     },
-    user::UserUnwrapped,
 };
 
 pub struct Privmsg;
@@ -18,41 +19,38 @@ impl Ts6Handler for Privmsg {
     async fn handle(
         &self,
         command: Vec<String>,
-        server_status: Ts6,
-        my_sid: ServerId,
+        tags: Tags,
+        _server_status: Ts6,
+        _my_sid: ServerId,
         sender: Option<CommandSender>,
-        hostname: &str,
+        _hostname: &str,
     ) -> Vec<Ts6Action> {
-        'priv_msg: {
-            let mut sending_user: Option<UserUnwrapped> = None;
+        let Some(CommandSender::User(command_sender)) = sender else {
+            return vec![];
+        };
+        let Some(sending_user) = NETWORK.get_user(&command_sender) else {
+            return vec![];
+        };
 
-            dbg!(&sender);
+        let [target, text, ..] = command.as_slice() else {
+            return vec![];
+        };
 
-            if let Ok(user_id) = UserId::try_from(command[0].clone()) {
-                if let Some(CommandSender::User(command_sender)) = sender {
-                    let foreign_users = FOREIGN_CONNECTED_USERS.lock().await;
+        let receiver = if let Ok(user_id) = UserId::try_from(target.clone()) {
+            Receiver::UserId(user_id)
+        } else if split_status_prefix(target).1.starts_with('#') {
+            Receiver::ChannelName(target.clone())
+        } else {
+            return vec![];
+        };
 
-                    for user in foreign_users.iter() {
-                        if user.user_id == command_sender {
-                            sending_user = Some(user.clone())
-                        }
-                    }
-                } else {
-                    dbg!("sender");
-                    break 'priv_msg vec![];
-                }
-
-                vec![Ts6Action::SendMessage(
-                    crate::messages::Message::PrivMessage(PrivMessage {
-                        sender: sending_user.unwrap(),
-                        receiver: Receiver::UserId(user_id),
-                        text: command[1].clone(),
-                    }),
-                )]
-            } else {
-                dbg!("userid");
-                vec![]
-            }
-        }
+        vec![Ts6Action::SendMessage(Box::new(
+            crate::messages::Message::PrivMessage(PrivMessage {
+                sender: sending_user,
+                receiver,
+                text: text.clone(),
+                tags: relayed_message_tags(&tags),
+            }),
+        ))]
     }
 }
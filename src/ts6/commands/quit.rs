@@ -0,0 +1,34 @@
+use async_trait::async_trait;
+
+use crate::{
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::ServerId,
+    },
+};
+
+pub struct Quit;
+
+#[async_trait]
+impl Ts6Handler for Quit {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        _server_status: Ts6,
+        _my_sid: ServerId,
+        sender: Option<CommandSender>,
+        _hostname: &str,
+    ) -> Vec<Ts6Action> {
+        let Some(CommandSender::User(user_id)) = sender else {
+            return vec![];
+        };
+
+        vec![Ts6Action::QuitUser {
+            user_id,
+            reason: command.first().cloned().unwrap_or_default(),
+        }]
+    }
+}
@@ -1,6 +1,10 @@
-use crate::ts6::{
-    ServerId, Ts6,
-    commands::{CommandSender, Ts6Action, Ts6Handler},
+use crate::{
+    state::{LinkedServer, NETWORK},
+    tags::Tags,
+    ts6::{
+        ServerId, Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+    },
 };
 use async_trait::async_trait;
 
@@ -13,6 +17,7 @@ impl Ts6Handler for Server {
     async fn handle(
         &self,
         command: Vec<String>,
+        _tags: Tags,
         _server_status: Ts6,
         my_sid: ServerId,
         sender: Option<CommandSender>,
@@ -28,6 +33,17 @@ impl Ts6Handler for Server {
             }
         };
         let _flags = Some(command[3].clone());
+
+        if let (Some(sid), Some(name)) = (&sid, &name) {
+            NETWORK.add_server(
+                sid.clone(),
+                LinkedServer {
+                    name: name.clone(),
+                    description: command[4].clone(),
+                    uplink: None,
+                },
+            );
+        }
         let description = Some(command[4].clone());
 
         println!("server cmd");
@@ -42,6 +58,7 @@ impl Ts6Handler for Server {
                 identified: Some(true),
             }),
             Ts6Action::SendText(crate::sender::IrcResponse {
+                tags: Tags::new(),
                 sender: Some(hostname.to_owned().clone()),
                 command: "SERVER".to_owned(),
                 receiver: None,
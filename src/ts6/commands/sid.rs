@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+
+use crate::{
+    state::{LinkedServer, NETWORK},
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::ServerId,
+    },
+};
+
+/// A server linked behind the one that sends this.
+pub struct Sid;
+
+#[async_trait]
+impl Ts6Handler for Sid {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        _server_status: Ts6,
+        _my_sid: ServerId,
+        sender: Option<CommandSender>,
+        _hostname: &str,
+    ) -> Vec<Ts6Action> {
+        let Some(CommandSender::Server(uplink)) = sender else {
+            return vec![];
+        };
+        let [name, _hopcount, sid, description, ..] = command.as_slice() else {
+            return vec![];
+        };
+        let Ok(sid) = ServerId::try_from(sid.clone()) else {
+            return vec![];
+        };
+
+        NETWORK.add_server(
+            sid,
+            LinkedServer {
+                name: name.clone(),
+                description: description.clone(),
+                uplink: Some(uplink),
+            },
+        );
+
+        vec![]
+    }
+}
@@ -2,8 +2,10 @@ use std::time::SystemTime;
 
 use crate::{
     sender::IrcResponse,
+    tags::Tags,
     ts6::{
         ServerId, Ts6,
+        burst::burst,
         commands::{CommandSender, Ts6Action, Ts6Handler},
     },
 };
@@ -19,8 +21,9 @@ impl Ts6Handler for Svinfo {
     async fn handle(
         &self,
         command: Vec<String>,
-        _server_status: Ts6,
-        _my_sid: ServerId,
+        _tags: Tags,
+        server_status: Ts6,
+        my_sid: ServerId,
         sender: Option<CommandSender>,
         _hostname: &str,
     ) -> Vec<Ts6Action> {
@@ -36,12 +39,20 @@ impl Ts6Handler for Svinfo {
         assert_eq!(ts_current, TS_CURRENT);
         assert_eq!(ts_minimum, TS_MINIMUM);
 
-        vec![Ts6Action::SendText(IrcResponse {
+        let svinfo = IrcResponse {
+            tags: Tags::new(),
             sender: None,
             command: "SVINFO".to_owned(),
             receiver: None,
             arguments: vec!["6".to_owned(), "6".to_owned(), "0".to_owned()],
             message: format!(":{}", current_time),
-        })]
+        };
+
+        // the handshake is done, so the other side gets to know the network
+        [svinfo]
+            .into_iter()
+            .chain(burst(&my_sid, &server_status.server_id))
+            .map(Ts6Action::SendText)
+            .collect()
     }
 }
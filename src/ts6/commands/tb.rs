@@ -0,0 +1,68 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use async_trait::async_trait;
+
+use crate::{
+    messages::{Message, TopicMessage},
+    state::NETWORK,
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::ServerId,
+    },
+};
+
+/// Topic burst: `TB #channel topicts [setter] :topic`
+pub struct Tb;
+
+#[async_trait]
+impl Ts6Handler for Tb {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        server_status: Ts6,
+        _my_sid: ServerId,
+        sender: Option<CommandSender>,
+        _hostname: &str,
+    ) -> Vec<Ts6Action> {
+        let Some(CommandSender::Server(_)) = sender else {
+            return vec![];
+        };
+        let (name, timestamp, setter, text) = match command.as_slice() {
+            [name, timestamp, text] => (name, timestamp, &server_status.hostname, text),
+            [name, timestamp, setter, text] => (name, timestamp, setter, text),
+            _ => return vec![],
+        };
+        let Ok(timestamp) = timestamp.parse::<u64>() else {
+            return vec![];
+        };
+
+        let changed = NETWORK.update_channel(name, |channel| {
+            // the older topic wins
+            if channel
+                .topic
+                .as_ref()
+                .is_some_and(|topic| topic.timestamp() <= timestamp)
+            {
+                return None;
+            }
+
+            channel
+                .set_topic(text, setter, UNIX_EPOCH + Duration::from_secs(timestamp))
+                .then(|| channel.clone())
+        });
+
+        match changed.flatten() {
+            Some(channel) => vec![Ts6Action::SendMessage(Box::new(Message::TopicMessage(
+                TopicMessage {
+                    setter: server_status.hostname,
+                    user_id: None,
+                    channel,
+                },
+            )))],
+            None => vec![],
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use async_trait::async_trait;
+
+use crate::{
+    chanmodes::parse_mode_changes,
+    messages::{ChannelModeMessage, Message},
+    state::NETWORK,
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::ServerId,
+    },
+};
+
+pub struct Tmode;
+
+#[async_trait]
+impl Ts6Handler for Tmode {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        _server_status: Ts6,
+        _my_sid: ServerId,
+        sender: Option<CommandSender>,
+        _hostname: &str,
+    ) -> Vec<Ts6Action> {
+        // TODO: modes set by servers
+        let Some(CommandSender::User(user_id)) = sender else {
+            return vec![];
+        };
+        let (Some(user), [timestamp, name, modestring, parameters @ ..]) =
+            (NETWORK.get_user(&user_id), command.as_slice())
+        else {
+            return vec![];
+        };
+        let Ok(timestamp) = timestamp.parse::<u64>() else {
+            return vec![];
+        };
+
+        let (changes, _) = parse_mode_changes(modestring, parameters);
+
+        let applied = NETWORK.update_channel(name, |channel| {
+            // modes from a younger channel lose
+            if timestamp > channel.timestamp() {
+                return None;
+            }
+
+            let applied = channel.apply_modes(changes, &user.hostmask());
+
+            Some((channel.clone(), applied))
+        });
+
+        match applied.flatten() {
+            Some((channel, changes)) if !changes.is_empty() => {
+                vec![Ts6Action::SendMessage(Box::new(
+                    Message::ChannelModeMessage(ChannelModeMessage {
+                        sender: user,
+                        channel,
+                        changes,
+                    }),
+                ))]
+            }
+            _ => vec![],
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+
+use crate::{
+    messages::{Message, TopicMessage},
+    state::NETWORK,
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::ServerId,
+    },
+};
+
+pub struct Topic;
+
+#[async_trait]
+impl Ts6Handler for Topic {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        _server_status: Ts6,
+        _my_sid: ServerId,
+        sender: Option<CommandSender>,
+        _hostname: &str,
+    ) -> Vec<Ts6Action> {
+        let Some(CommandSender::User(user_id)) = sender else {
+            return vec![];
+        };
+        let (Some(user), [name, text, ..]) = (NETWORK.get_user(&user_id), command.as_slice())
+        else {
+            return vec![];
+        };
+        let setter = user.hostmask();
+
+        let changed = NETWORK.update_channel(name, |channel| {
+            channel
+                .set_topic(text, &setter, SystemTime::now())
+                .then(|| channel.clone())
+        });
+
+        match changed.flatten() {
+            Some(channel) => vec![Ts6Action::SendMessage(Box::new(Message::TopicMessage(
+                TopicMessage {
+                    setter,
+                    user_id: Some(user_id),
+                    channel,
+                },
+            )))],
+            None => vec![],
+        }
+    }
+}
@@ -1,11 +1,14 @@
 use std::{
     net::{IpAddr, Ipv4Addr},
     str::FromStr,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{Duration, UNIX_EPOCH},
 };
 
 use crate::{
-    FOREIGN_CONNECTED_USERS,
+    routing,
+    sender::IrcResponse,
+    state::NETWORK,
+    tags::Tags,
     ts6::{
         ServerId, Ts6,
         commands::{CommandSender, Ts6Action, Ts6Handler},
@@ -23,9 +26,10 @@ impl Ts6Handler for Uid {
     async fn handle(
         &self,
         command: Vec<String>,
-        server_status: Ts6,
-        my_sid: ServerId,
-        sender: Option<CommandSender>,
+        _tags: Tags,
+        _server_status: Ts6,
+        _my_sid: ServerId,
+        _sender: Option<CommandSender>,
         hostname: &str,
     ) -> Vec<Ts6Action> {
         let username = command[0].clone();
@@ -46,13 +50,86 @@ impl Ts6Handler for Uid {
             usermodes,
             timestamp,
             ip,
+            account: None,
+            certfp: None,
+            away: None,
         };
 
         dbg!(&user);
 
-        let mut foreign_users = FOREIGN_CONNECTED_USERS.lock().await;
-        foreign_users.insert(user.clone());
+        let reason = format!("{hostname} (Nick collision)");
+        if let Some(existing) = NETWORK
+            .find_nick(&user.nickname)
+            .and_then(|user_id| NETWORK.get_user(&user_id))
+        {
+            // the newer one loses, both of them if they're the same age
+            if existing.timestamp >= user.timestamp {
+                routing::kill(&existing.user_id, &reason).await;
+            }
+            if existing.timestamp <= user.timestamp {
+                return vec![kill_reply(&user.user_id, &reason)];
+            }
+        }
+
+        // the nick might have been taken again in the meantime
+        if NETWORK.add_user(user.clone(), false).is_err() {
+            return vec![kill_reply(&user.user_id, &reason)];
+        }
 
         vec![]
     }
 }
+
+/// The user never made it onto our side, so only the server that sent them needs to hear about it
+fn kill_reply(user_id: &UserId, reason: &str) -> Ts6Action {
+    Ts6Action::SendText(IrcResponse {
+        tags: Tags::new(),
+        sender: None,
+        command: "KILL".to_owned(),
+        receiver: None,
+        arguments: vec![user_id.to_string()],
+        message: format!(":{reason}"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{state::NETWORK, test_client::TestClient, ts6::structs::UserId};
+
+    #[tokio::test]
+    async fn test_nick_collision() {
+        let mut alice = TestClient::register("calice").await;
+        let mut bob = TestClient::register("cbob").await;
+        let mut server = TestClient::link("9C0", "collide.example.com").await;
+
+        // older than the local calice, who has to make way
+        server
+            .send(":9C0 UID calice 1 1 + ~calice 127.0.0.1 127.0.0.1 127.0.0.1 9C0AAAAAA * :calice")
+            .await;
+        let kill = server.recv_until("KILL").await.pop().unwrap();
+        assert!(kill.starts_with(":000 KILL 000"));
+        assert!(kill.ends_with(" :irc.example.com (Nick collision)"));
+        assert_eq!(
+            alice.recv().await.as_deref(),
+            Some("ERROR :Closing Link: 127.0.0.1 (Killed (irc.example.com (Nick collision)))")
+        );
+        assert_eq!(
+            NETWORK.find_nick("calice"),
+            UserId::try_from("9C0AAAAAA".to_owned()).ok()
+        );
+
+        // newer than the local cbob, so it gets turned away
+        server
+            .send(":9C0 UID cbob 1 4000000000 + ~cbob 127.0.0.1 127.0.0.1 127.0.0.1 9C0AAAAAB * :cbob")
+            .await;
+        assert_eq!(
+            server.recv_until("KILL").await.last().map(String::as_str),
+            Some(":000 KILL 9C0AAAAAB :irc.example.com (Nick collision)")
+        );
+        bob.send("PING :still here").await;
+        assert_eq!(
+            bob.recv().await.as_deref(),
+            Some(":irc.example.com PONG cbob :still here")
+        );
+    }
+}
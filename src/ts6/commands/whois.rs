@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+
+use crate::{
+    commands::whois::whois_replies,
+    state::NETWORK,
+    tags::Tags,
+    ts6::{
+        Ts6,
+        commands::{CommandSender, Ts6Action, Ts6Handler},
+        structs::{ServerId, UserId},
+    },
+};
+
+/// A remote user asking for the WHOIS of one of ours: `WHOIS targetuid :nick`
+pub struct Whois;
+
+#[async_trait]
+impl Ts6Handler for Whois {
+    async fn handle(
+        &self,
+        command: Vec<String>,
+        _tags: Tags,
+        _server_status: Ts6,
+        _my_sid: ServerId,
+        sender: Option<CommandSender>,
+        hostname: &str,
+    ) -> Vec<Ts6Action> {
+        let Some(CommandSender::User(user_id)) = sender else {
+            return vec![];
+        };
+        let (Some(requester), Some(target)) = (NETWORK.get_user(&user_id), command.first()) else {
+            return vec![];
+        };
+        let Some(target) = UserId::try_from(target.clone())
+            .ok()
+            .or_else(|| NETWORK.find_nick(target))
+            .filter(|target_id| NETWORK.is_local(target_id))
+            .and_then(|target_id| NETWORK.get_user(&target_id))
+        else {
+            return vec![];
+        };
+
+        whois_replies(&requester, &target, &user_id.to_string(), hostname)
+            .into_iter()
+            .map(Ts6Action::SendText)
+            .collect()
+    }
+}
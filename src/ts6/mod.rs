@@ -1,21 +1,17 @@
 // TODO: better error handling
 
-use std::{
-    net::TcpStream,
-    time::{Duration, UNIX_EPOCH},
-};
-use tokio::{
-    io::{AsyncBufReadExt, BufReader as TokioBufReader, BufWriter as TokioBufWriter},
-    net::TcpStream as TokioTcpStream,
-    sync::broadcast::Receiver,
-    time::sleep,
-};
+use std::time::UNIX_EPOCH;
 
 use crate::{
+    chanmodes::render_changes,
     config::ServerInfo,
-    messages::Message,
+    connection::IrcSink,
+    messages::{Message, Receiver},
     sender::IrcResponse,
+    state::NETWORK,
+    tags::Tags,
     ts6::{commands::Ts6Command, structs::ServerId},
+    user::UserUnwrapped,
 };
 
 #[derive(Clone, Debug, Default)]
@@ -28,20 +24,32 @@ pub struct Ts6 {
     identified: bool,
 }
 
+mod burst;
 mod commands;
 pub mod structs;
 
 impl Ts6 {
+    /// Whether the other side introduced itself with SERVER yet
+    pub fn is_identified(&self) -> bool {
+        self.identified
+    }
+
     pub async fn handle_command(
         &mut self,
         _my_server_id: &ServerId,
         args: String,
         hostname: &str,
         my_sid: &ServerId,
-        writer: &mut TokioBufWriter<TokioTcpStream>,
+        writer: &mut dyn IrcSink,
     ) {
         println!("server command: {}", self.server_id);
-        let args = Ts6Command::new(args).await;
+        let args = match Ts6Command::new(args).await {
+            Ok(args) => args,
+            Err(error) => {
+                println!("couldn't parse server command: {error}");
+                return;
+            }
+        };
         println!("args: {args:#?}");
 
         // XXX
@@ -53,39 +61,22 @@ impl Ts6 {
 
     pub async fn tcp_listener(
         &self,
-        stream: &TcpStream,
+        line: String,
         info: &ServerInfo,
-        reader: &mut TokioBufReader<TokioTcpStream>,
+        writer: &mut dyn IrcSink,
         my_server_id: &ServerId,
     ) -> Result<Ts6, anyhow::Error> {
-        let mut buffer = String::new();
         let mut self_clone = self.clone();
 
-        let mut writer = TokioBufWriter::new(TokioTcpStream::from_std(stream.try_clone()?)?);
-
-        match reader.read_line(&mut buffer).await {
-            Ok(0) => anyhow::bail!(""),
-            Ok(_) => {}
-
-            Err(_) => {
-                anyhow::bail!("");
-            }
-        }
-
-        println!("ts6: {buffer}");
-
-        let args = buffer
-            .split_whitespace()
-            .map(|x| x.to_string())
-            .collect::<Vec<String>>();
+        println!("ts6: {line}");
 
         self_clone
             .handle_command(
                 my_server_id,
-                args.join(" "),
+                line,
                 &info.server_hostname,
                 my_server_id,
-                &mut writer,
+                writer,
             )
             .await;
 
@@ -94,50 +85,222 @@ impl Ts6 {
 
     pub async fn message_listener(
         &self,
-        receiver: &mut Receiver<Message>,
-        writer: &mut TokioBufWriter<TokioTcpStream>,
+        message: Message,
+        writer: &mut dyn IrcSink,
         my_sid: &ServerId,
         hostname: &str,
     ) -> Result<(), anyhow::Error> {
         if !self.identified {
-            sleep(Duration::from_millis(250)).await; // avoid immediate returns b'cuz they result in high
-            // cpu usage
-            return Ok(()); // TODO: error handling
+            return Ok(());
         }
 
-        let message: Message = receiver.recv().await.unwrap();
-
         match message {
             Message::NetJoinMessage(net_join_message) => {
-                let user = net_join_message.user.clone();
+                uid_line(&net_join_message.user)
+                    .send(hostname, writer, false)
+                    .await?;
+            }
+
+            Message::PrivMessage(privmsg) => {
+                let target = match privmsg.receiver {
+                    Receiver::ChannelName(name) => name,
+                    Receiver::UserId(user_id) => user_id.to_string(),
+                    Receiver::Username(nickname) => match NETWORK.find_nick(&nickname) {
+                        Some(user_id) => user_id.to_string(),
+                        None => return Ok(()),
+                    },
+                };
+
+                IrcResponse {
+                    tags: Tags::new(),
+                    sender: Some(privmsg.sender.user_id.to_string()),
+                    command: "PRIVMSG".to_owned(),
+                    receiver: None,
+                    arguments: vec![target],
+                    message: format!(":{}", privmsg.text),
+                }
+                .send(hostname, writer, false)
+                .await?;
+            }
+
+            Message::ChannelModeMessage(change) => {
+                // servers know members by UID, not by nickname
+                let changes = change
+                    .changes
+                    .into_iter()
+                    .map(|mut change| {
+                        if change.mode.member_status().is_some() {
+                            change.parameter = change
+                                .parameter
+                                .and_then(|nickname| NETWORK.find_nick(&nickname))
+                                .map(|user_id| user_id.to_string());
+                        }
+
+                        change
+                    })
+                    .collect::<Vec<_>>();
+                let (modestring, parameters) = render_changes(&changes);
 
-                // TODO: refactor this entire thing. we need hostmask and ip and such fully working
                 IrcResponse {
-                    sender: Some(my_sid.clone().to_string()),
-                    command: "UID".to_string(),
+                    tags: Tags::new(),
+                    sender: Some(change.sender.user_id.to_string()),
+                    command: "TMODE".to_owned(),
+                    receiver: None,
+                    arguments: [
+                        vec![
+                            change.channel.timestamp().to_string(),
+                            change.channel.name,
+                            modestring,
+                        ],
+                        parameters,
+                    ]
+                    .concat(),
+                    message: String::new(),
+                }
+                .send(hostname, writer, false)
+                .await?;
+            }
+
+            Message::PartMessage(part) => {
+                IrcResponse {
+                    tags: Tags::new(),
+                    sender: Some(part.sender.user_id.to_string()),
+                    command: "PART".to_owned(),
+                    receiver: None,
+                    arguments: vec![part.channel.name],
+                    message: part
+                        .reason
+                        .map(|reason| format!(":{reason}"))
+                        .unwrap_or_default(),
+                }
+                .send(hostname, writer, false)
+                .await?;
+            }
+
+            Message::NickChangeMessage(change) => {
+                IrcResponse {
+                    tags: Tags::new(),
+                    sender: Some(change.user.user_id.to_string()),
+                    command: "NICK".to_owned(),
                     receiver: None,
                     arguments: vec![
-                        user.nickname.clone(),
-                        (user.hopcount + 1).to_string(),
-                        user.timestamp
+                        change.nickname,
+                        change
+                            .timestamp
                             .duration_since(UNIX_EPOCH)
                             .unwrap()
                             .as_secs()
                             .to_string(),
-                        user.usermodes.into(),
-                        format!("~{}", user.username.clone()),
-                        user.ip.to_string(),
-                        user.ip.to_string(),
-                        user.ip.to_string(),
-                        user.user_id.to_string().clone(),
-                        "*".to_owned(),
-                        format!(":{}", user.username.clone()),
                     ],
                     message: String::new(),
                 }
                 .send(hostname, writer, false)
-                .await
-                .unwrap();
+                .await?;
+            }
+
+            Message::KickMessage(kick) => {
+                IrcResponse {
+                    tags: Tags::new(),
+                    sender: Some(kick.sender.user_id.to_string()),
+                    command: "KICK".to_owned(),
+                    receiver: None,
+                    arguments: vec![kick.channel.name, kick.target.user_id.to_string()],
+                    message: format!(":{}", kick.reason),
+                }
+                .send(hostname, writer, false)
+                .await?;
+            }
+
+            Message::InviteMessage(invite) => {
+                IrcResponse {
+                    tags: Tags::new(),
+                    sender: Some(invite.sender.user_id.to_string()),
+                    command: "INVITE".to_owned(),
+                    receiver: None,
+                    arguments: vec![
+                        invite.target.user_id.to_string(),
+                        invite.channel.name.clone(),
+                    ],
+                    message: invite.channel.timestamp().to_string(),
+                }
+                .send(hostname, writer, false)
+                .await?;
+            }
+
+            Message::WhoisRequest(request) => {
+                IrcResponse {
+                    tags: Tags::new(),
+                    sender: Some(request.sender.user_id.to_string()),
+                    command: "WHOIS".to_owned(),
+                    receiver: None,
+                    arguments: vec![request.target.user_id.to_string()],
+                    message: format!(":{}", request.target.nickname),
+                }
+                .send(hostname, writer, false)
+                .await?;
+            }
+
+            Message::TopicMessage(topic) => {
+                let Some(user_id) = topic.user_id else {
+                    return Ok(());
+                };
+
+                IrcResponse {
+                    tags: Tags::new(),
+                    sender: Some(user_id.to_string()),
+                    command: "TOPIC".to_owned(),
+                    receiver: None,
+                    arguments: vec![topic.channel.name],
+                    message: format!(
+                        ":{}",
+                        topic.channel.topic.map(|x| x.text).unwrap_or_default()
+                    ),
+                }
+                .send(hostname, writer, false)
+                .await?;
+            }
+
+            Message::AwayMessage(away) => {
+                IrcResponse {
+                    tags: Tags::new(),
+                    sender: Some(away.user.user_id.to_string()),
+                    command: "AWAY".to_owned(),
+                    receiver: None,
+                    arguments: Vec::new(),
+                    message: away
+                        .user
+                        .away
+                        .map(|text| format!(":{text}"))
+                        .unwrap_or_default(),
+                }
+                .send(hostname, writer, false)
+                .await?;
+            }
+
+            Message::QuitMessage(quit) => {
+                IrcResponse {
+                    tags: Tags::new(),
+                    sender: Some(quit.user.user_id.to_string()),
+                    command: "QUIT".to_owned(),
+                    receiver: None,
+                    arguments: Vec::new(),
+                    message: format!(":{}", quit.reason),
+                }
+                .send(hostname, writer, false)
+                .await?;
+            }
+
+            Message::KillMessage(kill) => {
+                IrcResponse {
+                    tags: Tags::new(),
+                    sender: Some(my_sid.to_string()),
+                    command: "KILL".to_owned(),
+                    receiver: None,
+                    arguments: vec![kill.user.user_id.to_string()],
+                    message: format!(":{}", kill.reason),
+                }
+                .send(hostname, writer, false)
+                .await?;
             }
 
             _ => {}
@@ -146,3 +309,32 @@ impl Ts6 {
         Ok(())
     }
 }
+
+/// Introduces a user to a server, on behalf of the server they're on.
+fn uid_line(user: &UserUnwrapped) -> IrcResponse {
+    // TODO: refactor this entire thing. we need hostmask and ip and such fully working
+    IrcResponse {
+        tags: Tags::new(),
+        sender: Some(user.user_id.get_server_id().to_string()),
+        command: "UID".to_string(),
+        receiver: None,
+        arguments: vec![
+            user.nickname.clone(),
+            (user.hopcount + 1).to_string(),
+            user.timestamp
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                .to_string(),
+            user.usermodes.clone().into(),
+            format!("~{}", user.username.clone()),
+            user.ip.to_string(),
+            user.ip.to_string(),
+            user.ip.to_string(),
+            user.user_id.to_string().clone(),
+            "*".to_owned(),
+            format!(":{}", user.username.clone()),
+        ],
+        message: String::new(),
+    }
+}
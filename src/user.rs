@@ -1,11 +1,12 @@
 #![allow(dead_code)]
 
 use std::{
+    collections::BTreeSet,
     net::{IpAddr, Ipv4Addr},
     time::SystemTime,
 };
 
-use crate::{ts6::structs::UserId, usermodes::Usermodes};
+use crate::{sasl::SaslSession, ts6::structs::UserId, usermodes::Usermodes};
 
 #[derive(Clone, Debug, Hash, Eq, PartialEq, PartialOrd, Ord)]
 pub struct User {
@@ -19,6 +20,19 @@ pub struct User {
     pub timestamp: Option<SystemTime>,
     pub ip: Option<IpAddr>,
     // pub hostname: Option<String>,
+    /// IRCv3 capabilities the client has enabled
+    pub capabilities: BTreeSet<String>,
+    /// CAP LS version the client sent, 0 if it never sent CAP LS
+    pub cap_version: u16,
+    /// registration is held back while this is set, until the client sends CAP END
+    pub cap_negotiating: bool,
+    /// account the user logged into using SASL
+    pub account: Option<String>,
+    pub sasl_session: Option<SaslSession>,
+    /// SHA-256 fingerprint of the TLS client certificate, if the client sent one
+    pub certfp: Option<String>,
+    /// away message, if the user is away
+    pub away: Option<String>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
@@ -33,6 +47,9 @@ pub struct UserUnwrapped {
     pub timestamp: SystemTime,
     pub ip: IpAddr,
     // pub hostname: Option<String>,
+    pub account: Option<String>,
+    pub certfp: Option<String>,
+    pub away: Option<String>,
 }
 
 impl User {
@@ -40,8 +57,23 @@ impl User {
         self.realname.is_some() && self.username.is_some() && self.nickname.is_some()
     }
 
+    /// Whether registration went through, NICK and USER alone aren't enough while CAP is going on
     pub fn is_populated(&self) -> bool {
-        self.realname.is_some() && self.username.is_some() && self.nickname.is_some()
+        self.identified && self.user_id.is_some() && self.is_populated_without_uid()
+    }
+
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.contains(capability)
+    }
+
+    /// Hostmask of a user who might not have finished registering yet
+    pub fn partial_hostmask(&self) -> String {
+        format!(
+            "{}!~{}@{}",
+            self.nickname.clone().unwrap_or("*".to_owned()),
+            self.username.clone().unwrap_or("*".to_owned()),
+            "unimplement.ed"
+        )
     }
 
     pub fn unwrap_all(&self) -> UserUnwrapped {
@@ -50,11 +82,14 @@ impl User {
             username: self.username.clone().unwrap(),
             realname: self.realname.clone().unwrap(),
             identified: self.identified,
-            hopcount: self.hopcount.clone().unwrap(),
+            hopcount: self.hopcount.unwrap(),
             user_id: self.user_id.clone().unwrap(),
             usermodes: self.usermodes.clone(),
-            timestamp: self.timestamp.clone().unwrap(),
+            timestamp: self.timestamp.unwrap(),
             ip: self.ip.unwrap_or(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
+            account: self.account.clone(),
+            certfp: self.certfp.clone(),
+            away: self.away.clone(),
         }
     }
 
@@ -69,6 +104,13 @@ impl User {
             usermodes: Usermodes::default(),
             timestamp: None,
             ip: None,
+            capabilities: BTreeSet::new(),
+            cap_version: 0,
+            cap_negotiating: false,
+            account: None,
+            sasl_session: None,
+            certfp: None,
+            away: None,
         }
     }
 }
@@ -79,7 +121,12 @@ impl UserUnwrapped {
             "{}!~{}@{}",
             self.nickname.clone(),
             self.username.clone(),
-            "unimplement.ed"
+            self.host()
         )
     }
+
+    // TODO: resolve the hostname
+    pub fn host(&self) -> String {
+        "unimplement.ed".to_owned()
+    }
 }
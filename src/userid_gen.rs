@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 use once_cell::sync::Lazy;
 use tokio::sync::Mutex;
 
@@ -6,6 +8,8 @@ use thiserror::Error;
 static CURRENT_ID: Lazy<Mutex<Vec<char>>> =
     Lazy::new(|| Mutex::new(vec!['A', 'A', 'A', 'A', 'A', 'A']));
 static ZZZZZZ_REACHED: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(false));
+/// ids of users who left, handed out again once the counter runs out
+static RELEASED_IDS: Lazy<Mutex<VecDeque<Vec<char>>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
 
 #[derive(Debug, Error)]
 pub enum UidIncreaseError {
@@ -17,10 +21,31 @@ pub async fn increase_user_id() -> Result<Vec<char>, UidIncreaseError> {
     let mut current_id = CURRENT_ID.lock().await;
     let mut zzzzzz_reached = ZZZZZZ_REACHED.lock().await;
 
+    match next_user_id(&mut current_id, &mut zzzzzz_reached) {
+        Err(UidIncreaseError::UserCapReached) => RELEASED_IDS
+            .lock()
+            .await
+            .pop_front()
+            .ok_or(UidIncreaseError::UserCapReached),
+        result => result,
+    }
+}
+
+/// Give back the id of a user who disconnected.
+pub async fn release_user_id(id: Vec<char>) {
+    RELEASED_IDS.lock().await.push_back(id);
+}
+
+/// Advance `current_id` to the next free id. Split out of `increase_user_id` so tests can run
+/// through the id space without touching the global counter.
+pub fn next_user_id(
+    current_id: &mut Vec<char>,
+    zzzzzz_reached: &mut bool,
+) -> Result<Vec<char>, UidIncreaseError> {
     let mut idx = 5;
 
     'id_increaser: {
-        if !zzzzzz_reached.clone() {
+        if !*zzzzzz_reached {
             loop {
                 if current_id[idx] != 'Z' {
                     current_id[idx] = (current_id[idx] as u8 + 1) as char;
@@ -39,7 +64,7 @@ pub async fn increase_user_id() -> Result<Vec<char>, UidIncreaseError> {
             // if we get here, our id is ZZZZZZ and we need to start using numbers
             idx = 5;
 
-            (*current_id) = vec!['A', '0', '0', '0', '0', '0'];
+            *current_id = vec!['A', '0', '0', '0', '0', '0'];
         }
 
         loop {
@@ -75,3 +100,42 @@ pub async fn manually_set_user_id(user_id: Vec<char>) {
 
     (*lock) = user_id;
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::userid_gen::next_user_id;
+
+    // The other tests register clients in this process, so this can't run the global counter out
+    // of ids the way it used to with `manually_set_user_id`, and works on its own counter instead.
+    #[test]
+    fn test_user_id_generator() {
+        let mut current_id = vec!['A', 'A', 'A', 'A', 'A', 'A'];
+        let mut zzzzzz_reached = false;
+
+        while let Ok(userid) = next_user_id(&mut current_id, &mut zzzzzz_reached) {
+            if userid == ['A', 'B', 'C', 'D', 'E', 'F'] {
+                current_id = ['Z', 'Z', 'Z', 'Z', 'Z', 'Y'].to_vec();
+                break;
+            }
+        }
+
+        assert_eq!(
+            next_user_id(&mut current_id, &mut zzzzzz_reached).unwrap(),
+            ['Z', 'Z', 'Z', 'Z', 'Z', 'Z']
+        );
+        assert_eq!(
+            next_user_id(&mut current_id, &mut zzzzzz_reached).unwrap(),
+            ['A', '0', '0', '0', '0', '1']
+        );
+
+        while let Ok(userid) = next_user_id(&mut current_id, &mut zzzzzz_reached) {
+            if userid == ['A', '1', '2', '3', '4', '5'] {
+                // ff a bit
+                current_id = ['Z', '1', '2', '3', '4', '5'].to_vec();
+            }
+        }
+
+        // the id space is exhausted once the first character would go past Z
+        assert_eq!(current_id[0], 'Z');
+    }
+}
@@ -3,16 +3,58 @@
 pub enum Usermode {
     Invisible = b'i',
     HostHiding = b'x',
+    Operator = b'o',
+    /// connected using TLS
+    Secure = b'Z',
+}
+
+impl Usermode {
+    pub const ALL: [Usermode; 4] = [
+        Usermode::Invisible,
+        Usermode::HostHiding,
+        Usermode::Operator,
+        Usermode::Secure,
+    ];
+
+    pub fn from_char(mode: char) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|x| char::from(x.clone()) == mode)
+    }
+}
+
+/// Letters of every user mode, for RPL_MYINFO
+pub fn mode_letters() -> String {
+    let mut letters = Usermode::ALL.map(char::from);
+    letters.sort();
+
+    letters.iter().collect()
 }
 
 #[derive(Clone, Hash, PartialEq, Eq, Debug, Ord, PartialOrd)]
 pub struct Usermodes(Vec<Usermode>);
 
-impl Into<Vec<String>> for Usermodes {
-    fn into(self) -> Vec<String> {
+impl Usermodes {
+    pub fn add(&mut self, mode: Usermode) {
+        if !self.0.contains(&mode) {
+            self.0.push(mode);
+        }
+    }
+
+    pub fn remove(&mut self, mode: &Usermode) {
+        self.0.retain(|x| x != mode);
+    }
+
+    pub fn has(&self, mode: &Usermode) -> bool {
+        self.0.contains(mode)
+    }
+}
+
+impl From<Usermodes> for Vec<String> {
+    fn from(value: Usermodes) -> Self {
         let mut vector: Vec<String> = vec![];
 
-        for i in self.0 {
+        for i in value.0 {
             vector.push(Into::<String>::into(i));
         }
 
@@ -20,9 +62,9 @@ impl Into<Vec<String>> for Usermodes {
     }
 }
 
-impl Into<String> for Usermodes {
-    fn into(self) -> String {
-        format!("+{}", Into::<Vec<String>>::into(self).join(""))
+impl From<Usermodes> for String {
+    fn from(value: Usermodes) -> Self {
+        format!("+{}", Into::<Vec<String>>::into(value).join(""))
     }
 }
 
@@ -32,14 +74,14 @@ impl Default for Usermodes {
     }
 }
 
-impl Into<char> for Usermode {
-    fn into(self) -> char {
-        self as u8 as char
+impl From<Usermode> for char {
+    fn from(value: Usermode) -> Self {
+        value as u8 as char
     }
 }
 
-impl Into<String> for Usermode {
-    fn into(self) -> String {
-        Into::<char>::into(self).to_string()
+impl From<Usermode> for String {
+    fn from(value: Usermode) -> Self {
+        Into::<char>::into(value).to_string()
     }
 }
@@ -0,0 +1,248 @@
+//! WebSocket transport for browser clients, following the IRCv3 WebSocket spec. Every WebSocket
+//! message carries exactly one IRC line, without the trailing CRLF.
+
+use std::{io, net::SocketAddr};
+
+use async_trait::async_trait;
+use futures_util::{
+    SinkExt, StreamExt,
+    stream::{SplitSink, SplitStream},
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::{
+    WebSocketStream, accept_hdr_async,
+    tungstenite::{
+        Error as WsError, Message as WsMessage,
+        handshake::server::{ErrorResponse, Request, Response},
+        http::{HeaderValue, StatusCode},
+    },
+};
+
+use crate::{
+    config::WebSocketConfig,
+    connection::{Connection, IrcSink, IrcSource},
+};
+
+pub const TEXT_SUBPROTOCOL: &str = "text.ircv3.net";
+pub const BINARY_SUBPROTOCOL: &str = "binary.ircv3.net";
+
+/// How lines are framed. Clients which don't ask for a subprotocol get text frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Subprotocol {
+    Text,
+    Binary,
+}
+
+pub struct WebSocketSink<S> {
+    sink: SplitSink<WebSocketStream<S>, WsMessage>,
+    subprotocol: Subprotocol,
+}
+
+pub struct WebSocketSource<S> {
+    stream: SplitStream<WebSocketStream<S>>,
+}
+
+/// Do the WebSocket handshake on a freshly accepted stream. Connections from origins which aren't
+/// allowed by the config, or without an origin when there is an allow-list, get rejected with 403.
+// the handshake callback's error type is dictated by tungstenite
+#[allow(clippy::result_large_err)]
+pub async fn accept<S>(
+    stream: S,
+    mut address: SocketAddr,
+    secure: bool,
+    config: &WebSocketConfig,
+) -> Result<Connection, WsError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut subprotocol = Subprotocol::Text;
+    let mut forwarded_for = None;
+
+    let websocket = accept_hdr_async(stream, |request: &Request, mut response: Response| {
+        if !config.origin_allowed(header(request, "Origin")) {
+            let mut error = ErrorResponse::new(Some("origin not allowed".to_owned()));
+            *error.status_mut() = StatusCode::FORBIDDEN;
+
+            return Err(error);
+        }
+
+        let requested = header(request, "Sec-WebSocket-Protocol")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .find(|protocol| [TEXT_SUBPROTOCOL, BINARY_SUBPROTOCOL].contains(protocol));
+
+        if let Some(protocol) = requested {
+            let protocol = if protocol == BINARY_SUBPROTOCOL {
+                subprotocol = Subprotocol::Binary;
+                BINARY_SUBPROTOCOL
+            } else {
+                TEXT_SUBPROTOCOL
+            };
+
+            response
+                .headers_mut()
+                .insert("Sec-WebSocket-Protocol", HeaderValue::from_static(protocol));
+        }
+
+        // the rightmost address is the one our own proxy added
+        forwarded_for = header(request, "X-Forwarded-For")
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|value| value.trim().parse().ok());
+
+        Ok(response)
+    })
+    .await?;
+
+    if config.trusted_proxies.contains(&address.ip())
+        && let Some(ip) = forwarded_for
+    {
+        address.set_ip(ip);
+    }
+
+    let (sink, stream) = websocket.split();
+
+    Ok(Connection {
+        source: Box::new(WebSocketSource { stream }),
+        sink: Box::new(WebSocketSink { sink, subprotocol }),
+        address,
+        secure,
+        certfp: None,
+    })
+}
+
+fn header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+#[async_trait]
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> IrcSink for WebSocketSink<S> {
+    async fn send_line(&mut self, line: &str) -> io::Result<()> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let message = match self.subprotocol {
+            Subprotocol::Text => WsMessage::text(line),
+            Subprotocol::Binary => WsMessage::binary(line.as_bytes().to_vec()),
+        };
+
+        self.sink.send(message).await.map_err(io::Error::other)
+    }
+
+    async fn close(&mut self) -> io::Result<()> {
+        self.sink.close().await.map_err(io::Error::other)
+    }
+}
+
+#[async_trait]
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> IrcSource for WebSocketSource<S> {
+    async fn read_line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            return match self.stream.next().await {
+                Some(Ok(WsMessage::Text(text))) => Ok(Some(text.as_str().to_owned())),
+                Some(Ok(WsMessage::Binary(data))) => {
+                    Ok(Some(String::from_utf8_lossy(&data).to_string()))
+                }
+                // pings get answered by tungstenite itself
+                Some(Ok(WsMessage::Ping(_) | WsMessage::Pong(_) | WsMessage::Frame(_))) => {
+                    continue;
+                }
+                Some(Ok(WsMessage::Close(_))) | None => Ok(None),
+                Some(Err(error)) => Err(io::Error::other(error)),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::{
+        client_async,
+        tungstenite::{Message as WsMessage, client::IntoClientRequest},
+    };
+
+    use crate::{config::WebSocketConfig, websocket::accept};
+
+    #[tokio::test]
+    async fn test_websocket_connection() {
+        let config = WebSocketConfig {
+            port: 0,
+            tls: false,
+            allowed_origins: vec!["https://web.example.com".to_owned()],
+            trusted_proxies: vec!["127.0.0.1".parse().unwrap()],
+        };
+        let address = SocketAddr::from(([127, 0, 0, 1], 1234));
+
+        let (client, server) = tokio::io::duplex(4096);
+        let mut request = "ws://irc.example.com/".into_client_request().unwrap();
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            "binary.ircv3.net, text.ircv3.net".parse().unwrap(),
+        );
+        request
+            .headers_mut()
+            .insert("Origin", "https://web.example.com".parse().unwrap());
+        request.headers_mut().insert(
+            "X-Forwarded-For",
+            "192.0.2.1, 198.51.100.7".parse().unwrap(),
+        );
+
+        let (server, client) = tokio::join!(
+            accept(server, address, false, &config),
+            client_async(request, client)
+        );
+        let mut connection = server.unwrap();
+        let (mut client, response) = client.unwrap();
+
+        assert_eq!(
+            response.headers()["Sec-WebSocket-Protocol"],
+            "binary.ircv3.net"
+        );
+        assert_eq!(connection.address.ip().to_string(), "198.51.100.7");
+
+        connection
+            .sink
+            .send_line(":irc.example.com PING :x\r\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            client.next().await.unwrap().unwrap(),
+            WsMessage::binary(b":irc.example.com PING :x".to_vec())
+        );
+
+        client.send(WsMessage::text("NICK tester")).await.unwrap();
+        assert_eq!(
+            connection.source.read_line().await.unwrap().as_deref(),
+            Some("NICK tester")
+        );
+
+        // browsers on other sites don't get in
+        let (client, server) = tokio::io::duplex(4096);
+        let mut request = "ws://irc.example.com/".into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("Origin", "https://evil.example.com".parse().unwrap());
+
+        let (server, client) = tokio::join!(
+            accept(server, address, false, &config),
+            client_async(request, client)
+        );
+        assert!(server.is_err());
+        assert!(client.is_err());
+
+        // nor do handshakes which leave the origin out
+        let (client, server) = tokio::io::duplex(4096);
+        let request = "ws://irc.example.com/".into_client_request().unwrap();
+
+        let (server, client) = tokio::join!(
+            accept(server, address, false, &config),
+            client_async(request, client)
+        );
+        assert!(server.is_err());
+        assert!(client.is_err());
+    }
+}